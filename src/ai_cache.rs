@@ -0,0 +1,87 @@
+use anyhow::{Result, Context};
+use chrono::Local;
+use rusqlite::{Connection, OptionalExtension, params};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use crate::config::cache_dir;
+use crate::state::state_dir;
+use crate::vision::VisionAnalysis;
+
+const CACHE_DB_NAME: &str = "ai_cache.db";
+
+/// AI results keyed by the photo's content, so renamed, moved and duplicated
+/// photos (and photos whose sidecar was deleted) aren't analyzed twice.
+/// Shared between pipeline threads, hence the lock around the connection.
+pub struct AiCache {
+    conn: Mutex<Connection>,
+}
+
+impl AiCache {
+    /// Open the cache in `~/.cache/zim-photo`, or in the photo root's state
+    /// directory when there is no home directory
+    pub fn open(photo_root: &Path) -> Result<Self> {
+        let dir = match cache_dir() {
+            Some(dir) => {
+                fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create cache directory: {}", dir.display()))?;
+                dir
+            }
+            None => state_dir(photo_root)?,
+        };
+        let db_path = dir.join(CACHE_DB_NAME);
+        let conn = Connection::open(&db_path)
+            .with_context(|| format!("Failed to open AI cache: {}", db_path.display()))?;
+        
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS prompt_analyses (
+                content_hash TEXT NOT NULL,
+                model TEXT NOT NULL,
+                prompt_hash TEXT NOT NULL,
+                analysis TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (content_hash, model, prompt_hash)
+            );
+        ")?;
+        
+        Ok(AiCache { conn: Mutex::new(conn) })
+    }
+    
+    /// A cached analysis of this content by this model, asked with this prompt (see `prompt_hash`)
+    pub fn get(&self, content_hash: &str, model: &str, prompt_hash: &str) -> Result<Option<VisionAnalysis>> {
+        let json: Option<String> = self.conn.lock().unwrap().query_row(
+            "SELECT analysis FROM prompt_analyses WHERE content_hash = ?1 AND model = ?2 AND prompt_hash = ?3",
+            params![content_hash, model, prompt_hash],
+            |row| row.get(0),
+        ).optional()?;
+        
+        // An entry written by an older version that no longer parses is just a miss
+        Ok(json.and_then(|json| serde_json::from_str(&json).ok()))
+    }
+    
+    pub fn store(&self, content_hash: &str, prompt_hash: &str, analysis: &VisionAnalysis) -> Result<()> {
+        self.conn.lock().unwrap().execute("
+            INSERT OR REPLACE INTO prompt_analyses (content_hash, model, prompt_hash, analysis, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+        ", params![
+            content_hash,
+            analysis.provenance.model,
+            prompt_hash,
+            serde_json::to_string(analysis)?,
+            Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        ])?;
+        Ok(())
+    }
+}
+
+/// SHA-256 of a photo file's bytes, read in chunks so large RAW files aren't held in memory
+pub fn content_hash(path: &Path) -> Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,252 @@
+use anyhow::{Result, anyhow};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::ai_cache::{AiCache, content_hash};
+use crate::ai_queue::interrupted;
+use crate::ollama_vision::{convert_to_jpeg, ImageSource};
+use crate::retry::{CircuitBreaker, is_transient, sleep_interruptible};
+use crate::sidecar_writer::AiOptions;
+use crate::vision::{AiProvenance, PhotoContext, VisionAnalysis, VisionBackend, prompt_hash};
+
+/// Stop sending photos to an endpoint after this many in a row failed because it was unreachable
+const OUTAGE_THRESHOLD: u32 = 3;
+const OUTAGE_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+/// How often workers look for an endpoint that's back while all of them are down
+const ALL_DOWN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A photo to analyze; `index` fixes the order results are handed back in
+pub struct AiTask {
+    pub index: usize,
+    pub path: PathBuf,
+    pub context: PhotoContext,
+}
+
+pub struct AiOutcome {
+    pub index: usize,
+    pub result: Result<VisionAnalysis>,
+    /// The analysis came from the cache rather than the model
+    pub cached: bool,
+}
+
+struct PreparedImage {
+    index: usize,
+    context: PhotoContext,
+    content_hash: Option<String>,
+    jpeg: Vec<u8>,
+    source: ImageSource,
+}
+
+/// Analyze `tasks` with a bounded pool and pass each outcome to `on_outcome` in task order.
+///
+/// Images are decoded and resized on CPU threads while up to `ai.workers` requests
+/// are in flight, spread round-robin across `ai.backends`. `on_outcome` always runs
+/// on the calling thread, so callers can write sidecars without extra locking.
+/// After Ctrl-C no new images are started; requests already in flight finish.
+///
+/// With a `cache`, photos whose content was analyzed before by the same model and
+/// with the same prompt, context included, are answered from it without being
+/// decoded, and new results are added.
+pub fn run_pipeline(
+    tasks: Vec<AiTask>,
+    ai: &AiOptions,
+    cache: Option<&AiCache>,
+    mut on_outcome: impl FnMut(AiOutcome) -> Result<()>,
+) -> Result<()> {
+    if ai.backends.is_empty() {
+        return Err(anyhow!("No AI endpoints configured"));
+    }
+    
+    let workers = ai.workers.max(1);
+    let cpu_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let preparers = workers.min(cpu_threads);
+    
+    let (task_tx, task_rx) = mpsc::channel::<AiTask>();
+    for task in tasks {
+        task_tx.send(task)?;
+    }
+    drop(task_tx);
+    
+    let task_rx = Mutex::new(task_rx);
+    // Bounded so decoding only runs a little ahead of the requests
+    let (prepared_tx, prepared_rx) = mpsc::sync_channel::<PreparedImage>(workers);
+    let prepared_rx = Mutex::new(prepared_rx);
+    let (outcome_tx, outcome_rx) = mpsc::channel::<AiOutcome>();
+    let next_endpoint = AtomicUsize::new(0);
+    let breakers: Vec<_> = ai.backends.iter()
+        .map(|_| Mutex::new(CircuitBreaker::new(OUTAGE_THRESHOLD, OUTAGE_PROBE_INTERVAL)))
+        .collect();
+    let model = ai.backends[0].model();
+    
+    thread::scope(|scope| {
+        for _ in 0..preparers {
+            let prepared_tx = prepared_tx.clone();
+            let outcome_tx = outcome_tx.clone();
+            let task_rx = &task_rx;
+            scope.spawn(move || {
+                loop {
+                    if interrupted() {
+                        break;
+                    }
+                    let Ok(task) = task_rx.lock().unwrap().recv() else {
+                        break;
+                    };
+                    
+                    let hash = cache.and_then(|_| content_hash(&task.path).ok());
+                    if let (Some(cache), Some(hash)) = (cache, &hash)
+                        && let Ok(Some(analysis)) = cache.get(hash, model, &prompt_hash(&task.context))
+                        && !ai.is_stale(&analysis.provenance)
+                    {
+                        let _ = outcome_tx.send(AiOutcome { index: task.index, result: Ok(analysis), cached: true });
+                        continue;
+                    }
+                    
+                    match convert_to_jpeg(&task.path) {
+                        Ok((jpeg, source)) => {
+                            let prepared = PreparedImage {
+                                index: task.index,
+                                context: task.context,
+                                content_hash: hash,
+                                jpeg,
+                                source,
+                            };
+                            if prepared_tx.send(prepared).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            let _ = outcome_tx.send(AiOutcome { index: task.index, result: Err(e), cached: false });
+                        }
+                    }
+                }
+            });
+        }
+        drop(prepared_tx);
+        
+        for _ in 0..workers {
+            let outcome_tx = outcome_tx.clone();
+            let prepared_rx = &prepared_rx;
+            let next_endpoint = &next_endpoint;
+            let breakers = &breakers;
+            scope.spawn(move || {
+                loop {
+                    let Ok(image) = prepared_rx.lock().unwrap().recv() else {
+                        break;
+                    };
+                    if interrupted() {
+                        // Leave it pending in the queue for the next run
+                        continue;
+                    }
+                    
+                    let started = Instant::now();
+                    let result = analyze_with_breakers(&image.jpeg, &image.context, ai, breakers, next_endpoint)
+                        .map(|(mut analysis, backend)| {
+                            analysis.provenance = AiProvenance::new(backend, image.source, started.elapsed());
+                            analysis
+                        });
+                    if let (Some(cache), Some(hash), Ok(analysis)) = (cache, &image.content_hash, &result)
+                        && let Err(e) = cache.store(hash, &prompt_hash(&image.context), analysis)
+                    {
+                        println!("  ⚠️  Failed to cache AI result: {}", e);
+                    }
+                    let _ = outcome_tx.send(AiOutcome { index: image.index, result, cached: false });
+                }
+            });
+        }
+        drop(outcome_tx);
+        
+        // Hand outcomes back in task order, holding early finishers until their turn
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+        let mut callback_result = Ok(());
+        for outcome in outcome_rx {
+            pending.insert(outcome.index, outcome);
+            while let Some(outcome) = pending.remove(&next_index) {
+                next_index += 1;
+                if callback_result.is_ok() {
+                    callback_result = on_outcome(outcome);
+                }
+            }
+        }
+        
+        // Anything left follows a gap left by an interrupted run
+        for (_, outcome) in pending {
+            if callback_result.is_ok() {
+                callback_result = on_outcome(outcome);
+            }
+        }
+        
+        callback_result
+    })
+}
+
+/// Retry transient errors. An endpoint that stays down is skipped until it responds
+/// again, and the image goes to another one; while every endpoint is down, the
+/// workers wait for one to come back. Returns the backend that answered.
+fn analyze_with_breakers<'a>(
+    jpeg: &[u8],
+    context: &PhotoContext,
+    ai: &'a AiOptions,
+    breakers: &[Mutex<CircuitBreaker>],
+    next_endpoint: &AtomicUsize,
+) -> Result<(VisionAnalysis, &'a dyn VisionBackend)> {
+    loop {
+        let index = choose_endpoint(ai, breakers, next_endpoint)?;
+        let backend = ai.backends[index].as_ref();
+        
+        let result = ai.retry.run(|| backend.analyze(jpeg, context));
+        match &result {
+            Ok(_) => breakers[index].lock().unwrap().record_success(),
+            Err(e) if is_transient(e) => {
+                let mut breaker = breakers[index].lock().unwrap();
+                if breaker.record_failure() {
+                    if breaker.consecutive_failures() == OUTAGE_THRESHOLD {
+                        println!("  🔌 {} failures in a row at {}, skipping it until it responds again (checking every {}s)",
+                            OUTAGE_THRESHOLD, backend.endpoint(), breaker.probe_interval().as_secs());
+                    }
+                    continue;
+                }
+            }
+            Err(_) => {}
+        }
+        return result.map(|analysis| (analysis, backend));
+    }
+}
+
+/// The next endpoint round-robin that isn't down, checking on those that are
+/// when they're due. Waits while all of them are down.
+fn choose_endpoint(ai: &AiOptions, breakers: &[Mutex<CircuitBreaker>], next_endpoint: &AtomicUsize) -> Result<usize> {
+    let mut paused = false;
+    loop {
+        let start = next_endpoint.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..breakers.len() {
+            let index = (start + offset) % breakers.len();
+            let mut breaker = breakers[index].lock().unwrap();
+            if !breaker.is_open() {
+                return Ok(index);
+            }
+            if breaker.probe_due() {
+                // Not holding the lock while waiting for the answer
+                drop(breaker);
+                let backend = ai.backends[index].as_ref();
+                if backend.reachable() {
+                    breakers[index].lock().unwrap().record_success();
+                    println!("  🔌 {} is back, resuming", backend.endpoint());
+                    return Ok(index);
+                }
+            }
+        }
+        
+        if interrupted() {
+            return Err(anyhow!("Stopped while every AI endpoint was unreachable"));
+        }
+        if !paused && breakers.len() > 1 {
+            println!("  🔌 Every AI endpoint is down, pausing until one responds again");
+        }
+        paused = true;
+        sleep_interruptible(ALL_DOWN_POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::{Result, Context};
+use chrono::Local;
+use rusqlite::{Connection, params};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::state::{state_dir, relative_key};
+
+const QUEUE_DB_NAME: &str = "ai_queue.db";
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// A photo waiting for (or retrying) AI analysis
+#[derive(Debug, Clone)]
+pub struct AiJob {
+    pub path: PathBuf,
+    pub attempts: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct QueueCounts {
+    pub pending: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
+/// Persistent record of AI work for a photo root, stored in `.zim-photo/ai_queue.db`
+pub struct AiQueue {
+    conn: Connection,
+    root: PathBuf,
+}
+
+impl AiQueue {
+    pub fn open<P: AsRef<Path>>(photo_root: P) -> Result<Self> {
+        let root = photo_root.as_ref().to_path_buf();
+        let db_path = state_dir(&root)?.join(QUEUE_DB_NAME);
+        let conn = Connection::open(&db_path)
+            .with_context(|| format!("Failed to open AI queue: {}", db_path.display()))?;
+        
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS jobs (
+                path TEXT PRIMARY KEY,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                updated_at TEXT NOT NULL
+            );
+        ")?;
+        
+        Ok(AiQueue { conn, root })
+    }
+    
+    /// The photo directory this queue belongs to
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    
+    /// Add a photo to the queue, or start it over as pending with no attempts if it
+    /// is already there, done or not, since it is queued again to be re-analyzed
+    pub fn enqueue(&self, path: &Path) -> Result<()> {
+        self.conn.execute("
+            INSERT INTO jobs (path, status, attempts, updated_at)
+            VALUES (?1, 'pending', 0, ?2)
+            ON CONFLICT(path) DO UPDATE SET status = 'pending', attempts = 0, last_error = NULL, updated_at = ?2
+        ", params![relative_key(&self.root, path), now()])?;
+        Ok(())
+    }
+    
+    pub fn mark_done(&self, path: &Path) -> Result<()> {
+        self.conn.execute("
+            UPDATE jobs
+            SET status = 'done', attempts = attempts + 1, last_error = NULL, updated_at = ?2
+            WHERE path = ?1
+        ", params![relative_key(&self.root, path), now()])?;
+        Ok(())
+    }
+    
+    pub fn mark_failed(&self, path: &Path, error: &str) -> Result<()> {
+        self.conn.execute("
+            UPDATE jobs
+            SET status = 'failed', attempts = attempts + 1, last_error = ?2, updated_at = ?3
+            WHERE path = ?1
+        ", params![relative_key(&self.root, path), error, now()])?;
+        Ok(())
+    }
+    
+    /// Jobs still to run: everything pending, plus failures with attempts to spare
+    pub fn remaining(&self, max_attempts: i64) -> Result<Vec<AiJob>> {
+        let mut stmt = self.conn.prepare("
+            SELECT path, attempts
+            FROM jobs
+            WHERE status = 'pending' OR (status = 'failed' AND attempts < ?1)
+            ORDER BY path
+        ")?;
+        
+        let jobs = stmt.query_map(params![max_attempts], |row| {
+            Ok(AiJob {
+                path: self.root.join(row.get::<_, String>(0)?),
+                attempts: row.get(1)?,
+            })
+        })?.filter_map(Result::ok).collect();
+        
+        Ok(jobs)
+    }
+    
+    pub fn counts(&self) -> Result<QueueCounts> {
+        let mut counts = QueueCounts::default();
+        let mut stmt = self.conn.prepare("SELECT status, COUNT(*) FROM jobs GROUP BY status")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        
+        for row in rows {
+            let (status, count) = row?;
+            match status.as_str() {
+                "pending" => counts.pending = count as usize,
+                "done" => counts.done = count as usize,
+                "failed" => counts.failed = count as usize,
+                _ => {}
+            }
+        }
+        
+        Ok(counts)
+    }
+    
+    /// Most recent failures with their error messages
+    pub fn recent_failures(&self, limit: usize) -> Result<Vec<(String, i64, String)>> {
+        let mut stmt = self.conn.prepare("
+            SELECT path, attempts, COALESCE(last_error, '')
+            FROM jobs
+            WHERE status = 'failed'
+            ORDER BY updated_at DESC
+            LIMIT ?1
+        ")?;
+        
+        let failures = stmt.query_map(params![limit as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?.filter_map(Result::ok).collect();
+        
+        Ok(failures)
+    }
+}
+
+fn now() -> String {
+    Local::now().format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+/// Install a Ctrl-C handler: the first press lets the current image finish,
+/// the second exits immediately
+pub fn install_interrupt_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            println!("\n🛑 Interrupted again, exiting now");
+            std::process::exit(130);
+        }
+        println!("\n⏸️  Finishing the current image, then stopping (Ctrl-C again to quit now)");
+    }).context("Failed to install Ctrl-C handler")?;
+    Ok(())
+}
+
+/// Whether Ctrl-C has been pressed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Print queue counts and recent failures for a photo root
+pub fn print_status<P: AsRef<Path>>(photo_root: P) -> Result<()> {
+    let queue = AiQueue::open(photo_root)?;
+    let counts = queue.counts()?;
+    
+    println!("\n📋 AI Queue Status:");
+    println!("  ⏳ Pending: {}", counts.pending);
+    println!("  ✅ Done: {}", counts.done);
+    println!("  ❌ Failed: {}", counts.failed);
+    
+    let failures = queue.recent_failures(5)?;
+    if !failures.is_empty() {
+        println!("\n  Recent failures:");
+        for (path, attempts, error) in failures {
+            println!("     {} (attempt {}): {}", path, attempts, error);
+        }
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,438 @@
+use anyhow::{Result, Context, anyhow};
+use image::imageops::FilterType;
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+use tokenizers::Tokenizer;
+use tract_onnx::prelude::{
+    tvec, DatumType, Framework, InferenceFact, InferenceModelExt, Tensor, TypedRunnableModel, TypedModel,
+};
+use tract_onnx::tract_hir::infer::Factoid;
+use tract_onnx::tract_hir::internal::DimLike;
+use crate::embeddings::Embedder;
+
+/// Tokens per text for CLIP text encoders, when the model doesn't say
+const DEFAULT_CONTEXT_LENGTH: usize = 77;
+
+/// Image size and normalization of the original CLIP models
+const DEFAULT_IMAGE_SIZE: u32 = 224;
+const CLIP_MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+const CLIP_STD: [f32; 3] = [0.268_629_54, 0.261_302_6, 0.275_777_1];
+
+/// `visual/preprocess_cfg.json` as shipped with OpenCLIP exports
+#[derive(Debug, Deserialize)]
+struct PreprocessConfig {
+    #[serde(default)]
+    size: Option<ImageSize>,
+    #[serde(default)]
+    mean: Option<[f32; 3]>,
+    #[serde(default)]
+    std: Option<[f32; 3]>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ImageSize {
+    Square(u32),
+    Sides([u32; 2]),
+}
+
+/// A CLIP model exported to ONNX, run on the CPU. The model directory has the
+/// layout of the OpenCLIP exports used by Immich:
+/// `visual/model.onnx`, `textual/model.onnx` and `textual/tokenizer.json`.
+pub struct ClipOnnxEmbedder {
+    model: String,
+    visual: TypedRunnableModel<TypedModel>,
+    textual: TypedRunnableModel<TypedModel>,
+    tokenizer: Tokenizer,
+    image_size: u32,
+    mean: [f32; 3],
+    std: [f32; 3],
+    context_length: usize,
+    /// Integer type of the text model's token ids
+    token_type: DatumType,
+}
+
+impl ClipOnnxEmbedder {
+    /// Load both halves of the model; `model` is the model directory
+    pub fn open(model: &str) -> Result<Self> {
+        let dir = PathBuf::from(model);
+        let visual_path = dir.join("visual").join("model.onnx");
+        let textual_path = dir.join("textual").join("model.onnx");
+        let tokenizer_path = dir.join("textual").join("tokenizer.json");
+        for path in [&visual_path, &textual_path, &tokenizer_path] {
+            if !path.is_file() {
+                return Err(anyhow!("{} not found; --embed-model must be a CLIP model directory with visual/ and textual/ ONNX models", path.display()));
+            }
+        }
+        
+        let preprocess = match fs::read_to_string(dir.join("visual").join("preprocess_cfg.json")) {
+            Ok(json) => Some(serde_json::from_str::<PreprocessConfig>(&json).context("Invalid visual/preprocess_cfg.json")?),
+            Err(_) => None,
+        };
+        let onnx = onnx_framework();
+        
+        let mut visual = onnx.model_for_path(&visual_path)
+            .map_err(|e| anyhow!("Failed to load {}: {}", visual_path.display(), e))?;
+        let image_size = match preprocess.as_ref().and_then(|p| p.size.as_ref()) {
+            Some(ImageSize::Square(size)) => *size,
+            Some(ImageSize::Sides([height, _])) => *height,
+            None => declared_dim(visual.input_fact(0)?, 2).map_or(DEFAULT_IMAGE_SIZE, |d| d as u32),
+        };
+        let size = image_size as usize;
+        visual.set_input_fact(0, InferenceFact::dt_shape(DatumType::F32, tvec!(1, 3, size, size)))?;
+        let visual = visual.into_optimized()
+            .and_then(|model| model.into_runnable())
+            .map_err(|e| anyhow!("Failed to prepare {}: {}", visual_path.display(), e))?;
+        
+        let mut textual = onnx.model_for_path(&textual_path)
+            .map_err(|e| anyhow!("Failed to load {}: {}", textual_path.display(), e))?;
+        let input = textual.input_fact(0)?;
+        let token_type = input.datum_type.concretize().unwrap_or(DatumType::I64);
+        let context_length = declared_dim(input, 1).unwrap_or(DEFAULT_CONTEXT_LENGTH);
+        textual.set_input_fact(0, InferenceFact::dt_shape(token_type, tvec!(1, context_length)))?;
+        let textual = textual.into_optimized()
+            .and_then(|model| model.into_runnable())
+            .map_err(|e| anyhow!("Failed to prepare {}: {}", textual_path.display(), e))?;
+        
+        let mut tokenizer = Tokenizer::from_file(&tokenizer_path)
+            .map_err(|e| anyhow!("Failed to load {}: {}", tokenizer_path.display(), e))?;
+        // Truncated and padded here, to the length the model takes
+        tokenizer.with_truncation(None).map_err(|e| anyhow!("{}", e))?;
+        tokenizer.with_padding(None);
+        
+        Ok(ClipOnnxEmbedder {
+            model: model.to_string(),
+            visual,
+            textual,
+            tokenizer,
+            image_size,
+            mean: preprocess.as_ref().and_then(|p| p.mean).unwrap_or(CLIP_MEAN),
+            std: preprocess.as_ref().and_then(|p| p.std).unwrap_or(CLIP_STD),
+            context_length,
+            token_type,
+        })
+    }
+    
+    /// Resize the shorter side to the model's size, crop the middle and normalize
+    /// the channels, as CLIP was trained
+    fn pixels(&self, jpeg_data: &[u8]) -> Result<Tensor> {
+        let size = self.image_size;
+        let image = image::load_from_memory(jpeg_data)
+            .context("Failed to decode image")?
+            .resize_to_fill(size, size, FilterType::CatmullRom)
+            .to_rgb8();
+        let size = size as usize;
+        let mut values = vec![0f32; 3 * size * size];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            for channel in 0..3 {
+                let value = pixel[channel] as f32 / 255.0;
+                values[channel * size * size + y as usize * size + x as usize] = (value - self.mean[channel]) / self.std[channel];
+            }
+        }
+        Tensor::from_shape(&[1, 3, size, size], &values)
+    }
+    
+    /// Token ids, cut to the context length with the end token kept, and padded with zeros
+    fn tokens(&self, text: &str) -> Result<Tensor> {
+        let encoding = self.tokenizer.encode(text, true)
+            .map_err(|e| anyhow!("Failed to tokenize '{}': {}", text, e))?;
+        let mut ids: Vec<i64> = encoding.get_ids().iter().map(|&id| id as i64).collect();
+        if ids.len() > self.context_length {
+            let end = ids[ids.len() - 1];
+            ids.truncate(self.context_length);
+            ids[self.context_length - 1] = end;
+        }
+        ids.resize(self.context_length, 0);
+        let tokens = Tensor::from_shape(&[1, self.context_length], &ids)?;
+        Ok(tokens.cast_to_dt(self.token_type)?.into_owned())
+    }
+}
+
+impl Embedder for ClipOnnxEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+    
+    fn endpoint(&self) -> &str {
+        "onnx"
+    }
+    
+    fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        texts.iter()
+            .map(|text| {
+                let outputs = self.textual.run(tvec!(self.tokens(text)?.into()))
+                    .map_err(|e| anyhow!("Text model failed: {}", e))?;
+                first_vector(&outputs[0])
+            })
+            .collect()
+    }
+    
+    fn embeds_images(&self) -> bool {
+        true
+    }
+    
+    fn embed_image(&self, jpeg_data: &[u8]) -> Result<Vec<f32>> {
+        let outputs = self.visual.run(tvec!(self.pixels(jpeg_data)?.into()))
+            .map_err(|e| anyhow!("Image model failed: {}", e))?;
+        first_vector(&outputs[0])
+    }
+}
+
+/// The values of a `[1, dimensions]` output
+fn first_vector(output: &Tensor) -> Result<Vec<f32>> {
+    let output = output.cast_to::<f32>().map_err(|e| anyhow!("Unexpected model output: {}", e))?;
+    Ok(output.as_slice::<f32>()?.to_vec())
+}
+
+/// A fixed size the model declares for an input axis
+fn declared_dim(fact: &InferenceFact, axis: usize) -> Option<usize> {
+    fact.shape.dims().nth(axis)?.concretize()?.to_usize().ok()
+}
+
+/// The ONNX loader, with the ops CLIP exports need that tract doesn't have yet
+fn onnx_framework() -> tract_onnx::Onnx {
+    let mut onnx = tract_onnx::onnx();
+    onnx.op_register.insert("LayerNormalization", layer_norm::layer_normalization);
+    onnx
+}
+
+/// ONNX `LayerNormalization` (opset 17), which newer CLIP exports use in every
+/// transformer block: normalize over the axes from `axis` on, then scale and shift
+mod layer_norm {
+    use tract_onnx::model::ParsingContext;
+    use tract_onnx::pb::NodeProto;
+    use tract_onnx::tract_hir::internal::*;
+    use tract_onnx::tract_hir::ops::nn::{Reduce, Reducer};
+    use tract_onnx::tract_hir::ops::math;
+    
+    pub fn layer_normalization(_ctx: &ParsingContext, node: &NodeProto) -> TractResult<(Box<dyn InferenceOp>, Vec<String>)> {
+        let axis = node.get_attr_opt("axis")?.unwrap_or(-1);
+        let epsilon = node.get_attr_opt("epsilon")?.unwrap_or(1e-5);
+        let has_bias = node.input.get(2).is_some_and(|name| !name.is_empty());
+        Ok((expand(LayerNorm { axis, epsilon, has_bias }), vec![]))
+    }
+    
+    #[derive(Debug, Clone)]
+    struct LayerNorm {
+        axis: i64,
+        epsilon: f32,
+        has_bias: bool,
+    }
+    
+    impl Expansion for LayerNorm {
+        fn name(&self) -> Cow<'_, str> {
+            "LayerNorm".into()
+        }
+        
+        fn rules<'r, 'p: 'r, 's: 'r>(
+            &'s self,
+            s: &mut Solver<'r>,
+            inputs: &'p [TensorProxy],
+            outputs: &'p [TensorProxy],
+        ) -> InferenceResult {
+            check_input_arity(inputs, 2 + self.has_bias as usize)?;
+            check_output_arity(outputs, 1)?;
+            s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+            s.equals(&inputs[0].datum_type, &inputs[1].datum_type)?;
+            s.equals(&inputs[0].shape, &outputs[0].shape)?;
+            Ok(())
+        }
+        
+        fn wire(&self, name: &str, model: &mut TypedModel, inputs: &[OutletId]) -> TractResult<TVec<OutletId>> {
+            let input_fact = model.outlet_fact(inputs[0])?.clone();
+            let rank = input_fact.rank();
+            let first = if self.axis < 0 { rank as i64 + self.axis } else { self.axis };
+            let axes: Vec<i64> = (first..rank as i64).collect();
+            
+            let mean = Reduce::new(Some(axes.clone()), true, Reducer::Mean)
+                .wire(&format!("{name}.mean"), model, &inputs[0..1])?;
+            let diff = model.wire_node(format!("{name}.diff"), math::sub(), &[inputs[0], mean[0]])?;
+            let square = model.wire_node(format!("{name}.sqr"), math::square(), &diff)?;
+            let variance = Reduce::new(Some(axes), true, Reducer::Mean)
+                .wire(&format!("{name}.variance"), model, &square)?;
+            let epsilon = model.add_const(
+                format!("{name}.epsilon.cst"),
+                tensor0(self.epsilon)
+                    .cast_to_dt(input_fact.datum_type)?
+                    .into_owned()
+                    .broadcast_into_rank(rank)?
+                    .into_arc_tensor(),
+            )?;
+            let variance = model.wire_node(format!("{name}.epsilon"), math::add(), &[variance[0], epsilon])?;
+            let rsqrt = model.wire_node(format!("{name}.rsqrt"), math::rsqrt(), &variance)?;
+            let normalized = model.wire_node(format!("{name}.normalized"), math::mul(), &[diff[0], rsqrt[0]])?;
+            
+            // Scale and bias cover the normalized axes only
+            let scale = leading_axes(model, &format!("{name}.scale"), inputs[1], first as usize)?;
+            let scaled = model.wire_node(format!("{name}.scaled"), math::mul(), &[normalized[0], scale])?;
+            if !self.has_bias {
+                return Ok(scaled);
+            }
+            let bias = leading_axes(model, &format!("{name}.bias"), inputs[2], first as usize)?;
+            model.wire_node(name, math::add(), &[scaled[0], bias])
+        }
+    }
+    
+    /// Prepend `count` axes of size 1, so a tensor broadcasts against the input
+    fn leading_axes(model: &mut TypedModel, name: &str, mut wire: OutletId, count: usize) -> TractResult<OutletId> {
+        for i in 0..count {
+            wire = model.wire_node(format!("{name}.add-axis-{i}"), AxisOp::Add(0), &[wire])?[0];
+        }
+        Ok(wire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use std::path::Path;
+    use tract_onnx::pb::{
+        attribute_proto, tensor_proto, tensor_shape_proto, type_proto, AttributeProto, GraphProto, ModelProto,
+        NodeProto, OperatorSetIdProto, TensorProto, TensorShapeProto, TypeProto, ValueInfoProto,
+    };
+    
+    fn value_info(name: &str, elem_type: tensor_proto::DataType, dims: &[i64]) -> ValueInfoProto {
+        let dim = dims.iter()
+            .map(|d| tensor_shape_proto::Dimension { value: Some(tensor_shape_proto::dimension::Value::DimValue(*d)), ..Default::default() })
+            .collect();
+        ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(TypeProto {
+                value: Some(type_proto::Value::TensorType(type_proto::Tensor {
+                    elem_type: elem_type as i32,
+                    shape: Some(TensorShapeProto { dim }),
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+    
+    fn node(op_type: &str, inputs: &[&str], output: &str, attribute: Vec<AttributeProto>) -> NodeProto {
+        NodeProto {
+            op_type: op_type.to_string(),
+            input: inputs.iter().map(|i| i.to_string()).collect(),
+            output: vec![output.to_string()],
+            attribute,
+            ..Default::default()
+        }
+    }
+    
+    fn ints(name: &str, values: &[i64]) -> AttributeProto {
+        AttributeProto { name: name.to_string(), r#type: attribute_proto::AttributeType::Ints as i32, ints: values.to_vec(), ..Default::default() }
+    }
+    
+    fn int(name: &str, value: i64) -> AttributeProto {
+        AttributeProto { name: name.to_string(), r#type: attribute_proto::AttributeType::Int as i32, i: value, ..Default::default() }
+    }
+    
+    fn write_model(path: &Path, graph: GraphProto) {
+        let model = ModelProto {
+            ir_version: 8,
+            opset_import: vec![OperatorSetIdProto { domain: String::new(), version: 17 }],
+            graph: Some(graph),
+            ..Default::default()
+        };
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, model.encode_to_vec()).unwrap();
+    }
+    
+    /// A stand-in CLIP: the image "embedding" is the layer-normalized mean of each
+    /// channel, and the text "embedding" is the token ids
+    fn tiny_clip(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zim-photo-clip-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        let float = tensor_proto::DataType::Float;
+        let ones = TensorProto { name: "scale".into(), dims: vec![3], data_type: float as i32, float_data: vec![1.0; 3], ..Default::default() };
+        let zeros = TensorProto { name: "bias".into(), dims: vec![3], data_type: float as i32, float_data: vec![0.0; 3], ..Default::default() };
+        write_model(&dir.join("visual/model.onnx"), GraphProto {
+            node: vec![
+                node("ReduceMean", &["pixel_values"], "means", vec![ints("axes", &[2, 3]), int("keepdims", 0)]),
+                node("LayerNormalization", &["means", "scale", "bias"], "embedding", vec![int("axis", -1)]),
+            ],
+            initializer: vec![ones, zeros],
+            input: vec![value_info("pixel_values", float, &[1, 3, 4, 4])],
+            output: vec![value_info("embedding", float, &[1, 3])],
+            ..Default::default()
+        });
+        write_model(&dir.join("textual/model.onnx"), GraphProto {
+            node: vec![node("Cast", &["input_ids"], "embedding", vec![int("to", float as i64)])],
+            input: vec![value_info("input_ids", tensor_proto::DataType::Int32, &[1, 8])],
+            output: vec![value_info("embedding", float, &[1, 8])],
+            ..Default::default()
+        });
+        let tokenizer = serde_json::json!({
+            "version": "1.0",
+            "added_tokens": [],
+            "normalizer": { "type": "Lowercase" },
+            "pre_tokenizer": { "type": "Whitespace" },
+            "post_processor": {
+                "type": "TemplateProcessing",
+                "single": [
+                    { "SpecialToken": { "id": "<start>", "type_id": 0 } },
+                    { "Sequence": { "id": "A", "type_id": 0 } },
+                    { "SpecialToken": { "id": "<end>", "type_id": 0 } },
+                ],
+                "pair": [],
+                "special_tokens": {
+                    "<start>": { "id": "<start>", "ids": [10], "tokens": ["<start>"] },
+                    "<end>": { "id": "<end>", "ids": [11], "tokens": ["<end>"] },
+                },
+            },
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "<unk>": 1, "kids": 2, "on": 3, "a": 4, "beach": 5, "<start>": 10, "<end>": 11 },
+                "unk_token": "<unk>",
+            },
+        });
+        fs::write(dir.join("textual/tokenizer.json"), tokenizer.to_string()).unwrap();
+        dir
+    }
+    
+    fn solid_jpeg(color: [u8; 3]) -> Vec<u8> {
+        let image = image::RgbImage::from_pixel(16, 8, image::Rgb(color));
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut jpeg, image::ImageFormat::Jpeg).unwrap();
+        jpeg.into_inner()
+    }
+    
+    #[test]
+    fn embeds_images_through_the_visual_model() {
+        let dir = tiny_clip("visual");
+        let clip = ClipOnnxEmbedder::open(dir.to_str().unwrap()).unwrap();
+        assert_eq!(clip.image_size, 4);
+        
+        let vector = clip.embed_image(&solid_jpeg([255, 0, 0])).unwrap();
+        // The normalized channel values, layer-normalized
+        let channels: Vec<f32> = (0..3).map(|c| ([1.0, 0.0, 0.0][c] - CLIP_MEAN[c]) / CLIP_STD[c]).collect();
+        let mean = channels.iter().sum::<f32>() / 3.0;
+        let deviation = (channels.iter().map(|c| (c - mean).powi(2)).sum::<f32>() / 3.0 + 1e-5).sqrt();
+        for (value, channel) in vector.iter().zip(&channels) {
+            assert!((value - (channel - mean) / deviation).abs() < 0.05, "{:?} vs {:?}", vector, channels);
+        }
+        assert!(vector[0] > 0.0 && vector[1] < 0.0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn tokenizes_texts_to_the_context_length() {
+        let dir = tiny_clip("textual");
+        let clip = ClipOnnxEmbedder::open(dir.to_str().unwrap()).unwrap();
+        let vectors = clip.embed_texts(&["Kids on a beach".to_string(), "kids on a beach on a beach".to_string()]).unwrap();
+        assert_eq!(vectors[0], vec![10.0, 2.0, 3.0, 4.0, 5.0, 11.0, 0.0, 0.0]);
+        // Too long: cut, but still ending with the end token
+        assert_eq!(vectors[1], vec![10.0, 2.0, 3.0, 4.0, 5.0, 3.0, 4.0, 11.0]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn a_directory_without_both_models_is_refused() {
+        let dir = tiny_clip("missing");
+        fs::remove_file(dir.join("textual/tokenizer.json")).unwrap();
+        let error = ClipOnnxEmbedder::open(dir.to_str().unwrap()).err().unwrap();
+        assert!(error.to_string().contains("tokenizer.json"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
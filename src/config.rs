@@ -0,0 +1,87 @@
+use anyhow::{Result, Context, anyhow};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::embeddings::{EmbeddingBackend, EmbeddingSource};
+use crate::file_types::FileTypeConfig;
+use crate::vision::{BackendKind, ContextField};
+
+/// Settings read from `~/.config/zim-photo/config.yaml` (or `--config`).
+/// Command-line flags take precedence over anything set here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub vision: VisionConfig,
+    pub embedding: EmbeddingConfig,
+    /// Tag vocabulary file (default: `vocabulary.yaml` next to this file)
+    pub vocabulary: Option<PathBuf>,
+    /// Extra file extensions to process, or changes to the built-in ones
+    pub file_types: HashMap<String, FileTypeConfig>,
+    /// Always give RAW+JPEG pairs and edits one sidecar, as with `--group`
+    pub group_companions: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VisionConfig {
+    pub backend: BackendKind,
+    pub model: Option<String>,
+    pub endpoints: Vec<String>,
+    pub api_key: Option<String>,
+    pub workers: Option<usize>,
+    pub context: Option<Vec<ContextField>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmbeddingConfig {
+    pub backend: EmbeddingBackend,
+    pub model: Option<String>,
+    pub endpoint: Option<String>,
+    pub source: EmbeddingSource,
+    pub api_key: Option<String>,
+}
+
+impl Config {
+    /// Load the given config file, or the default one if it exists
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => {
+                if !path.exists() {
+                    return Err(anyhow!("Config file not found: {}", path.display()));
+                }
+                path.to_path_buf()
+            }
+            None => match default_config_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config: {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid config file: {}", path.display()))
+    }
+}
+
+/// `~/.config/zim-photo`, honoring `$XDG_CONFIG_HOME`
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("zim-photo"))
+}
+
+/// `~/.cache/zim-photo`, honoring `$XDG_CACHE_HOME`
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_home.join("zim-photo"))
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.yaml"))
+}
@@ -0,0 +1,401 @@
+use anyhow::{Result, Context};
+use image::{DynamicImage, ImageReader, imageops::FilterType};
+use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
+use crate::ai_queue::{install_interrupt_handler, interrupted};
+use crate::file_types::Reader;
+use crate::groups::base_name;
+use crate::manifest::Manifest;
+use crate::photo_walker::{PhotoFile, PhotoWalker};
+use crate::raw_preview::extract_embedded_jpeg;
+use crate::sidecar_reader::{has_notes, read_sidecar_metadata};
+
+/// Perceptual hashes this many bits apart or fewer are near-duplicates by default
+pub const DEFAULT_MAX_DISTANCE: u32 = 6;
+
+/// A photo with whatever was needed to compare it
+struct HashedPhoto {
+    photo: PhotoFile,
+    size: u64,
+    hash: Option<String>,
+    /// 64-bit difference hash of the preview
+    dhash: Option<u64>,
+}
+
+/// How much a copy's sidecar holds; personal notes count for more than any
+/// number of generated fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Richness {
+    notes: bool,
+    fields: usize,
+    has_sidecar: bool,
+}
+
+impl Richness {
+    fn of(photo: &PhotoFile) -> Richness {
+        if !photo.sidecar_path.exists() {
+            return Richness { notes: false, fields: 0, has_sidecar: false };
+        }
+        let fields = read_sidecar_metadata(&photo.sidecar_path)
+            .map(|frontmatter| frontmatter.values().filter(|v| !is_empty(v)).count())
+            .unwrap_or(0);
+        let notes = has_notes(&photo.sidecar_path).unwrap_or(false);
+        Richness { notes, fields, has_sidecar: true }
+    }
+    
+    fn describe(&self) -> String {
+        match (self.has_sidecar, self.notes) {
+            (false, _) => "no sidecar".to_string(),
+            (true, true) => format!("{} fields, personal notes", self.fields),
+            (true, false) => format!("{} fields", self.fields),
+        }
+    }
+}
+
+/// Find photos that are the same file under another name or in another folder, and
+/// (unless `exact_only`) photos that look the same: resized, re-encoded or lightly
+/// edited copies. The files of a RAW+JPEG pair or its edits are not reported.
+pub fn find_duplicates(photo_dir: &str, exact_only: bool, max_distance: u32, show_progress: bool) -> Result<()> {
+    println!("🔍 Looking for duplicate photos in: {}\n", photo_dir);
+    
+    let start_time = Instant::now();
+    install_interrupt_handler()?;
+    let manifest = Manifest::open(photo_dir)?;
+    let photos = PhotoWalker::new(photo_dir, false).find_photos()?;
+    let total = photos.len();
+    
+    // Only photos that share their size with another can be exact copies
+    let mut sizes: HashMap<u64, usize> = HashMap::new();
+    let photos: Vec<(PhotoFile, u64)> = photos.into_iter()
+        .map(|photo| {
+            let size = fs::metadata(&photo.path).map_or(0, |m| m.len());
+            *sizes.entry(size).or_insert(0) += 1;
+            (photo, size)
+        })
+        .collect();
+    
+    let mut hashed = Vec::new();
+    let mut not_compared = 0;
+    let mut errors = Vec::new();
+    for (i, (photo, size)) in photos.into_iter().enumerate() {
+        if interrupted() {
+            break;
+        }
+        if show_progress && ((i + 1) % 50 == 0 || i + 1 == total) {
+            println!("  Hashing: {}/{} ({:.1}%)", i + 1, total, (i + 1) as f64 / total as f64 * 100.0);
+        }
+        
+        let needs_hash = !exact_only || sizes.get(&size).is_some_and(|&n| n > 1);
+        let hash = if needs_hash {
+            match manifest.content_hash(&photo.path) {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    errors.push(format!("{}: {}", photo.filename, e));
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        let dhash = match &hash {
+            Some(hash) if !exact_only => match perceptual_hash(&photo, hash, &manifest) {
+                Ok(dhash) => dhash,
+                Err(e) => {
+                    errors.push(format!("{}: {:#}", photo.filename, e));
+                    None
+                }
+            },
+            _ => None,
+        };
+        if !exact_only && dhash.is_none() {
+            not_compared += 1;
+        }
+        hashed.push(HashedPhoto { photo, size, hash, dhash });
+    }
+    
+    // Exact copies: the same content hash
+    let mut by_hash: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, photo) in hashed.iter().enumerate() {
+        if let Some(hash) = &photo.hash {
+            by_hash.entry(hash.as_str()).or_default().push(i);
+        }
+    }
+    let mut exact: Vec<Vec<usize>> = by_hash.into_values().filter(|copies| copies.len() > 1).collect();
+    exact.sort();
+    
+    let mut wasted = 0;
+    // The copy to keep of each set of exact duplicates, and how many others there are
+    let mut kept: HashMap<usize, usize> = HashMap::new();
+    if !exact.is_empty() {
+        println!("\n🟰 Exact duplicates: {} groups", exact.len());
+        for copies in &exact {
+            let size = hashed[copies[0]].size;
+            wasted += size * (copies.len() as u64 - 1);
+            println!("\n  {} copies, {} each:", copies.len(), format_size(size));
+            let best = print_copies(photo_dir, &hashed, copies, |_, _| String::new());
+            kept.insert(best, copies.len() - 1);
+        }
+    }
+    
+    // Near copies: the copy to keep of each content, compared by perceptual hash
+    let mut near = Vec::new();
+    if !exact_only {
+        let duplicated: HashMap<&str, usize> = kept.keys()
+            .filter_map(|&i| Some((hashed[i].hash.as_deref()?, i)))
+            .collect();
+        let representatives: Vec<usize> = hashed.iter()
+            .enumerate()
+            .filter(|(i, photo)| {
+                photo.dhash.is_some()
+                    && photo.hash.as_deref().and_then(|h| duplicated.get(h)).is_none_or(|best| best == i)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        near = near_duplicate_groups(&hashed, &representatives, max_distance);
+        if !near.is_empty() {
+            println!("\n≈  Near duplicates: {} groups", near.len());
+            for copies in &near {
+                println!("\n  {} similar photos:", copies.len());
+                print_copies(photo_dir, &hashed, copies, |best, copy| {
+                    let mut detail = String::new();
+                    if copy != best
+                        && let (Some(a), Some(b)) = (hashed[best].dhash, hashed[copy].dhash)
+                    {
+                        detail.push_str(&format!(" (distance {})", (a ^ b).count_ones()));
+                    }
+                    if let Some(identical) = kept.get(&copy) {
+                        detail.push_str(&format!(" (+{} identical)", identical));
+                    }
+                    detail
+                });
+            }
+        }
+    }
+    
+    println!("\n📊 Duplicate Summary:");
+    println!("  📸 Photos checked: {}", hashed.len());
+    println!("  🟰 Exact duplicate groups: {} ({} reclaimable)", exact.len(), format_size(wasted));
+    if !exact_only {
+        println!("  ≈  Near duplicate groups: {}", near.len());
+        if not_compared > 0 {
+            println!("  ⏭️  Without a preview to compare: {}", not_compared);
+        }
+    }
+    if !errors.is_empty() {
+        println!("  ❌ Errors: {}", errors.len());
+        for error in errors.iter().take(5) {
+            println!("     {}", error);
+        }
+    }
+    if interrupted() {
+        println!("\n⏸️  Stopped early; only the photos hashed so far were compared.");
+    }
+    println!("  Total time: {:.2}s", start_time.elapsed().as_secs_f64());
+    if !exact.is_empty() || !near.is_empty() {
+        println!("\n⭐ marks the copy whose sidecar holds the most: the one to keep.");
+    }
+    
+    Ok(())
+}
+
+/// The perceptual hash of a photo's preview, from the manifest if this content was
+/// hashed before; `None` for files that can't be decoded
+fn perceptual_hash(photo: &PhotoFile, content_hash: &str, manifest: &Manifest) -> Result<Option<u64>> {
+    if let Some(dhash) = manifest.perceptual_hash(content_hash)? {
+        return Ok(Some(dhash));
+    }
+    let Some(image) = preview_image(photo)? else {
+        return Ok(None);
+    };
+    let dhash = difference_hash(&image);
+    manifest.record_perceptual_hash(content_hash, dhash)?;
+    Ok(Some(dhash))
+}
+
+/// The picture of a photo, small as it may be: the embedded preview (or thumbnail)
+/// of a RAW file is plenty for a perceptual hash
+pub fn preview_image(photo: &PhotoFile) -> Result<Option<DynamicImage>> {
+    match photo.file_type.reader {
+        Reader::MetadataOnly => Ok(None),
+        Reader::EmbeddedPreview => match extract_embedded_jpeg(&photo.path)? {
+            Some(preview) => Ok(Some(image::load_from_memory(&preview.data)
+                .context("Failed to decode embedded preview")?)),
+            None => Ok(None),
+        },
+        Reader::Decode => {
+            let image = ImageReader::open(&photo.path)?
+                .with_guessed_format()?
+                .decode()
+                .with_context(|| format!("Failed to decode {}", photo.path.display()))?;
+            Ok(Some(image))
+        }
+    }
+}
+
+/// dHash: shrink to 9x8 grey pixels and record whether each pixel is brighter than
+/// its right-hand neighbour. Survives resizing, re-encoding and small edits.
+pub fn difference_hash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Group photos whose perceptual hashes are within `max_distance` bits of each
+/// other, directly or through another photo of the group
+fn near_duplicate_groups(hashed: &[HashedPhoto], candidates: &[usize], max_distance: u32) -> Vec<Vec<usize>> {
+    // Union-find over positions in `candidates`
+    let mut parent: Vec<usize> = (0..candidates.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    
+    for a in 0..candidates.len() {
+        let (first, Some(first_hash)) = (&hashed[candidates[a]], hashed[candidates[a]].dhash) else {
+            continue;
+        };
+        for b in a + 1..candidates.len() {
+            let second = &hashed[candidates[b]];
+            let Some(second_hash) = second.dhash else {
+                continue;
+            };
+            if (first_hash ^ second_hash).count_ones() > max_distance || same_picture(&first.photo, &second.photo) {
+                continue;
+            }
+            let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+            parent[rb] = ra;
+        }
+    }
+    
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, &candidate) in candidates.iter().enumerate() {
+        let r = root(&mut parent, i);
+        groups.entry(r).or_default().push(candidate);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().filter(|g| g.len() > 1).collect();
+    groups.sort();
+    groups
+}
+
+/// Files of a RAW+JPEG pair or its edits look alike on purpose
+fn same_picture(a: &PhotoFile, b: &PhotoFile) -> bool {
+    a.path.parent() == b.path.parent() && base_name(&a.filename).0 == base_name(&b.filename).0
+}
+
+/// List the copies of a group, the one with the richest sidecar first and starred,
+/// each with `detail` about it compared to that one. Returns the starred copy.
+fn print_copies(
+    photo_dir: &str,
+    hashed: &[HashedPhoto],
+    copies: &[usize],
+    detail: impl Fn(usize, usize) -> String,
+) -> usize {
+    let mut ranked: Vec<(Richness, usize)> = copies.iter()
+        .map(|&i| (Richness::of(&hashed[i].photo), i))
+        .collect();
+    // Richest first; among equals, the first in path order
+    ranked.sort_by(|(a, a_index), (b, b_index)| {
+        b.cmp(a).then_with(|| hashed[*a_index].photo.path.cmp(&hashed[*b_index].photo.path))
+    });
+    
+    let best = ranked[0].1;
+    for (i, (richness, index)) in ranked.iter().enumerate() {
+        let copy = &hashed[*index];
+        let marker = if i == 0 { "⭐" } else { "  " };
+        let path = copy.photo.path.strip_prefix(photo_dir).unwrap_or(&copy.photo.path);
+        println!("     {} {} ({}){}", marker, path.display(), richness.describe(), detail(best, *index));
+    }
+    best
+}
+
+fn is_empty(value: &serde_yaml::Value) -> bool {
+    match value {
+        serde_yaml::Value::Null => true,
+        serde_yaml::Value::String(s) => s.trim().is_empty(),
+        serde_yaml::Value::Sequence(items) => items.is_empty(),
+        serde_yaml::Value::Mapping(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    if mb >= 1024.0 {
+        format!("{:.1} GB", mb / 1024.0)
+    } else {
+        format!("{:.1} MB", mb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+    use std::path::PathBuf;
+    
+    /// A horizontal gradient, brightening or darkening to the right
+    fn gradient(rising: bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(90, 80, |x, _| {
+            let value = (x * 255 / 89) as u8;
+            Luma([if rising { value } else { 255 - value }])
+        }))
+    }
+    
+    fn hashed(path: &str, dhash: Option<u64>) -> HashedPhoto {
+        HashedPhoto {
+            photo: PhotoFile::new(PathBuf::from(path)).unwrap(),
+            size: 0,
+            hash: None,
+            dhash,
+        }
+    }
+    
+    #[test]
+    fn difference_hash_compares_neighbouring_pixels() {
+        assert_eq!(difference_hash(&gradient(true)), 0);
+        assert_eq!(difference_hash(&gradient(false)), u64::MAX);
+        // Resizing keeps the hash
+        let resized = gradient(false).resize_exact(300, 200, FilterType::Triangle);
+        assert_eq!(difference_hash(&resized), u64::MAX);
+    }
+    
+    #[test]
+    fn groups_near_duplicates_transitively() {
+        let photos = vec![
+            hashed("/photos/a.jpg", Some(0b0000)),
+            hashed("/photos/b.jpg", Some(0b0011)),
+            hashed("/photos/c.jpg", Some(0b1111)),
+            hashed("/photos/d.jpg", Some(u64::MAX)),
+            hashed("/photos/e.jpg", None),
+        ];
+        // a-b and b-c are 2 bits apart, a-c 4
+        let candidates: Vec<usize> = (0..photos.len()).collect();
+        assert_eq!(near_duplicate_groups(&photos, &candidates, 2), vec![vec![0, 1, 2]]);
+        assert_eq!(near_duplicate_groups(&photos, &candidates, 1), Vec::<Vec<usize>>::new());
+        // Only candidates are compared
+        assert_eq!(near_duplicate_groups(&photos, &[0, 2, 3], 2), Vec::<Vec<usize>>::new());
+    }
+    
+    #[test]
+    fn versions_of_one_picture_are_not_duplicates() {
+        let photos = vec![
+            hashed("/photos/IMG_1234.CR2", Some(0)),
+            hashed("/photos/IMG_1234-Edit.jpg", Some(0)),
+            hashed("/backup/IMG_1234.CR2", Some(0)),
+        ];
+        assert_eq!(near_duplicate_groups(&photos, &[0, 1], 0), Vec::<Vec<usize>>::new());
+        assert_eq!(near_duplicate_groups(&photos, &[0, 1, 2], 0), vec![vec![0, 1, 2]]);
+    }
+}
@@ -0,0 +1,646 @@
+use anyhow::{Result, Context, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chrono::Local;
+use clap::ValueEnum;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use crate::ai_queue::{install_interrupt_handler, interrupted};
+use crate::photo_walker::{PhotoFile, PhotoWalker};
+use crate::retry::{RetryPolicy, TransientError};
+use crate::state::{relative_key, state_dir};
+use crate::vision::BackendKind;
+use crate::clip_onnx::ClipOnnxEmbedder;
+
+pub const DEFAULT_MODEL: &str = "nomic-embed-text";
+
+/// Texts sent to the embedding server per request
+const TEXT_BATCH_SIZE: usize = 32;
+
+/// Dimensions of the fake embedder's vectors
+const FAKE_DIMENSIONS: usize = 256;
+
+/// Which kind of server, or local model, computes the embeddings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingBackend {
+    /// Ollama's `/api/embed` (text only)
+    #[default]
+    Ollama,
+    /// Any OpenAI-compatible `/v1/embeddings` server
+    Openai,
+    /// A CLIP model exported to ONNX, run on the CPU; the model is its directory
+    Onnx,
+    /// Hashed bag of words, for trying out search without a server
+    Fake,
+}
+
+impl EmbeddingBackend {
+    pub fn default_endpoint(&self) -> &'static str {
+        match self {
+            EmbeddingBackend::Ollama => BackendKind::Ollama.default_endpoint(),
+            EmbeddingBackend::Openai => BackendKind::Openai.default_endpoint(),
+            EmbeddingBackend::Onnx => "onnx",
+            EmbeddingBackend::Fake => "fake",
+        }
+    }
+}
+
+/// What gets embedded for each photo
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingSource {
+    /// The sidecar's text: title, caption, keywords, location and AI description and tags
+    #[default]
+    Text,
+    /// The pixels, for CLIP-style models that embed images and text into the same space.
+    /// Needs a local ONNX model or an OpenAI-compatible server; Ollama only embeds text.
+    Image,
+}
+
+impl EmbeddingSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmbeddingSource::Text => "text",
+            EmbeddingSource::Image => "image",
+        }
+    }
+    
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(EmbeddingSource::Text),
+            "image" => Some(EmbeddingSource::Image),
+            _ => None,
+        }
+    }
+}
+
+/// A server (or stand-in) that turns text or images into vectors
+pub trait Embedder {
+    fn model(&self) -> &str;
+    
+    fn endpoint(&self) -> &str;
+    
+    fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+    
+    /// Whether `embed_image` is supported at all
+    fn embeds_images(&self) -> bool {
+        false
+    }
+    
+    fn embed_image(&self, _jpeg_data: &[u8]) -> Result<Vec<f32>> {
+        Err(anyhow!("{} can't embed images; use --embed-source text", self.endpoint()))
+    }
+    
+    /// Embed a short text to make sure the server is up and the model works
+    fn check(&self) -> Result<()> {
+        let vectors = self.embed_texts(&["test".to_string()])
+            .with_context(|| format!("Embedding model '{}' is not usable at {}", self.model(), self.endpoint()))?;
+        if vectors.first().is_none_or(|v| v.is_empty()) {
+            return Err(anyhow!("Embedding model '{}' returned no vector; is it an embedding model?", self.model()));
+        }
+        Ok(())
+    }
+}
+
+pub fn create_embedder(kind: EmbeddingBackend, model: &str, endpoint: &str, api_key: Option<&str>) -> Result<Box<dyn Embedder>> {
+    Ok(match kind {
+        EmbeddingBackend::Ollama => Box::new(OllamaEmbedder {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+        }),
+        EmbeddingBackend::Openai => Box::new(OpenAiEmbedder {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            api_key: api_key.map(|k| k.to_string()),
+        }),
+        EmbeddingBackend::Onnx => Box::new(ClipOnnxEmbedder::open(model)?),
+        EmbeddingBackend::Fake => Box::new(FakeEmbedder { model: model.to_string() }),
+    })
+}
+
+/// POST a JSON request, treating connection problems and 5xx as transient
+fn post_json<T: for<'de> Deserialize<'de>>(request: reqwest::blocking::RequestBuilder, body: &serde_json::Value, endpoint: &str) -> Result<T> {
+    let response = request
+        .json(body)
+        .send()
+        .map_err(|e| TransientError(format!("Failed to send request to {}: {}", endpoint, e)))?;
+    
+    let status = response.status();
+    if status.is_server_error() {
+        return Err(TransientError(format!("API error from {}: {}", endpoint, status)).into());
+    }
+    if !status.is_success() {
+        let body = response.text().unwrap_or_default();
+        return Err(anyhow!("API error from {}: {} {}", endpoint, status, body.trim()));
+    }
+    
+    let text = response.text()
+        .map_err(|e| TransientError(format!("Failed to read response from {}: {}", endpoint, e)))?;
+    serde_json::from_str(&text).context("Failed to parse embedding response")
+}
+
+fn http_client() -> Result<reqwest::blocking::Client> {
+    Ok(reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(300))
+        .build()?)
+}
+
+/// Text embeddings through Ollama's `/api/embed`
+struct OllamaEmbedder {
+    endpoint: String,
+    model: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+impl Embedder for OllamaEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+    
+    fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+    
+    fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let body = serde_json::json!({ "model": self.model, "input": texts });
+        let request = http_client()?.post(format!("{}/api/embed", self.endpoint));
+        let response: OllamaEmbedResponse = post_json(request, &body, &self.endpoint)?;
+        Ok(response.embeddings)
+    }
+}
+
+/// Embeddings through any OpenAI-compatible `/v1/embeddings` server. Images are
+/// sent as data URLs, which CLIP servers with an OpenAI-style API accept.
+struct OpenAiEmbedder {
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbedResponse {
+    data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbedding {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: usize,
+}
+
+impl OpenAiEmbedder {
+    fn embed_inputs(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let body = serde_json::json!({ "model": self.model, "input": inputs });
+        let mut request = http_client()?.post(format!("{}/v1/embeddings", self.endpoint));
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let mut response: OpenAiEmbedResponse = post_json(request, &body, &self.endpoint)?;
+        response.data.sort_by_key(|e| e.index);
+        Ok(response.data.into_iter().map(|e| e.embedding).collect())
+    }
+}
+
+impl Embedder for OpenAiEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+    
+    fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+    
+    fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.embed_inputs(texts)
+    }
+    
+    fn embeds_images(&self) -> bool {
+        true
+    }
+    
+    fn embed_image(&self, jpeg_data: &[u8]) -> Result<Vec<f32>> {
+        let image_url = format!("data:image/jpeg;base64,{}", BASE64.encode(jpeg_data));
+        self.embed_inputs(&[image_url])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Embedding response from {} has no vector", self.endpoint))
+    }
+}
+
+/// Hashed bag of words, so search can be tried without a server
+struct FakeEmbedder {
+    model: String,
+}
+
+impl Embedder for FakeEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+    
+    fn endpoint(&self) -> &str {
+        "fake"
+    }
+    
+    fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter()
+            .map(|text| {
+                let mut vector = vec![0.0; FAKE_DIMENSIONS];
+                for word in text.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+                    let hash = word.bytes().fold(0usize, |acc, b| acc.wrapping_mul(31).wrapping_add(b as usize));
+                    vector[hash % FAKE_DIMENSIONS] += 1.0;
+                }
+                vector
+            })
+            .collect())
+    }
+}
+
+/// Scale a vector to unit length, so cosine similarity is a dot product
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for x in &mut vector {
+            *x /= norm;
+        }
+    }
+    vector
+}
+
+fn vector_to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn blob_to_vector(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// The searchable text of a sidecar: human metadata first, then the AI's
+pub fn sidecar_text(frontmatter: &serde_yaml::Mapping) -> String {
+    const FIELDS: &[&str] = &[
+        "title", "caption", "location", "keywords",
+        "ai_description", "ai_tags", "ai_subjects", "ai_setting", "ai_mood", "ai_text_in_image", "text_in_image",
+    ];
+    
+    let mut parts = Vec::new();
+    for field in FIELDS {
+        match frontmatter.get(*field) {
+            Some(serde_yaml::Value::String(text)) if !text.trim().is_empty() => parts.push(text.trim().to_string()),
+            Some(serde_yaml::Value::Sequence(items)) => {
+                let items: Vec<&str> = items.iter().filter_map(|i| i.as_str()).collect();
+                if !items.is_empty() {
+                    parts.push(items.join(", "));
+                }
+            }
+            _ => {}
+        }
+    }
+    parts.join(". ")
+}
+
+/// Vector index of a photo directory, stored in `.zim-photo/embeddings.db`.
+/// One vector per photo; brute-force search is fast enough for a personal archive.
+pub struct EmbeddingIndex {
+    conn: Connection,
+    root: PathBuf,
+}
+
+impl EmbeddingIndex {
+    pub fn open<P: AsRef<Path>>(photo_root: P) -> Result<Self> {
+        let root = photo_root.as_ref().to_path_buf();
+        let db_path = state_dir(&root)?.join("embeddings.db");
+        let conn = Connection::open(&db_path)
+            .with_context(|| format!("Failed to open embedding index: {}", db_path.display()))?;
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS embeddings (
+                path TEXT PRIMARY KEY,
+                model TEXT NOT NULL,
+                source TEXT NOT NULL,
+                input_hash TEXT NOT NULL,
+                vector BLOB NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+        ")?;
+        Ok(EmbeddingIndex { conn, root })
+    }
+    
+    /// Whether the photo already has a vector for this model, source and input
+    pub fn is_current(&self, path: &Path, model: &str, source: EmbeddingSource, input_hash: &str) -> Result<bool> {
+        let existing: Option<(String, String, String)> = self.conn.query_row(
+            "SELECT model, source, input_hash FROM embeddings WHERE path = ?1",
+            params![relative_key(&self.root, path)],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).optional()?;
+        Ok(existing.is_some_and(|(m, s, h)| m == model && s == source.name() && h == input_hash))
+    }
+    
+    pub fn store(&self, path: &Path, model: &str, source: EmbeddingSource, input_hash: &str, vector: Vec<f32>) -> Result<()> {
+        self.conn.execute("
+            INSERT INTO embeddings (path, model, source, input_hash, vector, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(path) DO UPDATE SET
+                model = excluded.model, source = excluded.source, input_hash = excluded.input_hash,
+                vector = excluded.vector, updated_at = excluded.updated_at
+        ", params![
+            relative_key(&self.root, path),
+            model,
+            source.name(),
+            input_hash,
+            vector_to_blob(&normalize(vector)),
+            Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        ])?;
+        Ok(())
+    }
+    
+    /// The model and source most of the index was built with
+    pub fn model(&self) -> Result<Option<(String, String)>> {
+        Ok(self.conn.query_row(
+            "SELECT model, source FROM embeddings GROUP BY model, source ORDER BY COUNT(*) DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?)
+    }
+    
+    /// The source most vectors from `model` were computed from
+    pub fn source_for(&self, model: &str) -> Result<Option<EmbeddingSource>> {
+        let source: Option<String> = self.conn.query_row(
+            "SELECT source FROM embeddings WHERE model = ?1 GROUP BY source ORDER BY COUNT(*) DESC LIMIT 1",
+            params![model],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(source.as_deref().and_then(EmbeddingSource::from_name))
+    }
+    
+    /// Drop the vectors of photos not in `keep` (relative keys), e.g. deleted ones.
+    /// Returns how many were removed.
+    pub fn prune(&self, keep: &HashSet<String>) -> Result<usize> {
+        let paths: Vec<String> = self.conn.prepare("SELECT path FROM embeddings")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut removed = 0;
+        for path in paths.iter().filter(|p| !keep.contains(*p)) {
+            removed += self.conn.execute("DELETE FROM embeddings WHERE path = ?1", params![path])?;
+        }
+        Ok(removed)
+    }
+    
+    /// The `limit` photos closest to `query`, best first, among vectors from `model`
+    /// and `source`. Photos that are gone from disk are left out.
+    pub fn nearest(&self, query: Vec<f32>, model: &str, source: EmbeddingSource, limit: usize) -> Result<Vec<(f32, PathBuf)>> {
+        let query = normalize(query);
+        let mut stmt = self.conn.prepare("SELECT path, vector FROM embeddings WHERE model = ?1 AND source = ?2")?;
+        let rows = stmt.query_map(params![model, source.name()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?;
+        
+        let mut scored = Vec::new();
+        let mut mismatched = None;
+        for row in rows {
+            let (path, blob) = row?;
+            let path = self.root.join(path);
+            if !path.exists() {
+                continue;
+            }
+            let vector = blob_to_vector(&blob);
+            if vector.len() != query.len() {
+                mismatched = Some(vector.len());
+                continue;
+            }
+            let score: f32 = vector.iter().zip(&query).map(|(a, b)| a * b).sum();
+            scored.push((score, path));
+        }
+        
+        if let Some(dimensions) = mismatched
+            && scored.is_empty()
+        {
+            return Err(anyhow!(
+                "The index has {}-dimensional vectors but the query has {}; search with the backend and model used for embed",
+                dimensions, query.len()
+            ));
+        }
+        
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(limit);
+        Ok(scored)
+    }
+}
+
+/// Embed every photo under a directory that doesn't have an up-to-date vector yet
+pub fn embed_directory(
+    photo_dir: &str,
+    embedder: &dyn Embedder,
+    source: EmbeddingSource,
+    force: bool,
+    show_progress: bool,
+) -> Result<()> {
+    use crate::sidecar_reader::read_sidecar_metadata;
+    
+    if source == EmbeddingSource::Image && !embedder.embeds_images() {
+        return Err(anyhow!(
+            "{} only embeds text. Image embeddings need a CLIP model, either exported to ONNX and run locally \
+             (--embed-backend onnx) or on an OpenAI-compatible server (--embed-backend openai). \
+             Use --embed-source text otherwise.",
+            embedder.endpoint()
+        ));
+    }
+    
+    println!("🧭 Computing {} embeddings with {} for: {}\n", source.name(), embedder.model(), photo_dir);
+    
+    let start_time = Instant::now();
+    install_interrupt_handler()?;
+    let index = EmbeddingIndex::open(photo_dir)?;
+    let retry = RetryPolicy::default();
+    
+    let photos = PhotoWalker::new(photo_dir, false).find_photos()?;
+    let total = photos.len();
+    let mut embedded = 0;
+    let mut unchanged = 0;
+    let mut skipped = 0;
+    let mut errors = Vec::new();
+    // Photos that still have something to embed; vectors of any others are pruned
+    let mut present = HashSet::new();
+    
+    // Text is embedded in batches; images one at a time
+    let mut batch: Vec<(PhotoFile, String, String)> = Vec::new();
+    let flush = |batch: &mut Vec<(PhotoFile, String, String)>, embedded: &mut usize, errors: &mut Vec<String>| -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let texts: Vec<String> = batch.iter().map(|(_, text, _)| text.clone()).collect();
+        match retry.run(|| embedder.embed_texts(&texts)) {
+            Ok(vectors) if vectors.len() == batch.len() => {
+                for ((photo, _, hash), vector) in batch.drain(..).zip(vectors) {
+                    index.store(&photo.path, embedder.model(), source, &hash, vector)?;
+                    *embedded += 1;
+                }
+            }
+            Ok(vectors) => {
+                errors.push(format!("Expected {} vectors, got {}", batch.len(), vectors.len()));
+                batch.clear();
+            }
+            Err(e) => {
+                for (photo, _, _) in batch.drain(..) {
+                    errors.push(format!("{}: {:#}", photo.filename, e));
+                }
+            }
+        }
+        Ok(())
+    };
+    
+    for (i, photo) in photos.into_iter().enumerate() {
+        if interrupted() {
+            break;
+        }
+        if show_progress && i % 100 == 0 {
+            println!("  Embedding: {}/{} ({:.1}%)", i, total, i as f64 / total.max(1) as f64 * 100.0);
+        }
+        
+        match source {
+            EmbeddingSource::Text => {
+                let text = if photo.has_sidecar {
+                    read_sidecar_metadata(&photo.sidecar_path).map(|m| sidecar_text(&m)).unwrap_or_default()
+                } else {
+                    String::new()
+                };
+                if text.is_empty() {
+                    skipped += 1;
+                    continue;
+                }
+                present.insert(relative_key(&index.root, &photo.path));
+                
+                let hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+                if !force && index.is_current(&photo.path, embedder.model(), source, &hash)? {
+                    unchanged += 1;
+                    continue;
+                }
+                batch.push((photo, text, hash));
+                if batch.len() >= TEXT_BATCH_SIZE {
+                    flush(&mut batch, &mut embedded, &mut errors)?;
+                }
+            }
+            EmbeddingSource::Image if !photo.file_type.can_preview() => {}
+            EmbeddingSource::Image => {
+                present.insert(relative_key(&index.root, &photo.path));
+                // Size and modification time stand in for the content, to avoid reading every file
+                let hash = match std::fs::metadata(&photo.path) {
+                    Ok(meta) => format!("{}:{:?}", meta.len(), meta.modified().ok()),
+                    Err(e) => {
+                        errors.push(format!("{}: {}", photo.filename, e));
+                        continue;
+                    }
+                };
+                if !force && index.is_current(&photo.path, embedder.model(), source, &hash)? {
+                    unchanged += 1;
+                    continue;
+                }
+                
+                let result = crate::ollama_vision::convert_to_jpeg(&photo.path)
+                    .and_then(|(jpeg, _)| retry.run(|| embedder.embed_image(&jpeg)));
+                match result {
+                    Ok(vector) => {
+                        index.store(&photo.path, embedder.model(), source, &hash, vector)?;
+                        embedded += 1;
+                    }
+                    Err(e) => errors.push(format!("{}: {:#}", photo.filename, e)),
+                }
+            }
+        }
+    }
+    flush(&mut batch, &mut embedded, &mut errors)?;
+    
+    // Only a full walk knows which photos are gone
+    let removed = if interrupted() { 0 } else { index.prune(&present)? };
+    
+    println!("\n📊 Embedding Summary:");
+    println!("  ✅ Embedded: {}", embedded);
+    println!("  ⏭️  Already up to date: {}", unchanged);
+    if skipped > 0 {
+        println!("  📝 No sidecar text to embed: {}", skipped);
+    }
+    if removed > 0 {
+        println!("  🗑️  Removed vectors of deleted photos: {}", removed);
+    }
+    if !errors.is_empty() {
+        println!("  ❌ Errors: {}", errors.len());
+        for error in errors.iter().take(10) {
+            println!("     {}", error);
+        }
+        if errors.len() > 10 {
+            println!("     ... and {} more", errors.len() - 10);
+        }
+    }
+    if interrupted() {
+        println!("\n⏸️  Stopped early. Run embed again to continue.");
+    }
+    println!("  Total time: {:.2}s", start_time.elapsed().as_secs_f64());
+    
+    Ok(())
+}
+
+/// Print the photos whose embeddings are closest to the query text
+pub fn semantic_search(photo_dir: &str, query: &str, embedder: &dyn Embedder, limit: usize) -> Result<()> {
+    let index = EmbeddingIndex::open(photo_dir)?;
+    if index.model()?.is_none() {
+        return Err(anyhow!("No embeddings for {} yet; run `zim-photo embed {}` first", photo_dir, photo_dir));
+    }
+    let Some(source) = index.source_for(embedder.model())? else {
+        println!("No photos embedded with '{}' (run embed with the same model)", embedder.model());
+        return Ok(());
+    };
+    
+    let query_vector = RetryPolicy::default()
+        .run(|| embedder.embed_texts(&[query.to_string()]))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Embedding server returned no vector for the query"))?;
+    
+    let results = index.nearest(query_vector, embedder.model(), source, limit)?;
+    if results.is_empty() {
+        println!("No photos embedded with '{}' (run embed with the same model)", embedder.model());
+        return Ok(());
+    }
+    
+    for (rank, (score, path)) in results.iter().enumerate() {
+        println!("{:>3}. {:.3}  {}", rank + 1, score, path.display());
+    }
+    Ok(())
+}
+
+/// Print the photos whose sidecar text contains every word of the query
+pub fn text_search(photo_dir: &str, query: &str, limit: usize) -> Result<()> {
+    use crate::sidecar_reader::read_sidecar_metadata;
+    
+    let words: Vec<String> = query.to_lowercase().split_whitespace().map(str::to_string).collect();
+    let mut found = 0;
+    for photo in PhotoWalker::new(photo_dir, false).find_photos()? {
+        if !photo.has_sidecar {
+            continue;
+        }
+        let Ok(frontmatter) = read_sidecar_metadata(&photo.sidecar_path) else {
+            continue;
+        };
+        let text = sidecar_text(&frontmatter).to_lowercase();
+        if words.iter().all(|word| text.contains(word.as_str())) {
+            found += 1;
+            println!("{:>3}. {}", found, photo.path.display());
+            if found >= limit {
+                break;
+            }
+        }
+    }
+    
+    if found == 0 {
+        println!("No photos match '{}'", query);
+    }
+    Ok(())
+}
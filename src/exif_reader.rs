@@ -4,12 +4,14 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use exif::{In, Tag, Value};
-use chrono::{DateTime, NaiveDateTime, Local};
+use chrono::{DateTime, Duration, NaiveDateTime, Local};
 
 pub struct ExifData {
     pub camera_make: Option<String>,
     pub camera_model: Option<String>,
+    pub camera_serial: Option<String>,
     pub lens_info: Option<String>,
+    /// Capture time, to the fraction of a second the camera recorded
     pub date_taken: Option<DateTime<Local>>,
     pub iso: Option<u32>,
     pub aperture: Option<f64>,
@@ -30,6 +32,9 @@ impl ExifData {
         if let Some(model) = &self.camera_model {
             map.insert("camera_model".to_string(), model.clone());
         }
+        if let Some(serial) = &self.camera_serial {
+            map.insert("camera_serial".to_string(), serial.clone());
+        }
         if let Some(lens) = &self.lens_info {
             map.insert("lens".to_string(), lens.clone());
         }
@@ -74,6 +79,7 @@ pub fn read_exif<P: AsRef<Path>>(path: P) -> Result<ExifData> {
     let mut data = ExifData {
         camera_make: None,
         camera_model: None,
+        camera_serial: None,
         lens_info: None,
         date_taken: None,
         iso: None,
@@ -84,13 +90,28 @@ pub fn read_exif<P: AsRef<Path>>(path: P) -> Result<ExifData> {
         gps_longitude: None,
         gps_altitude: None,
     };
+    let mut from_original = false;
+    let mut subsec = None;
     
     for field in exif.fields() {
         match field.tag {
             Tag::Make => data.camera_make = field.display_value().to_string().into(),
             Tag::Model => data.camera_model = field.display_value().to_string().into(),
+            Tag::BodySerialNumber => {
+                let serial = field.display_value().to_string().trim_matches('"').trim().to_string();
+                data.camera_serial = (!serial.is_empty()).then_some(serial);
+            }
             Tag::LensModel => data.lens_info = field.display_value().to_string().into(),
-            Tag::DateTime | Tag::DateTimeOriginal | Tag::DateTimeDigitized => {
+            // The capture time wins over the times of digitizing and last editing
+            Tag::DateTimeOriginal => {
+                if let Some(date) = parse_exif_datetime(&field.display_value().to_string()) {
+                    data.date_taken = Some(date);
+                    from_original = true;
+                }
+            },
+            // Fractions of a second, which tell apart the frames of a burst
+            Tag::SubSecTimeOriginal => subsec = parse_subsec(&field.display_value().to_string()),
+            Tag::DateTime | Tag::DateTimeDigitized => {
                 if data.date_taken.is_none() {
                     data.date_taken = parse_exif_datetime(&field.display_value().to_string());
                 }
@@ -131,6 +152,12 @@ pub fn read_exif<P: AsRef<Path>>(path: P) -> Result<ExifData> {
         }
     }
     
+    if from_original
+        && let (Some(date), Some(subsec)) = (data.date_taken, subsec)
+    {
+        data.date_taken = Some(date + subsec);
+    }
+    
     // GPS data extraction
     if let Some(lat) = get_gps_coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef) {
         data.gps_latitude = Some(lat);
@@ -150,13 +177,24 @@ pub fn read_exif<P: AsRef<Path>>(path: P) -> Result<ExifData> {
 }
 
 fn parse_exif_datetime(datetime_str: &str) -> Option<DateTime<Local>> {
-    // EXIF datetime format: "2023:08:15 14:30:45"
+    // EXIF datetime format: "2023:08:15 14:30:45", displayed by the exif crate as "2023-08-15 14:30:45"
     let cleaned = datetime_str.trim_matches('"');
-    NaiveDateTime::parse_from_str(cleaned, "%Y:%m:%d %H:%M:%S")
+    NaiveDateTime::parse_from_str(cleaned, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(cleaned, "%Y:%m:%d %H:%M:%S"))
         .ok()
         .map(|naive| DateTime::from_naive_utc_and_offset(naive, *Local::now().offset()))
 }
 
+/// EXIF subseconds are the digits after the decimal point: "45" is 0.45 seconds
+fn parse_subsec(subsec_str: &str) -> Option<Duration> {
+    let digits: String = subsec_str.trim_matches('"').trim().chars().take(9).collect();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let nanos: i64 = format!("{:0<9}", digits).parse().ok()?;
+    Some(Duration::nanoseconds(nanos))
+}
+
 fn get_gps_coordinate(exif: &exif::Exif, coord_tag: Tag, ref_tag: Tag) -> Option<f64> {
     let coord_field = exif.get_field(coord_tag, In::PRIMARY)?;
     let ref_field = exif.get_field(ref_tag, In::PRIMARY)?;
@@ -179,4 +217,17 @@ fn get_gps_coordinate(exif: &exif::Exif, coord_tag: Tag, ref_tag: Tag) -> Option
     }
     
     None
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn parses_exif_and_displayed_datetimes() {
+        let expected = NaiveDateTime::parse_from_str("2023-08-15 14:30:45", "%Y-%m-%d %H:%M:%S").unwrap();
+        for value in ["2023:08:15 14:30:45", "2023-08-15 14:30:45", "\"2023-08-15 14:30:45\""] {
+            assert_eq!(parse_exif_datetime(value).map(|date| date.naive_utc()), Some(expected), "{}", value);
+        }
+        assert!(parse_exif_datetime("    :  :     :  :  ").is_none());
+    }
+}
@@ -0,0 +1,317 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// What kind of file a photo is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    /// Camera RAW file
+    Raw,
+    /// Processed image: JPEG, TIFF, PNG, PSD, HEIC, ...
+    Image,
+}
+
+/// How pixels are obtained when a photo has to be looked at (AI, OCR, quality)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reader {
+    /// Decoded by the image crate
+    Decode,
+    /// The JPEG preview embedded in a RAW file
+    EmbeddedPreview,
+    /// Sidecars only: metadata is read, but the image can't be decoded
+    MetadataOnly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileType {
+    pub kind: MediaKind,
+    pub reader: Reader,
+}
+
+impl FileType {
+    const fn raw() -> Self {
+        FileType { kind: MediaKind::Raw, reader: Reader::EmbeddedPreview }
+    }
+
+    const fn image() -> Self {
+        FileType { kind: MediaKind::Image, reader: Reader::Decode }
+    }
+
+    const fn metadata_only(kind: MediaKind) -> Self {
+        FileType { kind, reader: Reader::MetadataOnly }
+    }
+
+    /// Whether the image can be turned into a JPEG for analysis
+    pub fn can_preview(&self) -> bool {
+        self.reader != Reader::MetadataOnly
+    }
+}
+
+/// Built-in file types, by lowercase extension
+const BUILTIN_TYPES: &[(&str, FileType)] = &[
+    ("jpg", FileType::image()),
+    ("jpeg", FileType::image()),
+    ("tif", FileType::image()),
+    ("tiff", FileType::image()),
+    ("png", FileType::image()),
+    ("cr2", FileType::raw()),  // Canon
+    ("nef", FileType::raw()),  // Nikon
+    ("dng", FileType::raw()),  // Adobe Digital Negative
+    ("pef", FileType::raw()),  // Pentax
+    ("raf", FileType::raw()),  // Fujifilm
+    ("arw", FileType::raw()),  // Sony
+    ("orf", FileType::raw()),  // Olympus
+    ("rw2", FileType::raw()),  // Panasonic
+    ("3fr", FileType::raw()),  // Hasselblad
+    ("psd", FileType::metadata_only(MediaKind::Image)),
+    ("heic", FileType::metadata_only(MediaKind::Image)),
+];
+
+/// A file type added or overridden in the config file, e.g.
+/// `file_types: { x3f: { kind: raw }, heic: { kind: image, reader: decode } }`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileTypeConfig {
+    pub kind: MediaKind,
+    /// Defaults to the embedded preview for RAW files and decoding for images
+    pub reader: Option<Reader>,
+}
+
+/// Maps file extensions (case-insensitively) to file types
+pub struct FileTypeRegistry {
+    by_extension: HashMap<String, FileType>,
+}
+
+impl Default for FileTypeRegistry {
+    fn default() -> Self {
+        FileTypeRegistry {
+            by_extension: BUILTIN_TYPES.iter()
+                .map(|(extension, file_type)| (extension.to_string(), *file_type))
+                .collect(),
+        }
+    }
+}
+
+impl FileTypeRegistry {
+    /// The built-in types plus those from the config file
+    pub fn with_config(config: &HashMap<String, FileTypeConfig>) -> Self {
+        let mut registry = FileTypeRegistry::default();
+        for (extension, entry) in config {
+            let reader = entry.reader.unwrap_or(match entry.kind {
+                MediaKind::Raw => Reader::EmbeddedPreview,
+                MediaKind::Image => Reader::Decode,
+            });
+            let extension = extension.trim_start_matches('.').to_lowercase();
+            registry.by_extension.insert(extension, FileType { kind: entry.kind, reader });
+        }
+        registry
+    }
+
+    pub fn by_extension(&self, extension: &str) -> Option<FileType> {
+        self.by_extension.get(&extension.to_lowercase()).copied()
+    }
+
+    /// The file type of a path, and the extension it was recognized by. Files
+    /// without an extension are recognized by their first bytes.
+    pub fn identify(&self, path: &Path) -> Option<(FileType, String)> {
+        match path.extension() {
+            Some(extension) => {
+                let extension = extension.to_str()?;
+                Some((self.by_extension(extension)?, extension.to_string()))
+            }
+            None => {
+                let extension = sniff(path)?;
+                Some((self.by_extension(extension)?, extension.to_string()))
+            }
+        }
+    }
+}
+
+/// Extensionless files smaller than this aren't opened to guess their type;
+/// lockfiles, notes and the like are, photos never are
+const MIN_SNIFF_SIZE: u64 = 16 * 1024;
+
+/// How much of an extensionless TIFF-like file is read to tell RAW files from TIFFs
+const TIFF_SNIFF_BYTES: u64 = 64 * 1024;
+
+// TIFF tags that tell camera RAW files apart from plain TIFFs
+const TAG_NEW_SUBFILE_TYPE: u16 = 0x00FE;
+const TAG_MAKE: u16 = 0x010F;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_DNG_VERSION: u16 = 0xC612;
+
+/// Extensions of TIFF-based RAW files by the start of the camera maker's name
+const RAW_MAKERS: &[(&str, &str)] = &[
+    ("nikon", "nef"),
+    ("sony", "arw"),
+    ("pentax", "pef"),
+    ("ricoh", "pef"),
+    ("hasselblad", "3fr"),
+    ("canon", "cr2"),
+    ("olympus", "orf"),
+    ("om digital", "orf"),
+    ("panasonic", "rw2"),
+];
+
+static REGISTRY: OnceLock<FileTypeRegistry> = OnceLock::new();
+
+/// Use these file types for the rest of the run (call once, before walking)
+pub fn init(registry: FileTypeRegistry) {
+    let _ = REGISTRY.set(registry);
+}
+
+/// The file types in effect: the configured ones, or the built-ins
+pub fn registry() -> &'static FileTypeRegistry {
+    REGISTRY.get_or_init(FileTypeRegistry::default)
+}
+
+/// Guess the extension of a file from its magic bytes. Only regular files large
+/// enough to be a photo are opened.
+fn sniff(path: &Path) -> Option<&'static str> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() < MIN_SNIFF_SIZE {
+        return None;
+    }
+
+    let mut header = Vec::new();
+    File::open(path).ok()?.take(TIFF_SNIFF_BYTES).read_to_end(&mut header).ok()?;
+    let header = header.as_slice();
+
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if header.starts_with(b"FUJIFILMCCD-RAW") {
+        Some("raf")
+    } else if header.starts_with(b"IIRO") || header.starts_with(b"IIRS") {
+        Some("orf")
+    } else if header.starts_with(b"IIU\0") {
+        Some("rw2")
+    } else if header.starts_with(b"II*\0") && header.get(8..10) == Some(b"CR") {
+        Some("cr2")
+    } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        // NEF, DNG and friends look like any other TIFF from the first bytes
+        Some(sniff_tiff(header))
+    } else if header.starts_with(b"8BPS") {
+        Some("psd")
+    } else if header.get(4..8) == Some(b"ftyp")
+        && matches!(header.get(8..12), Some(b"heic" | b"heix" | b"mif1" | b"msf1"))
+    {
+        Some("heic")
+    } else {
+        None
+    }
+}
+
+/// Tell TIFF-based RAW files from plain TIFFs by their first IFD: DNGs carry a
+/// DNGVersion, and the others a camera maker's Make with the first IFD holding
+/// only a reduced-size image or SubIFDs for the full one. TIFFs exported from a
+/// RAW file keep its Make, but their first image is the full one.
+fn sniff_tiff(data: &[u8]) -> &'static str {
+    let big_endian = data.starts_with(b"MM");
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = data.get(offset..offset + 2)?;
+        Some(if big_endian { u16::from_be_bytes([bytes[0], bytes[1]]) } else { u16::from_le_bytes([bytes[0], bytes[1]]) })
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+
+    let Some(ifd) = u32_at(4).map(|offset| offset as usize) else {
+        return "tif";
+    };
+    let count = u16_at(ifd).unwrap_or(0) as usize;
+    let mut make = None;
+    let mut reduced = false;
+    for entry in (0..count.min(256)).map(|i| ifd + 2 + i * 12) {
+        let (Some(tag), Some(length), Some(value)) = (u16_at(entry), u32_at(entry + 4), u32_at(entry + 8)) else {
+            break;
+        };
+        match tag {
+            TAG_DNG_VERSION => return "dng",
+            TAG_SUB_IFDS => reduced = true,
+            // Bit 0: a reduced-resolution version of another image in the file
+            TAG_NEW_SUBFILE_TYPE => reduced |= value & 1 == 1,
+            TAG_MAKE => {
+                let (start, length) = if length <= 4 { (entry + 8, length as usize) } else { (value as usize, length as usize) };
+                make = data.get(start..start + length)
+                    .map(|bytes| String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_lowercase());
+            }
+            _ => {}
+        }
+    }
+
+    match make {
+        Some(make) if reduced => RAW_MAKERS.iter()
+            .find(|(maker, _)| make.starts_with(maker))
+            .map_or("tif", |(_, extension)| extension),
+        _ => "tif",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A little-endian TIFF whose first IFD has the given tags (SHORT/LONG values)
+    /// and, if given, a Make; padded so it's large enough to be sniffed
+    fn tiff(tags: &[(u16, u32)], make: Option<&str>) -> Vec<u8> {
+        let count = tags.len() + make.is_some() as usize;
+        let strings_at = 8 + 2 + count * 12 + 4;
+        let mut out = b"II*\0\x08\0\0\0".to_vec();
+        out.extend((count as u16).to_le_bytes());
+        for (tag, value) in tags {
+            out.extend(tag.to_le_bytes());
+            out.extend(4u16.to_le_bytes());
+            out.extend(1u32.to_le_bytes());
+            out.extend(value.to_le_bytes());
+        }
+        if let Some(make) = make {
+            out.extend(TAG_MAKE.to_le_bytes());
+            out.extend(2u16.to_le_bytes());
+            out.extend((make.len() as u32 + 1).to_le_bytes());
+            out.extend((strings_at as u32).to_le_bytes());
+        }
+        out.extend(0u32.to_le_bytes());
+        if let Some(make) = make {
+            out.extend(make.as_bytes());
+            out.push(0);
+        }
+        out.resize(MIN_SNIFF_SIZE as usize, 0);
+        out
+    }
+
+    fn sniff_bytes(name: &str, data: &[u8]) -> Option<&'static str> {
+        let path: PathBuf = std::env::temp_dir().join(format!("zim-photo-sniff-{}-{}", std::process::id(), name));
+        fs::write(&path, data).unwrap();
+        let extension = sniff(&path);
+        fs::remove_file(&path).unwrap();
+        extension
+    }
+
+    #[test]
+    fn small_files_and_directories_are_not_opened() {
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0];
+        assert_eq!(sniff_bytes("small", &jpeg), None);
+        jpeg.resize(MIN_SNIFF_SIZE as usize, 0);
+        assert_eq!(sniff_bytes("large", &jpeg), Some("jpg"));
+        assert_eq!(sniff(&std::env::temp_dir()), None);
+    }
+
+    #[test]
+    fn tiff_based_raw_files_are_told_from_tiffs() {
+        assert_eq!(sniff_bytes("dng", &tiff(&[(TAG_NEW_SUBFILE_TYPE, 1), (TAG_DNG_VERSION, 0x0104)], Some("Canon"))), Some("dng"));
+        assert_eq!(sniff_bytes("nef", &tiff(&[(TAG_NEW_SUBFILE_TYPE, 1), (TAG_SUB_IFDS, 64)], Some("NIKON CORPORATION"))), Some("nef"));
+        assert_eq!(sniff_bytes("arw", &tiff(&[(TAG_NEW_SUBFILE_TYPE, 1)], Some("SONY"))), Some("arw"));
+        // Exported from a NEF: the Make is kept, but the first image is the full one
+        assert_eq!(sniff_bytes("export", &tiff(&[(TAG_NEW_SUBFILE_TYPE, 0)], Some("NIKON CORPORATION"))), Some("tif"));
+        assert_eq!(sniff_bytes("scan", &tiff(&[], None)), Some("tif"));
+    }
+}
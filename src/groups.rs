@@ -0,0 +1,150 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::file_types::MediaKind;
+use crate::ignore_rules::IgnoreRules;
+use crate::photo_walker::PhotoFile;
+
+/// Suffixes editors add to the files they export: `IMG_1234-Edit.tif`,
+/// `IMG_1234_HDR.dng`, `IMG_1234-Enhanced-NR.dng`
+const DERIVATIVE_SUFFIXES: &[&str] = &["edit", "hdr", "pano", "enhanced", "nr", "sr", "denoise"];
+
+/// Separators between a file name and a derivative suffix
+const SUFFIX_SEPARATORS: [char; 3] = ['-', '_', ' '];
+
+/// Versions of the same picture in one folder: the RAW file, the JPEG the camera
+/// wrote alongside it and edits exported from either. The master gets the sidecar.
+#[derive(Debug, Clone)]
+pub struct PhotoGroup {
+    pub master: PhotoFile,
+    pub companions: Vec<PhotoFile>,
+}
+
+impl PhotoGroup {
+    pub fn companion_names(&self) -> Vec<String> {
+        self.companions.iter().map(|c| c.filename.clone()).collect()
+    }
+    
+    pub fn contains(&self, path: &Path) -> bool {
+        self.master.path == path || self.companions.iter().any(|c| c.path == path)
+    }
+}
+
+impl From<PhotoFile> for PhotoGroup {
+    fn from(photo: PhotoFile) -> Self {
+        PhotoGroup { master: photo, companions: Vec::new() }
+    }
+}
+
+/// The name a file shares with the other versions of its picture (lowercase, no
+/// extension or derivative suffixes), and whether it is a derivative
+pub fn base_name(filename: &str) -> (String, bool) {
+    let stem = Path::new(filename).file_stem().and_then(|s| s.to_str()).unwrap_or(filename);
+    let mut base = stem.to_lowercase();
+    let mut derivative = false;
+    
+    let is_suffix = |part: &str| DERIVATIVE_SUFFIXES.contains(&part);
+    while let Some((rest, tail)) = base.rsplit_once(SUFFIX_SEPARATORS) {
+        // A numbered edit: IMG_1234-Edit-2
+        let numbered = tail.len() <= 2
+            && tail.chars().all(|c| c.is_ascii_digit())
+            && rest.rsplit_once(SUFFIX_SEPARATORS).is_some_and(|(_, t)| is_suffix(t));
+        if !is_suffix(tail) && !numbered {
+            break;
+        }
+        derivative = true;
+        base = rest.to_string();
+    }
+    (base, derivative)
+}
+
+/// Group photos by folder and base name, in the order their groups were first seen.
+/// The master is the original rather than an edit, RAW rather than JPEG, and one
+/// that can be looked at rather than one that can't.
+pub fn group_photos(photos: Vec<PhotoFile>) -> Vec<PhotoGroup> {
+    let mut order: Vec<(PathBuf, String)> = Vec::new();
+    let mut members: HashMap<(PathBuf, String), Vec<(bool, PhotoFile)>> = HashMap::new();
+    for photo in photos {
+        let (base, derivative) = base_name(&photo.filename);
+        let folder = photo.path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let key = (folder, base);
+        if !members.contains_key(&key) {
+            order.push(key.clone());
+        }
+        members.entry(key).or_default().push((derivative, photo));
+    }
+    
+    order.into_iter()
+        .filter_map(|key| {
+            let mut files = members.remove(&key)?;
+            files.sort_by(|(a_derivative, a), (b_derivative, b)| {
+                let rank = |derivative: bool, photo: &PhotoFile| {
+                    (derivative, photo.file_type.kind != MediaKind::Raw, !photo.file_type.can_preview())
+                };
+                rank(*a_derivative, a).cmp(&rank(*b_derivative, b))
+                    .then_with(|| a.filename.cmp(&b.filename))
+            });
+            let mut files = files.into_iter().map(|(_, photo)| photo);
+            let master = files.next()?;
+            let mut companions: Vec<PhotoFile> = files.collect();
+            companions.sort_by(|a, b| a.filename.cmp(&b.filename));
+            Some(PhotoGroup { master, companions })
+        })
+        .collect()
+}
+
+/// The group a photo belongs to, from the other photos in its folder
+pub fn group_of(photo: PhotoFile, root: &Path) -> Result<PhotoGroup> {
+    let Some(folder) = photo.path.parent() else {
+        return Ok(photo.into());
+    };
+    let (base, _) = base_name(&photo.filename);
+    let mut rules = IgnoreRules::for_root(root)?;
+    
+    let mut siblings = Vec::new();
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if path == photo.path || base_name(name).0 != base || !path.is_file() {
+            continue;
+        }
+        if rules.file_excluded_by(&path)?.is_some() {
+            continue;
+        }
+        if let Some(sibling) = PhotoFile::new(path) {
+            siblings.push(sibling);
+        }
+    }
+    
+    let path = photo.path.clone();
+    siblings.push(photo);
+    group_photos(siblings).into_iter()
+        .find(|group| group.contains(&path))
+        .ok_or_else(|| anyhow::anyhow!("{} is in no group", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn base_name_strips_extensions_and_derivative_suffixes() {
+        assert_eq!(base_name("IMG_1234.CR2"), ("img_1234".to_string(), false));
+        assert_eq!(base_name("IMG_1234.jpg"), ("img_1234".to_string(), false));
+        assert_eq!(base_name("IMG_1234-Edit.tif"), ("img_1234".to_string(), true));
+        assert_eq!(base_name("IMG_1234_HDR.dng"), ("img_1234".to_string(), true));
+        assert_eq!(base_name("IMG_1234-Enhanced-NR.dng"), ("img_1234".to_string(), true));
+        assert_eq!(base_name("IMG_1234-Edit-2.jpg"), ("img_1234".to_string(), true));
+    }
+    
+    #[test]
+    fn base_name_keeps_numbers_that_are_not_edits() {
+        // A trailing number only counts after a derivative suffix
+        assert_eq!(base_name("IMG_1234-2.jpg"), ("img_1234-2".to_string(), false));
+        assert_eq!(base_name("Beach 12.jpg"), ("beach 12".to_string(), false));
+        assert_eq!(base_name("Paradise Edit"), ("paradise".to_string(), true));
+    }
+}
@@ -0,0 +1,327 @@
+use anyhow::{Result, Context, anyhow};
+use glob::{MatchOptions, Pattern};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Name of the gitignore-style files read at every level of a photo tree
+pub const IGNORE_FILE_NAME: &str = ".zimignore";
+
+/// Always skipped: hidden files and folders, and NAS and Lightroom housekeeping
+const BUILTIN_RULES: &[&str] = &[".*", "@eaDir/", "\\#recycle/", "*.lrdata/"];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// For the extension part of a rule: cameras write `.NEF` and `.nef` alike
+const EXTENSION_MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    ..MATCH_OPTIONS
+};
+
+/// One line of a `.zimignore`, or an `--exclude` glob
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Pattern,
+    /// The pattern split at its last `.`, so the extension can match in any case
+    extension_split: Option<(Pattern, Pattern)>,
+    /// `!pattern`: un-ignore what an earlier rule ignored
+    negated: bool,
+    /// `pattern/`: only matches directories
+    dir_only: bool,
+    /// Contains a `/`, so it matches the path from `base` rather than any file name
+    anchored: bool,
+    /// Directory the rule is relative to, relative to the walk root
+    base: PathBuf,
+    /// Where the rule came from, for the exclusion report
+    source: String,
+}
+
+impl Rule {
+    /// Parse a gitignore-style line; `None` for blank lines and comments
+    fn parse(line: &str, base: &Path, source: String) -> Result<Option<Rule>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        let pattern = Pattern::new(line)
+            .map_err(|e| anyhow!("Invalid pattern '{}' ({}): {}", line, source, e))?;
+        let extension_split = split_extension(line)
+            .and_then(|(stem, extension)| Some((Pattern::new(stem).ok()?, Pattern::new(extension).ok()?)));
+        
+        Ok(Some(Rule { pattern, extension_split, negated, dir_only, anchored, base: base.to_path_buf(), source }))
+    }
+    
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(from_base) = relative.strip_prefix(&self.base) else {
+            return false;
+        };
+        if self.anchored {
+            self.matches_text(&to_slashes(from_base))
+        } else {
+            from_base.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| self.matches_text(name))
+        }
+    }
+    
+    /// Case-sensitive, except that `*.NEF` also matches `DSC_0001.nef`
+    fn matches_text(&self, text: &str) -> bool {
+        if self.pattern.matches_with(text, MATCH_OPTIONS) {
+            return true;
+        }
+        let (Some((stem_pattern, extension_pattern)), Some((stem, extension))) = (&self.extension_split, split_extension(text)) else {
+            return false;
+        };
+        stem_pattern.matches_with(stem, MATCH_OPTIONS) && extension_pattern.matches_with(extension, EXTENSION_MATCH_OPTIONS)
+    }
+}
+
+/// Split at the last `.` of the last path component: `2024/*.NEF` → (`2024/*`, `NEF`)
+fn split_extension(text: &str) -> Option<(&str, &str)> {
+    let dot = text.rfind('.')?;
+    let extension = &text[dot + 1..];
+    (!extension.is_empty() && !extension.contains('/')).then(|| (&text[..dot], extension))
+}
+
+fn to_slashes(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `--include` and `--exclude` globs given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct WalkFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+static WALK_FILTER: OnceLock<WalkFilter> = OnceLock::new();
+
+/// Use these globs for every walk in this run (call once, before walking)
+pub fn init(filter: WalkFilter) -> Result<()> {
+    // Check the patterns now, so a typo fails before any work starts
+    IgnoreRules::new(Path::new("."), &filter)?;
+    let _ = WALK_FILTER.set(filter);
+    Ok(())
+}
+
+/// Decides which paths under a photo root are skipped: the built-in rules, then
+/// `--exclude` globs, then the `.zimignore` files from the root down to the path,
+/// with the last matching rule winning as in git
+pub struct IgnoreRules {
+    root: PathBuf,
+    global: Vec<Rule>,
+    include: Vec<Rule>,
+    /// Rules of each directory's `.zimignore`, read the first time they're needed
+    per_directory: HashMap<PathBuf, Vec<Rule>>,
+}
+
+impl IgnoreRules {
+    fn new(root: &Path, filter: &WalkFilter) -> Result<Self> {
+        let mut global = Vec::new();
+        for line in BUILTIN_RULES {
+            global.extend(Rule::parse(line, Path::new(""), "built-in".to_string())?);
+        }
+        for glob in &filter.exclude {
+            global.extend(Rule::parse(glob, Path::new(""), format!("--exclude {}", glob))?);
+        }
+        let mut include = Vec::new();
+        for glob in &filter.include {
+            include.extend(Rule::parse(glob, Path::new(""), format!("--include {}", glob))?);
+        }
+        
+        Ok(IgnoreRules {
+            root: root.to_path_buf(),
+            global,
+            include,
+            per_directory: HashMap::new(),
+        })
+    }
+    
+    /// Rules for a walk of `root`, with the command line globs of this run
+    pub fn for_root(root: &Path) -> Result<Self> {
+        IgnoreRules::new(root, WALK_FILTER.get().unwrap_or(&WalkFilter::default()))
+    }
+    
+    /// The rule that excludes a path, if any
+    pub fn excluded_by(&mut self, path: &Path, is_dir: bool) -> Result<Option<String>> {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return Ok(None);
+        };
+        let relative = relative.to_path_buf();
+        if relative.as_os_str().is_empty() {
+            return Ok(None);
+        }
+        
+        // .zimignore files from the root down to the path's own directory
+        let mut directories: Vec<PathBuf> = relative.ancestors().skip(1).map(Path::to_path_buf).collect();
+        directories.reverse();
+        for directory in &directories {
+            self.load(directory)?;
+        }
+        
+        let mut verdict: Option<&Rule> = None;
+        for rule in &self.global {
+            if rule.matches(&relative, is_dir) {
+                verdict = Some(rule);
+            }
+        }
+        for directory in &directories {
+            for rule in &self.per_directory[directory] {
+                if rule.matches(&relative, is_dir) {
+                    verdict = Some(rule);
+                }
+            }
+        }
+        
+        Ok(verdict.filter(|rule| !rule.negated).map(|rule| rule.source.clone()))
+    }
+    
+    /// The rule that excludes a file on its own or through one of its folders, for
+    /// paths that didn't come from a walk
+    pub fn file_excluded_by(&mut self, path: &Path) -> Result<Option<String>> {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return Ok(None);
+        };
+        let mut directories: Vec<PathBuf> = relative.ancestors().skip(1)
+            .filter(|d| !d.as_os_str().is_empty())
+            .map(|d| self.root.join(d))
+            .collect();
+        directories.reverse();
+        for directory in directories {
+            if let Some(rule) = self.excluded_by(&directory, true)? {
+                return Ok(Some(rule));
+            }
+        }
+        
+        match self.excluded_by(path, false)? {
+            None if !self.included(path) => Ok(Some("not matched by --include".to_string())),
+            verdict => Ok(verdict),
+        }
+    }
+    
+    /// Whether a file matches one of the `--include` globs, if there are any
+    pub fn included(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.include.is_empty() || self.include.iter().any(|rule| rule.matches(relative, false))
+    }
+    
+    fn load(&mut self, directory: &Path) -> Result<()> {
+        if self.per_directory.contains_key(directory) {
+            return Ok(());
+        }
+        
+        let file = self.root.join(directory).join(IGNORE_FILE_NAME);
+        let mut rules = Vec::new();
+        if file.is_file() {
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            for (number, line) in content.lines().enumerate() {
+                let source = format!("{}:{}", file.display(), number + 1);
+                rules.extend(Rule::parse(line, directory, source)?);
+            }
+        }
+        self.per_directory.insert(directory.to_path_buf(), rules);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// A fresh photo tree with the given `.zimignore` files (relative path, contents)
+    fn tree(name: &str, ignore_files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("zim-photo-ignore-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in ignore_files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+    
+    #[test]
+    fn builtin_rules_skip_hidden_and_housekeeping() {
+        let root = tree("builtin", &[]);
+        let mut rules = IgnoreRules::new(&root, &WalkFilter::default()).unwrap();
+        assert_eq!(rules.excluded_by(&root.join(".git"), true).unwrap().as_deref(), Some("built-in"));
+        assert_eq!(rules.excluded_by(&root.join("2024/@eaDir"), true).unwrap().as_deref(), Some("built-in"));
+        assert_eq!(rules.excluded_by(&root.join("2024/IMG_0001.jpg"), false).unwrap(), None);
+    }
+    
+    #[test]
+    fn negation_unignores_and_the_last_rule_wins() {
+        let root = tree("negation", &[
+            (".zimignore", "*.jpg\n!keep.jpg\n"),
+            ("trips/.zimignore", "keep.jpg\n"),
+        ]);
+        let mut rules = IgnoreRules::new(&root, &WalkFilter::default()).unwrap();
+        assert!(rules.excluded_by(&root.join("a.jpg"), false).unwrap().is_some());
+        assert_eq!(rules.excluded_by(&root.join("keep.jpg"), false).unwrap(), None);
+        // The deeper file comes later, so it ignores keep.jpg again
+        assert!(rules.excluded_by(&root.join("trips/keep.jpg"), false).unwrap().is_some());
+        assert!(rules.excluded_by(&root.join("a.cr2"), false).unwrap().is_none());
+    }
+    
+    #[test]
+    fn dir_only_and_anchored_rules() {
+        let root = tree("anchored", &[(".zimignore", "exports/\n2024/drafts\n")]);
+        let mut rules = IgnoreRules::new(&root, &WalkFilter::default()).unwrap();
+        assert!(rules.excluded_by(&root.join("2024/exports"), true).unwrap().is_some());
+        assert!(rules.excluded_by(&root.join("exports"), false).unwrap().is_none());
+        assert!(rules.excluded_by(&root.join("2024/drafts"), true).unwrap().is_some());
+        assert!(rules.excluded_by(&root.join("2023/2024/drafts"), true).unwrap().is_none());
+    }
+    
+    #[test]
+    fn zimignore_can_unignore_an_exclude_glob() {
+        let root = tree("exclude", &[(".zimignore", "!*.png\n")]);
+        let filter = WalkFilter { include: Vec::new(), exclude: vec!["*.png".to_string()] };
+        let mut rules = IgnoreRules::new(&root, &filter).unwrap();
+        assert_eq!(rules.excluded_by(&root.join("a.png"), false).unwrap(), None);
+        
+        let root = tree("exclude-only", &[]);
+        let mut rules = IgnoreRules::new(&root, &filter).unwrap();
+        assert_eq!(rules.excluded_by(&root.join("a.png"), false).unwrap().as_deref(), Some("--exclude *.png"));
+    }
+    
+    #[test]
+    fn extensions_match_in_any_case_but_names_do_not() {
+        let root = tree("case", &[(".zimignore", "*.NEF
+drafts/*.jpg
+IMG_*
+")]);
+        let mut rules = IgnoreRules::new(&root, &WalkFilter::default()).unwrap();
+        assert!(rules.excluded_by(&root.join("DSC_0001.nef"), false).unwrap().is_some());
+        assert!(rules.excluded_by(&root.join("DSC_0001.NEF"), false).unwrap().is_some());
+        assert!(rules.excluded_by(&root.join("drafts/a.JPG"), false).unwrap().is_some());
+        assert!(rules.excluded_by(&root.join("Drafts/a.jpg"), false).unwrap().is_none());
+        assert!(rules.excluded_by(&root.join("img_0001.jpg"), false).unwrap().is_none());
+        // Hidden files are still matched by the built-in `.*`
+        assert!(rules.excluded_by(&root.join(".DS_Store"), false).unwrap().is_some());
+    }
+}
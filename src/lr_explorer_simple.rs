@@ -88,5 +88,28 @@ pub fn find_image_by_filename(conn: &Connection, filename: &str) -> Result<Optio
         Ok(())
     });
     
+    // Get the place name from the IPTC location fields (values are interned in lookup tables)
+    if let Ok(mut stmt) = conn.prepare("
+        SELECT loc.value, city.value, state.value, country.value
+        FROM AgHarvestedIptcMetadata m
+        LEFT JOIN AgInternedIptcLocation loc ON m.locationRef = loc.id_local
+        LEFT JOIN AgInternedIptcCity city ON m.cityRef = city.id_local
+        LEFT JOIN AgInternedIptcState state ON m.stateRef = state.id_local
+        LEFT JOIN AgInternedIptcCountry country ON m.countryRef = country.id_local
+        WHERE m.image = ?1
+    ") {
+        let _ = stmt.query_row(params![image_id], |row| {
+            let parts: Vec<String> = (0..4)
+                .filter_map(|i| row.get::<_, Option<String>>(i).ok().flatten())
+                .map(|part| part.trim().to_string())
+                .filter(|part| !part.is_empty())
+                .collect();
+            if !parts.is_empty() {
+                metadata.insert("lr_location".to_string(), parts.join(", "));
+            }
+            Ok(())
+        });
+    }
+    
     Ok(Some(metadata))
 }
\ No newline at end of file
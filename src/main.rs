@@ -1,5 +1,5 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 mod lr_explorer;
 mod lr_explorer_simple;
@@ -13,12 +13,51 @@ mod test_single_file;
 mod sidecar_writer;
 mod sidecar_reader;
 mod ollama_vision;
+mod raw_preview;
 mod find_good_test_day;
+mod state;
+mod ai_queue;
+mod retry;
+mod ai_pipeline;
+mod ai_cache;
+mod manifest;
+mod vision;
+mod openai_vision;
+mod config;
+mod file_types;
+mod groups;
+mod ignore_rules;
+mod vocabulary;
+mod embeddings;
+mod clip_onnx;
+mod ocr;
+mod quality;
+mod watch;
+mod relink;
+mod orphans;
+mod dupes;
+mod sequences;
 
 #[derive(Parser)]
 #[command(name = "zim-photo")]
 #[command(about = "Photo metadata management tool", long_about = None)]
 struct Cli {
+    /// Config file (default: ~/.config/zim-photo/config.yaml)
+    #[arg(long, global = true)]
+    config: Option<std::path::PathBuf>,
+    
+    /// Tag vocabulary file (default: ~/.config/zim-photo/vocabulary.yaml)
+    #[arg(long, global = true)]
+    vocabulary: Option<std::path::PathBuf>,
+    
+    /// Only process photos matching this glob (repeatable), e.g. "2024/**" or "*.NEF"
+    #[arg(long, global = true)]
+    include: Vec<String>,
+    
+    /// Skip files and folders matching this glob (repeatable), e.g. "exports" or "*.tmp"
+    #[arg(long, global = true)]
+    exclude: Vec<String>,
+    
     #[command(subcommand)]
     command: Commands,
 }
@@ -43,6 +82,14 @@ enum Commands {
         #[arg(short, long)]
         force: bool,
         
+        /// Number of photos to process in parallel (default: one per CPU)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        
+        /// One sidecar per RAW+JPEG pair and its edits, listing the other files
+        #[arg(short, long)]
+        group: bool,
+        
         /// Use AI (Ollama) to generate descriptions and tags
         #[arg(short, long)]
         ai: bool,
@@ -50,6 +97,230 @@ enum Commands {
         /// Minimum rating for AI analysis (1-5)
         #[arg(long, help = "Only use AI for photos with this rating or higher")]
         ai_min_rating: Option<i32>,
+        
+        /// Re-analyze photos whose AI data was generated before this date (YYYY-MM-DD)
+        #[arg(long, value_name = "DATE")]
+        ai_reanalyze_if_older_than: Option<chrono::NaiveDate>,
+        
+        /// Re-analyze photos whose AI data came from a different model or model build
+        #[arg(long)]
+        ai_model_changed: bool,
+        
+        /// Only analyze the N sharpest frames of each burst found by `sequences`
+        #[arg(long, value_name = "N")]
+        ai_sequence_top: Option<usize>,
+        
+        #[command(flatten)]
+        ai_args: AiArgs,
+    },
+    
+    /// Watch a folder and write sidecars for photos as they arrive
+    Watch {
+        /// Directory to watch
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Path to Lightroom catalog (used if it exists)
+        #[arg(short, long, default_value = "data/lr/lightroom_main.lrcat")]
+        catalog: String,
+        
+        /// Seconds a new file must stay unchanged before it is processed
+        #[arg(long, default_value_t = 2)]
+        settle: u64,
+        
+        /// Show progress while processing
+        #[arg(short, long)]
+        progress: bool,
+        
+        /// Number of photos to process in parallel (default: one per CPU)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        
+        /// One sidecar per RAW+JPEG pair and its edits, listing the other files
+        #[arg(short, long)]
+        group: bool,
+        
+        /// Use AI to generate descriptions and tags
+        #[arg(short, long)]
+        ai: bool,
+        
+        /// Minimum rating for AI analysis (1-5)
+        #[arg(long, help = "Only use AI for photos with this rating or higher")]
+        ai_min_rating: Option<i32>,
+        
+        #[command(flatten)]
+        ai_args: AiArgs,
+    },
+    
+    /// Move orphaned sidecars next to their photo after it was renamed or moved
+    Relink {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Show what would be moved without moving anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// List sidecars whose photo is gone, and archive or delete the untouched ones
+    Orphans {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Move sidecars without personal notes to .zim-photo/orphans/
+        #[arg(long, conflicts_with = "delete")]
+        archive: bool,
+        
+        /// Delete sidecars without personal notes
+        #[arg(long)]
+        delete: bool,
+    },
+    
+    /// Find duplicate and near-duplicate photos, and the copy with the richest sidecar
+    Dupes {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Only look for identical files, without comparing previews
+        #[arg(long)]
+        exact: bool,
+        
+        /// Largest perceptual hash difference (in bits, out of 64) for photos to count as near duplicates
+        #[arg(long, default_value_t = dupes::DEFAULT_MAX_DISTANCE)]
+        max_distance: u32,
+        
+        /// Show progress while hashing
+        #[arg(short, long)]
+        progress: bool,
+    },
+    
+    /// Find bursts and sequences and write `sequence:` with each frame's sharpness rank to the sidecars
+    Sequences {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Largest gap between frames of a sequence, in seconds
+        #[arg(long, default_value_t = sequences::DEFAULT_MAX_GAP)]
+        max_gap: f64,
+        
+        /// Largest perceptual hash difference (in bits, out of 64) between neighbouring frames
+        #[arg(long, default_value_t = sequences::DEFAULT_MAX_DISTANCE)]
+        max_distance: u32,
+        
+        /// Fewest frames that make a sequence
+        #[arg(long, default_value_t = sequences::DEFAULT_MIN_FRAMES)]
+        min_frames: usize,
+        
+        /// Show the sequences without writing them to the sidecars
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Show progress while processing
+        #[arg(short, long)]
+        progress: bool,
+    },
+    
+    /// Manage the persistent AI analysis queue
+    Ai {
+        #[command(subcommand)]
+        command: AiCommands,
+    },
+    
+    /// Compute embeddings for semantic search
+    Embed {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Show progress while processing
+        #[arg(short, long)]
+        progress: bool,
+        
+        /// Embed every photo again, even if its vector is up to date
+        #[arg(short, long)]
+        force: bool,
+        
+        /// Embed the sidecar text, or the image itself with a CLIP-style model (default: text)
+        #[arg(long, value_enum)]
+        embed_source: Option<embeddings::EmbeddingSource>,
+        
+        #[command(flatten)]
+        embed_args: EmbedArgs,
+    },
+    
+    /// Find photos by their sidecar text, or by meaning with --semantic
+    Search {
+        /// What to look for, e.g. "kids on a beach at dusk"
+        query: String,
+        
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Rank photos by embedding similarity instead of matching words
+        #[arg(long)]
+        semantic: bool,
+        
+        /// Maximum number of results
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        
+        #[command(flatten)]
+        embed_args: EmbedArgs,
+    },
+    
+    /// Read text (signs, menus, documents) in photos into the sidecars
+    Ocr {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Show progress while processing
+        #[arg(short, long)]
+        progress: bool,
+        
+        /// Read photos again even if they were read before
+        #[arg(short, long)]
+        force: bool,
+        
+        /// OCR engine
+        #[arg(long, value_enum, default_value_t = ocr::OcrEngine::Auto)]
+        engine: ocr::OcrEngine,
+        
+        /// Also read photos rated this or higher
+        #[arg(long)]
+        min_rating: Option<i32>,
+        
+        /// Read photos with any of these AI tags or keywords, comma separated
+        #[arg(long, value_delimiter = ',', default_values_t = ocr::DEFAULT_OCR_TAGS.iter().map(|t| t.to_string()))]
+        tags: Vec<String>,
+        
+        /// Read every photo, ignoring rating and tags
+        #[arg(long)]
+        all: bool,
+        
+        /// Tesseract language(s), e.g. eng+deu
+        #[arg(long)]
+        language: Option<String>,
+        
+        #[command(flatten)]
+        ai_args: AiArgs,
+    },
+    
+    /// Score sharpness, exposure and noise, and suggest ratings for unrated photos
+    Quality {
+        #[command(subcommand)]
+        command: QualityCommands,
+    },
+    
+    /// Clean up AI tags with the tag vocabulary
+    Tags {
+        #[command(subcommand)]
+        command: TagsCommands,
     },
     
     /// Test the metadata pipeline
@@ -67,26 +338,371 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum AiCommands {
+    /// Continue AI analysis where an earlier run stopped
+    Resume {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Path to Lightroom catalog
+        #[arg(short, long, default_value = "data/lr/lightroom_main.lrcat")]
+        catalog: String,
+        
+        /// Show progress while processing
+        #[arg(short, long)]
+        progress: bool,
+        
+        /// One sidecar per RAW+JPEG pair and its edits, as with `update --group`
+        #[arg(short, long)]
+        group: bool,
+        
+        #[command(flatten)]
+        ai_args: AiArgs,
+        
+        /// Give up on a photo after this many failed attempts
+        #[arg(long, default_value_t = sidecar_writer::DEFAULT_MAX_ATTEMPTS)]
+        max_attempts: i64,
+    },
+    
+    /// Show pending, finished and failed AI jobs
+    Status {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagsCommands {
+    /// Re-apply the vocabulary to the AI tags of existing sidecars
+    Normalize {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Show what would change without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// List AI tags that aren't in the vocabulary yet
+    Review {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum QualityCommands {
+    /// Measure photo quality and write `quality:` with a suggested rating to the sidecars
+    Assess {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Show progress while processing
+        #[arg(short, long)]
+        progress: bool,
+        
+        /// Assess photos again even if they were assessed before
+        #[arg(short, long)]
+        force: bool,
+        
+        /// Also ask the vision model for an aesthetic score
+        #[arg(long)]
+        aesthetic: bool,
+        
+        /// Only assess the N sharpest frames of each burst found by `sequences`
+        #[arg(long, value_name = "N")]
+        sequence_top: Option<usize>,
+        
+        #[command(flatten)]
+        ai_args: AiArgs,
+    },
+    
+    /// Use the suggested ratings as ratings for photos that have none
+    Accept {
+        /// Directory containing photos
+        #[arg(default_value = ".")]
+        directory: String,
+        
+        /// Only accept suggestions of this rating or higher
+        #[arg(long)]
+        min_rating: Option<i64>,
+        
+        /// List the ratings that would be accepted without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// AI settings shared by every command that runs the vision model.
+/// Anything not given here falls back to the `vision:` section of the config file.
+#[derive(Args)]
+struct AiArgs {
+    /// Kind of server that runs the vision model (default: ollama)
+    #[arg(long, value_enum)]
+    ai_backend: Option<vision::BackendKind>,
+    
+    /// Vision model to use for AI analysis (default: qwen2.5vl)
+    #[arg(long)]
+    ai_model: Option<String>,
+    
+    /// Pull the AI model if it is not installed yet
+    #[arg(long)]
+    ai_pull: bool,
+    
+    /// Server to send images to; repeat to spread requests across several hosts
+    #[arg(long = "ai-endpoint")]
+    ai_endpoints: Vec<String>,
+    
+    /// Number of AI requests to keep in flight at once (default: 1)
+    #[arg(long)]
+    ai_workers: Option<usize>,
+    
+    /// Retries per photo when the server is unreachable, times out or returns a server error
+    #[arg(long, default_value_t = 3)]
+    ai_retries: u32,
+    
+    /// Seconds to wait before the first retry (doubles on each further retry)
+    #[arg(long, default_value_t = 5)]
+    ai_retry_delay: u64,
+    
+    /// Metadata to include in the prompt, comma separated (default: none)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    ai_context: Option<Vec<vision::ContextField>>,
+    
+    /// Analyze every photo, even ones whose content is in the AI result cache
+    #[arg(long)]
+    no_ai_cache: bool,
+}
+
+impl AiArgs {
+    /// Check the model up front and build the options for a run, exiting on failure
+    fn into_options(
+        self,
+        config: &config::VisionConfig,
+        vocabulary: Option<vocabulary::Vocabulary>,
+        min_rating: Option<i32>,
+        max_attempts: i64,
+    ) -> sidecar_writer::AiOptions {
+        let kind = self.ai_backend.unwrap_or(config.backend);
+        let model = self.ai_model
+            .or_else(|| config.model.clone())
+            .unwrap_or_else(|| kind.default_model().to_string());
+        let endpoints = if !self.ai_endpoints.is_empty() {
+            self.ai_endpoints
+        } else if !config.endpoints.is_empty() {
+            config.endpoints.clone()
+        } else {
+            vec![kind.default_endpoint().to_string()]
+        };
+        let api_key = config.api_key.clone().or_else(|| std::env::var("OPENAI_API_KEY").ok());
+        
+        let backends = vision::create_backends(kind, &model, &endpoints, api_key.as_deref());
+        
+        // Fail fast if the server is down or the model is missing, rather than per image
+        for backend in &backends {
+            if let Err(e) = backend.check(self.ai_pull) {
+                println!("❌ {}", e);
+                std::process::exit(1);
+            }
+        }
+        
+        sidecar_writer::AiOptions {
+            backends,
+            workers: self.ai_workers.or(config.workers).unwrap_or(1),
+            min_rating,
+            max_attempts,
+            retry: retry::RetryPolicy {
+                max_retries: self.ai_retries,
+                initial_delay: std::time::Duration::from_secs(self.ai_retry_delay),
+                ..Default::default()
+            },
+            reanalyze: sidecar_writer::ReanalyzePolicy::default(),
+            sequence_top: None,
+            context_fields: self.ai_context
+                .or_else(|| config.context.clone())
+                .unwrap_or_default(),
+            vocabulary,
+            use_cache: !self.no_ai_cache,
+        }
+    }
+}
+
+/// Embedding server settings shared by `embed` and `search`.
+/// Anything not given here falls back to the `embedding:` section of the config file.
+#[derive(Args)]
+struct EmbedArgs {
+    /// Kind of server that computes embeddings, or `onnx` for a local CLIP model (default: ollama)
+    #[arg(long, value_enum)]
+    embed_backend: Option<embeddings::EmbeddingBackend>,
+    
+    /// Embedding model (default: nomic-embed-text), or the model directory for `onnx`
+    #[arg(long)]
+    embed_model: Option<String>,
+    
+    /// Embedding server (default: the AI backend's default endpoint)
+    #[arg(long)]
+    embed_endpoint: Option<String>,
+}
+
+impl EmbedArgs {
+    /// Build the embedder; `index_model` is what an existing index was built with,
+    /// preferred over the config file so searches match the stored vectors
+    fn into_embedder(self, config: &config::EmbeddingConfig, index_model: Option<String>) -> Box<dyn embeddings::Embedder> {
+        let kind = self.embed_backend.unwrap_or(config.backend);
+        let model = self.embed_model
+            .or(index_model)
+            .or_else(|| config.model.clone())
+            .unwrap_or_else(|| match kind {
+                embeddings::EmbeddingBackend::Fake => "fake".to_string(),
+                _ => embeddings::DEFAULT_MODEL.to_string(),
+            });
+        let endpoint = self.embed_endpoint
+            .or_else(|| config.endpoint.clone())
+            .unwrap_or_else(|| kind.default_endpoint().to_string());
+        let api_key = config.api_key.clone().or_else(|| std::env::var("OPENAI_API_KEY").ok());
+        
+        let embedder = embeddings::create_embedder(kind, &model, &endpoint, api_key.as_deref())
+            .and_then(|embedder| embedder.check().map(|()| embedder));
+        match embedder {
+            Ok(embedder) => embedder,
+            Err(e) => {
+                println!("❌ {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let config = config::Config::load(cli.config.as_deref())?;
+    file_types::init(file_types::FileTypeRegistry::with_config(&config.file_types));
+    ignore_rules::init(ignore_rules::WalkFilter { include: cli.include, exclude: cli.exclude })?;
+    let vocabulary = vocabulary::Vocabulary::load(cli.vocabulary.as_deref().or(config.vocabulary.as_deref()))?;
     let catalog_path = "data/lr/lightroom_main.lrcat";
     
     match cli.command {
-        Commands::Update { directory, catalog, progress, force, ai, ai_min_rating } => {
-            if ai {
-                // Check if Ollama is available
-                if !ollama_vision::check_ollama_available()? {
-                    println!("⚠️  Warning: Ollama is not running or qwen2.5vl is not available");
-                    println!("   Make sure Ollama is running: ollama serve");
-                    println!("   And the model is pulled: ollama pull qwen2.5vl");
-                    return Ok(());
-                }
+        Commands::Update {
+            directory, catalog, progress, force, jobs, group, ai, ai_min_rating,
+            ai_reanalyze_if_older_than, ai_model_changed, ai_sequence_top, ai_args,
+        } => {
+            let ai_options = if ai {
                 if let Some(rating) = ai_min_rating {
                     println!("🎯 AI analysis enabled for photos with rating ≥ {}", rating);
                 }
+                let mut options = ai_args.into_options(&config.vision, vocabulary, ai_min_rating, sidecar_writer::DEFAULT_MAX_ATTEMPTS);
+                options.reanalyze = sidecar_writer::ReanalyzePolicy {
+                    older_than: ai_reanalyze_if_older_than,
+                    model_changed: ai_model_changed,
+                };
+                options.sequence_top = ai_sequence_top;
+                Some(options)
+            } else {
+                None
+            };
+            let group = group || config.group_companions;
+            sidecar_writer::process_directory(&directory, &catalog, !force, progress, jobs, group, ai_options.as_ref())?;
+        }
+        Commands::Watch { directory, catalog, settle, progress, jobs, group, ai, ai_min_rating, ai_args } => {
+            let ai_options = ai.then(|| {
+                ai_args.into_options(&config.vision, vocabulary, ai_min_rating, sidecar_writer::DEFAULT_MAX_ATTEMPTS)
+            });
+            let settle = std::time::Duration::from_secs(settle);
+            let group = group || config.group_companions;
+            watch::watch_directory(&directory, &catalog, settle, progress, jobs, group, ai_options.as_ref())?;
+        }
+        Commands::Relink { directory, dry_run } => {
+            relink::relink_sidecars(&directory, dry_run)?;
+        }
+        Commands::Orphans { directory, archive, delete } => {
+            let action = match (archive, delete) {
+                (true, _) => orphans::OrphanAction::Archive,
+                (_, true) => orphans::OrphanAction::Delete,
+                _ => orphans::OrphanAction::List,
+            };
+            orphans::clean_orphans(&directory, action)?;
+        }
+        Commands::Dupes { directory, exact, max_distance, progress } => {
+            dupes::find_duplicates(&directory, exact, max_distance, progress)?;
+        }
+        Commands::Sequences { directory, max_gap, max_distance, min_frames, dry_run, progress } => {
+            sequences::detect_sequences(&directory, max_gap, max_distance, min_frames.max(2), dry_run, progress)?;
+        }
+        Commands::Ai { command } => match command {
+            AiCommands::Resume { directory, catalog, progress, group, ai_args, max_attempts } => {
+                let ai_options = ai_args.into_options(&config.vision, vocabulary, None, max_attempts);
+                let group = group || config.group_companions;
+                sidecar_writer::resume_ai_queue(&directory, &catalog, progress, group, &ai_options)?;
+            }
+            AiCommands::Status { directory } => {
+                ai_queue::print_status(&directory)?;
+            }
+        },
+        Commands::Embed { directory, progress, force, embed_source, embed_args } => {
+            let embedder = embed_args.into_embedder(&config.embedding, None);
+            let source = embed_source.unwrap_or(config.embedding.source);
+            embeddings::embed_directory(&directory, embedder.as_ref(), source, force, progress)?;
+        }
+        Commands::Search { query, directory, semantic, limit, embed_args } => {
+            if semantic {
+                let index_model = embeddings::EmbeddingIndex::open(&directory)?.model()?.map(|(model, _)| model);
+                let embedder = embed_args.into_embedder(&config.embedding, index_model);
+                embeddings::semantic_search(&directory, &query, embedder.as_ref(), limit)?;
+            } else {
+                embeddings::text_search(&directory, &query, limit)?;
             }
-            sidecar_writer::process_directory(&directory, &catalog, !force, progress, ai, ai_min_rating)?;
         }
+        Commands::Ocr { directory, progress, force, engine, min_rating, tags, all, language, ai_args } => {
+            let use_tesseract = match engine {
+                ocr::OcrEngine::Tesseract => true,
+                ocr::OcrEngine::Model => false,
+                ocr::OcrEngine::Auto => ocr::tesseract_available(),
+            };
+            if engine == ocr::OcrEngine::Tesseract && !ocr::tesseract_available() {
+                println!("❌ tesseract is not installed (try --engine model to use the vision model)");
+                std::process::exit(1);
+            }
+            let backend = if use_tesseract {
+                None
+            } else {
+                let options = ai_args.into_options(&config.vision, None, None, sidecar_writer::DEFAULT_MAX_ATTEMPTS);
+                options.backends.into_iter().next()
+            };
+            let options = ocr::OcrOptions { backend, language, min_rating, tags, all, force };
+            ocr::ocr_directory(&directory, &options, progress)?;
+        }
+        Commands::Quality { command } => match command {
+            QualityCommands::Assess { directory, progress, force, aesthetic, sequence_top, ai_args } => {
+                let backend = if aesthetic {
+                    let options = ai_args.into_options(&config.vision, None, None, sidecar_writer::DEFAULT_MAX_ATTEMPTS);
+                    options.backends.into_iter().next()
+                } else {
+                    None
+                };
+                quality::assess_directory(&directory, backend, force, sequence_top, progress)?;
+            }
+            QualityCommands::Accept { directory, min_rating, dry_run } => {
+                quality::accept_suggestions(&directory, min_rating, dry_run)?;
+            }
+        },
+        Commands::Tags { command } => match command {
+            TagsCommands::Normalize { directory, dry_run } => {
+                let Some(vocabulary) = vocabulary else {
+                    println!("❌ No tag vocabulary found. Create ~/.config/zim-photo/vocabulary.yaml or pass --vocabulary");
+                    std::process::exit(1);
+                };
+                vocabulary::normalize_sidecars(&directory, &vocabulary, dry_run)?;
+            }
+            TagsCommands::Review { directory } => {
+                vocabulary::print_review(&directory, vocabulary.as_ref())?;
+            }
+        },
         Commands::Test { directory } => {
             if std::path::Path::new(&directory).exists() {
                 test_pipeline::test_pipeline(&directory, catalog_path)?;
@@ -0,0 +1,190 @@
+use anyhow::{Result, Context};
+use chrono::Local;
+use rusqlite::{Connection, OptionalExtension, params};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use crate::ai_cache::content_hash;
+use crate::state::{state_dir, relative_key};
+
+const MANIFEST_DB_NAME: &str = "manifest.db";
+
+/// What changed since a photo's sidecar was last written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManifestCheck {
+    /// The photo file's content is different
+    pub source_changed: bool,
+    /// The sidecar no longer matches what was written: someone edited it by hand
+    pub sidecar_edited: bool,
+}
+
+/// Size, modification time and content hash of every photo as of its last sidecar
+/// write, with the hash of that sidecar, stored in `.zim-photo/manifest.db`, along
+/// with the perceptual hashes `dupes` computed, by content hash.
+/// Shared between update workers, hence the lock around the connection.
+pub struct Manifest {
+    conn: Mutex<Connection>,
+    root: PathBuf,
+}
+
+impl Manifest {
+    pub fn open<P: AsRef<Path>>(photo_root: P) -> Result<Self> {
+        let root = photo_root.as_ref().to_path_buf();
+        let db_path = state_dir(&root)?.join(MANIFEST_DB_NAME);
+        let conn = Connection::open(&db_path)
+            .with_context(|| format!("Failed to open manifest: {}", db_path.display()))?;
+        
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS photos (
+                path TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                mtime_ns INTEGER NOT NULL,
+                content_hash TEXT NOT NULL,
+                sidecar_hash TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS perceptual_hashes (
+                content_hash TEXT PRIMARY KEY,
+                dhash INTEGER NOT NULL
+            );
+        ")?;
+        
+        Ok(Manifest { conn: Mutex::new(conn), root })
+    }
+    
+    /// Compare a photo and its sidecar with the manifest; `None` if the photo isn't in it.
+    /// The photo is only hashed when its size or modification time changed.
+    pub fn check(&self, photo_path: &Path, sidecar_path: &Path) -> Result<Option<ManifestCheck>> {
+        let key = relative_key(&self.root, photo_path);
+        let entry: Option<(i64, i64, String, String)> = self.conn.lock().unwrap().query_row(
+            "SELECT size, mtime_ns, content_hash, sidecar_hash FROM photos WHERE path = ?1",
+            params![key],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        ).optional()?;
+        let Some((size, mtime_ns, hash, sidecar_hash)) = entry else {
+            return Ok(None);
+        };
+        
+        let (current_size, current_mtime) = file_stamp(photo_path)?;
+        let source_changed = if (current_size, current_mtime) == (size, mtime_ns) {
+            false
+        } else if content_hash(photo_path)? == hash {
+            // Touched or copied without changing: remember the new stamp
+            self.conn.lock().unwrap().execute(
+                "UPDATE photos SET size = ?2, mtime_ns = ?3 WHERE path = ?1",
+                params![key, current_size, current_mtime],
+            )?;
+            false
+        } else {
+            true
+        };
+        let sidecar_edited = content_hash(sidecar_path)? != sidecar_hash;
+        
+        Ok(Some(ManifestCheck { source_changed, sidecar_edited }))
+    }
+    
+    /// Size and content hash of a photo when its sidecar was last written
+    pub fn recorded(&self, photo_path: &Path) -> Result<Option<(i64, String)>> {
+        Ok(self.conn.lock().unwrap().query_row(
+            "SELECT size, content_hash FROM photos WHERE path = ?1",
+            params![relative_key(&self.root, photo_path)],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?)
+    }
+    
+    /// A photo's content hash: the recorded one if the photo's size and modification
+    /// time are unchanged, otherwise computed
+    pub fn content_hash(&self, photo_path: &Path) -> Result<String> {
+        let entry: Option<(i64, i64, String)> = self.conn.lock().unwrap().query_row(
+            "SELECT size, mtime_ns, content_hash FROM photos WHERE path = ?1",
+            params![relative_key(&self.root, photo_path)],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).optional()?;
+        match entry {
+            Some((size, mtime_ns, hash)) if file_stamp(photo_path)? == (size, mtime_ns) => Ok(hash),
+            _ => content_hash(photo_path),
+        }
+    }
+    
+    /// The perceptual hash stored for content with this hash
+    pub fn perceptual_hash(&self, content_hash: &str) -> Result<Option<u64>> {
+        let dhash: Option<i64> = self.conn.lock().unwrap().query_row(
+            "SELECT dhash FROM perceptual_hashes WHERE content_hash = ?1",
+            params![content_hash],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(dhash.map(|d| d as u64))
+    }
+    
+    pub fn record_perceptual_hash(&self, content_hash: &str, dhash: u64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO perceptual_hashes (content_hash, dhash) VALUES (?1, ?2)",
+            params![content_hash, dhash as i64],
+        )?;
+        Ok(())
+    }
+    
+    /// Record a photo and the sidecar just written for it
+    pub fn record(&self, photo_path: &Path, sidecar_path: &Path) -> Result<()> {
+        let (size, mtime_ns) = file_stamp(photo_path)?;
+        let hash = content_hash(photo_path)?;
+        let sidecar_hash = content_hash(sidecar_path)?;
+        self.conn.lock().unwrap().execute("
+            INSERT OR REPLACE INTO photos (path, size, mtime_ns, content_hash, sidecar_hash, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ", params![relative_key(&self.root, photo_path), size, mtime_ns, hash, sidecar_hash, now()])?;
+        Ok(())
+    }
+    
+    /// Follow a photo, or a folder of photos, that was renamed or moved
+    pub fn moved(&self, from: &Path, to: &Path) -> Result<()> {
+        let (from, to) = (relative_key(&self.root, from), relative_key(&self.root, to));
+        let conn = self.conn.lock().unwrap();
+        conn.execute("UPDATE OR REPLACE photos SET path = ?2 WHERE path = ?1", params![from, to])?;
+        conn.execute("
+            UPDATE OR REPLACE photos SET path = ?2 || substr(path, length(?1) + 1)
+            WHERE substr(path, 1, length(?1) + 1) = ?1 || '/'
+        ", params![from, to])?;
+        Ok(())
+    }
+    
+    /// Drop a photo whose sidecar was removed
+    pub fn forget(&self, photo_path: &Path) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM photos WHERE path = ?1",
+            params![relative_key(&self.root, photo_path)],
+        )?;
+        Ok(())
+    }
+    
+    /// Follow an in-place edit of a sidecar by one of our own commands. A sidecar that
+    /// had already been edited by hand stays marked as such.
+    pub fn sidecar_edited(&self, photo_path: &Path, before: &str, after: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute("
+            UPDATE photos SET sidecar_hash = ?3, updated_at = ?4
+            WHERE path = ?1 AND sidecar_hash = ?2
+        ", params![relative_key(&self.root, photo_path), text_hash(before), text_hash(after), now()])?;
+        Ok(())
+    }
+}
+
+/// File size and modification time in nanoseconds, the cheap test for a changed photo
+fn file_stamp(path: &Path) -> Result<(i64, i64)> {
+    let metadata = fs::metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let mtime_ns = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as i64);
+    Ok((metadata.len() as i64, mtime_ns))
+}
+
+/// Same hash as `content_hash`, for content already in memory
+fn text_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+fn now() -> String {
+    Local::now().format("%Y-%m-%dT%H:%M:%S").to_string()
+}
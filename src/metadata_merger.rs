@@ -4,15 +4,24 @@ use rusqlite::Connection;
 use crate::lr_explorer_simple::find_image_by_filename;
 use crate::exif_reader::read_exif;
 use crate::photo_walker::PhotoFile;
-use crate::ollama_vision::{analyze_image, VisionAnalysis};
+use crate::ollama_vision::{analyze_image, DEFAULT_MODEL};
+use crate::ocr::OcrResult;
+use crate::quality::QualityAssessment;
+use crate::sequences::SequenceInfo;
+use crate::vision::{ContextField, PhotoContext, VisionAnalysis};
 
 /// Combined metadata from all sources
 #[derive(Debug)]
 pub struct PhotoMetadata {
     pub filename: String,
+    /// Other files of the photo's group (RAW+JPEG pair, edits), when grouping
+    pub companions: Vec<String>,
     pub exif_data: HashMap<String, String>,
     pub lightroom_data: HashMap<String, String>,
     pub ai_analysis: Option<VisionAnalysis>,
+    pub ocr: Option<OcrResult>,
+    pub quality: Option<QualityAssessment>,
+    pub sequence: Option<SequenceInfo>,
     pub merged_data: HashMap<String, String>,
 }
 
@@ -20,9 +29,13 @@ impl PhotoMetadata {
     pub fn new(filename: String) -> Self {
         PhotoMetadata {
             filename,
+            companions: Vec::new(),
             exif_data: HashMap::new(),
             lightroom_data: HashMap::new(),
             ai_analysis: None,
+            ocr: None,
+            quality: None,
+            sequence: None,
             merged_data: HashMap::new(),
         }
     }
@@ -52,6 +65,9 @@ impl PhotoMetadata {
                 "lr_title" => {
                     self.merged_data.insert("title".to_string(), value.clone());
                 },
+                "lr_location" => {
+                    self.merged_data.insert("location".to_string(), value.clone());
+                },
                 "lr_color_label" => {
                     if !value.is_empty() {
                         self.merged_data.insert("color_label".to_string(), value.clone());
@@ -80,8 +96,63 @@ impl PhotoMetadata {
             if !ai.tags.is_empty() {
                 self.merged_data.insert("ai_tags".to_string(), ai.tags.join(", "));
             }
-        } else {
-            println!("  ❌ No AI analysis to save");
+            
+            if !ai.subjects.is_empty() {
+                self.merged_data.insert("ai_subjects".to_string(), ai.subjects.join(", "));
+            }
+            if let Some(ref setting) = ai.setting {
+                self.merged_data.insert("ai_setting".to_string(), setting.clone());
+            }
+            if let Some(ref mood) = ai.mood {
+                self.merged_data.insert("ai_mood".to_string(), mood.clone());
+            }
+            if !ai.dominant_colors.is_empty() {
+                self.merged_data.insert("ai_colors".to_string(), ai.dominant_colors.join(", "));
+            }
+            if let Some(ref text) = ai.text_in_image {
+                self.merged_data.insert("ai_text_in_image".to_string(), text.clone());
+            }
+            
+            // How the AI fields were produced
+            let provenance = &ai.provenance;
+            if !provenance.backend.is_empty() {
+                self.merged_data.insert("ai_backend".to_string(), provenance.backend.clone());
+            }
+            if !provenance.model.is_empty() {
+                self.merged_data.insert("ai_model".to_string(), provenance.model.clone());
+            }
+            if let Some(ref digest) = provenance.model_digest {
+                self.merged_data.insert("ai_model_digest".to_string(), digest.clone());
+            }
+            if let Some(ref version) = provenance.prompt_version {
+                self.merged_data.insert("ai_prompt_version".to_string(), version.clone());
+            }
+            if let Some(ref generated) = provenance.generated {
+                self.merged_data.insert("ai_generated".to_string(), generated.clone());
+            }
+            if let Some(ref source) = provenance.source_image {
+                self.merged_data.insert("ai_source_image".to_string(), source.clone());
+            }
+            if let Some(duration) = provenance.duration_secs {
+                self.merged_data.insert("ai_duration_secs".to_string(), duration.to_string());
+            }
+        }
+        
+        // Text read by the OCR pass
+        if let Some(ref ocr) = self.ocr {
+            if let Some(ref text) = ocr.text {
+                self.merged_data.insert("text_in_image".to_string(), text.clone());
+            }
+            self.merged_data.insert("ocr_engine".to_string(), ocr.engine.clone());
+            self.merged_data.insert("ocr_generated".to_string(), ocr.generated.clone());
+        }
+        
+        // An accepted quality suggestion stands in for a rating Lightroom doesn't have
+        if let Some(ref quality) = self.quality
+            && quality.accepted
+            && !self.merged_data.contains_key("rating")
+        {
+            self.merged_data.insert("rating".to_string(), quality.suggested_rating.to_string());
         }
         
         // Add source info
@@ -100,8 +171,55 @@ impl PhotoMetadata {
         }
     }
     
+    /// Build the context for the vision prompt from the merged metadata, limited to `fields`
+    pub fn ai_context(&self, fields: &[ContextField]) -> PhotoContext {
+        let get = |key: &str| self.merged_data.get(key)
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        let mut context = PhotoContext {
+            filename: self.filename.clone(),
+            ..Default::default()
+        };
+        
+        for field in fields {
+            match field {
+                ContextField::Date => context.captured = get("captured"),
+                ContextField::Camera => {
+                    context.camera = match (get("camera_make"), get("camera_model")) {
+                        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+                        (make, model) => model.or(make),
+                    };
+                    context.lens = get("lens");
+                }
+                ContextField::Keywords => {
+                    context.keywords = get("keywords")
+                        .map(|v| v.split(", ").map(str::to_string).collect())
+                        .unwrap_or_default();
+                }
+                ContextField::Title => {
+                    context.title = get("title");
+                    context.caption = get("caption");
+                }
+                ContextField::Location => context.location = get("location"),
+                ContextField::Gps => {
+                    let latitude = get("gps_latitude").and_then(|v| v.parse::<f64>().ok());
+                    let longitude = get("gps_longitude").and_then(|v| v.parse::<f64>().ok());
+                    context.gps = latitude.zip(longitude);
+                }
+            }
+        }
+        
+        context
+    }
+    
     /// Generate YAML frontmatter
     pub fn to_yaml_frontmatter(&self) -> Result<String> {
+        let yaml = serde_yaml::to_string(&self.frontmatter())?;
+        Ok(format!("---\n{}---\n", yaml))
+    }
+    
+    /// The frontmatter fields, in the order they are written
+    pub fn frontmatter(&self) -> serde_yaml::Mapping {
         // Order matters for readability
         let mut ordered_data = serde_yaml::Mapping::new();
         
@@ -109,6 +227,9 @@ impl PhotoMetadata {
         if let Some(v) = self.merged_data.get("filename") {
             ordered_data.insert("filename".into(), v.clone().into());
         }
+        if !self.companions.is_empty() {
+            ordered_data.insert("companions".into(), self.companions.clone().into());
+        }
         
         // Capture info
         if let Some(v) = self.merged_data.get("captured") {
@@ -122,6 +243,10 @@ impl PhotoMetadata {
             }
         }
         
+        if let Some(v) = self.merged_data.get("camera_serial") {
+            ordered_data.insert("camera_serial".into(), v.clone().into());
+        }
+        
         if let Some(v) = self.merged_data.get("lens") {
             ordered_data.insert("lens".into(), v.clone().into());
         }
@@ -169,6 +294,10 @@ impl PhotoMetadata {
             ordered_data.insert("caption".into(), v.clone().into());
         }
         
+        if let Some(v) = self.merged_data.get("location") {
+            ordered_data.insert("location".into(), v.clone().into());
+        }
+        
         // AI generated content (kept separate)
         if let Some(v) = self.merged_data.get("ai_description") {
             ordered_data.insert("ai_description".into(), v.clone().into());
@@ -179,6 +308,79 @@ impl PhotoMetadata {
             ordered_data.insert("ai_tags".into(), ai_tags.into());
         }
         
+        if let Some(v) = self.merged_data.get("ai_subjects") {
+            let subjects: Vec<_> = v.split(", ").collect();
+            ordered_data.insert("ai_subjects".into(), subjects.into());
+        }
+        
+        if let Some(v) = self.merged_data.get("ai_setting") {
+            ordered_data.insert("ai_setting".into(), v.clone().into());
+        }
+        
+        if let Some(v) = self.merged_data.get("ai_mood") {
+            ordered_data.insert("ai_mood".into(), v.clone().into());
+        }
+        
+        if let Some(v) = self.merged_data.get("ai_colors") {
+            let colors: Vec<_> = v.split(", ").collect();
+            ordered_data.insert("ai_colors".into(), colors.into());
+        }
+        
+        if let Some(v) = self.merged_data.get("ai_text_in_image") {
+            ordered_data.insert("ai_text_in_image".into(), v.clone().into());
+        }
+        
+        let mut ai = serde_yaml::Mapping::new();
+        if let Some(v) = self.merged_data.get("ai_backend") {
+            ai.insert("backend".into(), v.clone().into());
+        }
+        if let Some(v) = self.merged_data.get("ai_model") {
+            ai.insert("model".into(), v.clone().into());
+        }
+        if let Some(v) = self.merged_data.get("ai_model_digest") {
+            ai.insert("model_digest".into(), v.clone().into());
+        }
+        if let Some(v) = self.merged_data.get("ai_prompt_version") {
+            ai.insert("prompt_version".into(), v.clone().into());
+        }
+        if let Some(v) = self.merged_data.get("ai_generated") {
+            ai.insert("generated".into(), v.clone().into());
+        }
+        if let Some(v) = self.merged_data.get("ai_source_image") {
+            ai.insert("source_image".into(), v.clone().into());
+        }
+        if let Some(v) = self.merged_data.get("ai_duration_secs")
+            && let Ok(secs) = v.parse::<f64>()
+        {
+            ai.insert("duration_secs".into(), secs.into());
+        }
+        
+        if !ai.is_empty() {
+            ordered_data.insert("ai".into(), ai.into());
+        }
+        
+        // OCR
+        if let Some(v) = self.merged_data.get("text_in_image") {
+            ordered_data.insert("text_in_image".into(), v.clone().into());
+        }
+        
+        if let (Some(engine), Some(generated)) = (self.merged_data.get("ocr_engine"), self.merged_data.get("ocr_generated")) {
+            let mut ocr = serde_yaml::Mapping::new();
+            ocr.insert("engine".into(), engine.clone().into());
+            ocr.insert("generated".into(), generated.clone().into());
+            ordered_data.insert("ocr".into(), ocr.into());
+        }
+        
+        // Quality
+        if let Some(ref quality) = self.quality {
+            ordered_data.insert("quality".into(), quality.to_yaml().into());
+        }
+        
+        // Burst or sequence
+        if let Some(ref sequence) = self.sequence {
+            ordered_data.insert("sequence".into(), sequence.to_yaml().into());
+        }
+        
         // GPS
         if let Some(lat) = self.merged_data.get("gps_latitude") {
             if let Some(lon) = self.merged_data.get("gps_longitude") {
@@ -199,8 +401,7 @@ impl PhotoMetadata {
             ordered_data.insert("_metadata_sources".into(), v.clone().into());
         }
         
-        let yaml = serde_yaml::to_string(&ordered_data)?;
-        Ok(format!("---\n{}---\n", yaml))
+        ordered_data
     }
 }
 
@@ -253,7 +454,7 @@ pub fn extract_metadata_verbose(photo: &PhotoFile, lr_conn: Option<&Connection>,
     // Try AI analysis if requested
     if use_ai {
         println!("  🤖 Starting AI analysis for: {}", photo.filename);
-        match analyze_image(&photo.path) {
+        match analyze_image(&photo.path, DEFAULT_MODEL) {
             Ok(analysis) => {
                 metadata.ai_analysis = Some(analysis);
                 if verbose {
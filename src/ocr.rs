@@ -0,0 +1,269 @@
+use anyhow::{Result, Context, anyhow};
+use chrono::Local;
+use clap::ValueEnum;
+use serde_yaml::{Mapping, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Instant;
+use crate::ai_queue::{install_interrupt_handler, interrupted};
+use crate::ollama_vision::convert_to_jpeg;
+use crate::photo_walker::PhotoWalker;
+use crate::retry::RetryPolicy;
+use crate::vision::{PhotoContext, VisionBackend};
+
+/// AI tags and keywords that suggest a photo has text worth reading
+pub const DEFAULT_OCR_TAGS: &[&str] = &[
+    "sign", "street sign", "text", "document", "menu", "whiteboard", "poster", "book", "screen",
+];
+
+/// Which engine reads the text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OcrEngine {
+    /// Tesseract if it is installed, otherwise the vision model
+    #[default]
+    Auto,
+    /// The vision model, with a dedicated transcription prompt
+    Model,
+    /// The local `tesseract` command
+    Tesseract,
+}
+
+/// Result of the OCR pass, as stored in the sidecar
+#[derive(Debug, Clone, Default)]
+pub struct OcrResult {
+    /// The text found, if any
+    pub text: Option<String>,
+    /// "tesseract", or the vision model's name
+    pub engine: String,
+    pub generated: String,
+}
+
+/// Settings for an OCR run
+pub struct OcrOptions {
+    /// Vision backend; tesseract is used when this is `None`
+    pub backend: Option<Arc<dyn VisionBackend>>,
+    /// Tesseract language, e.g. "eng+deu"
+    pub language: Option<String>,
+    /// Read photos rated at least this
+    pub min_rating: Option<i32>,
+    /// Read photos with any of these AI tags or keywords
+    pub tags: Vec<String>,
+    /// Read every photo, ignoring rating and tags
+    pub all: bool,
+    /// Read photos again even if they were read before
+    pub force: bool,
+}
+
+impl OcrOptions {
+    fn engine_name(&self) -> String {
+        match &self.backend {
+            Some(backend) => backend.model().to_string(),
+            None => "tesseract".to_string(),
+        }
+    }
+    
+    /// Whether a photo qualifies by rating, by its tags, or because the
+    /// vision analysis already noticed text in it
+    fn wants(&self, frontmatter: &Mapping) -> bool {
+        if self.all || frontmatter.contains_key("ai_text_in_image") {
+            return true;
+        }
+        
+        if let Some(min_rating) = self.min_rating
+            && frontmatter.get("rating").and_then(|v| v.as_i64()).is_some_and(|r| r >= min_rating as i64)
+        {
+            return true;
+        }
+        
+        ["ai_tags", "keywords"].iter()
+            .filter_map(|key| frontmatter.get(*key).and_then(|v| v.as_sequence()))
+            .flatten()
+            .filter_map(|tag| tag.as_str())
+            .any(|tag| self.tags.iter().any(|wanted| wanted.eq_ignore_ascii_case(tag.trim())))
+    }
+}
+
+/// Whether the `tesseract` command can be run
+pub fn tesseract_available() -> bool {
+    Command::new("tesseract")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Read text from a JPEG with tesseract, piping the image through stdin
+fn run_tesseract(jpeg_data: &[u8], language: Option<&str>) -> Result<Option<String>> {
+    let mut command = Command::new("tesseract");
+    command.arg("stdin").arg("stdout");
+    if let Some(language) = language {
+        command.arg("-l").arg(language);
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run tesseract")?;
+    
+    child.stdin.take()
+        .ok_or_else(|| anyhow!("Failed to open tesseract stdin"))?
+        .write_all(jpeg_data)
+        .context("Failed to send image to tesseract")?;
+    let output = child.wait_with_output().context("Failed to run tesseract")?;
+    if !output.status.success() {
+        return Err(anyhow!("tesseract failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    
+    // Drop the blank lines tesseract leaves between blocks
+    let text = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok((!text.is_empty()).then_some(text))
+}
+
+/// Read the OCR result back from a sidecar, so rewriting it keeps the text
+pub fn read_ocr_result(frontmatter: &Mapping) -> Option<OcrResult> {
+    let ocr = frontmatter.get("ocr")?.as_mapping()?;
+    Some(OcrResult {
+        text: frontmatter.get("text_in_image").and_then(|v| v.as_str()).map(str::to_string),
+        engine: ocr.get("engine").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+        generated: ocr.get("generated").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+    })
+}
+
+/// Store an OCR result in the frontmatter, ahead of the GPS and source fields
+/// like a freshly generated sidecar
+fn store_ocr_result(frontmatter: &mut Mapping, result: &OcrResult) {
+    let mut ocr = Mapping::new();
+    ocr.insert("engine".into(), result.engine.clone().into());
+    ocr.insert("generated".into(), result.generated.clone().into());
+    
+    let mut entries: Vec<(Value, Value)> = Vec::new();
+    if let Some(text) = &result.text {
+        entries.push(("text_in_image".into(), text.clone().into()));
+    }
+    entries.push(("ocr".into(), ocr.into()));
+    
+    let old = std::mem::take(frontmatter);
+    for (key, value) in old {
+        if matches!(key.as_str(), Some("text_in_image" | "ocr")) {
+            continue;
+        }
+        if matches!(key.as_str(), Some("gps" | "_metadata_sources")) {
+            for (k, v) in entries.drain(..) {
+                frontmatter.insert(k, v);
+            }
+        }
+        frontmatter.insert(key, value);
+    }
+    for (k, v) in entries {
+        frontmatter.insert(k, v);
+    }
+}
+
+/// Read the text in every qualifying photo under a directory and write it to the sidecars
+pub fn ocr_directory(photo_dir: &str, options: &OcrOptions, show_progress: bool) -> Result<()> {
+    use crate::sidecar_reader::read_sidecar_metadata;
+    use crate::manifest::Manifest;
+    use crate::sidecar_writer::update_frontmatter;
+    
+    println!("🔤 Reading text in photos with {}: {}\n", options.engine_name(), photo_dir);
+    
+    let start_time = Instant::now();
+    install_interrupt_handler()?;
+    let retry = RetryPolicy::default();
+    
+    // Only photos with sidecars; OCR results are added to what `update` wrote
+    let photos: Vec<_> = PhotoWalker::new(photo_dir, false)
+        .find_photos()?
+        .into_iter()
+        .filter(|p| p.has_sidecar && p.file_type.can_preview())
+        .collect();
+    let total = photos.len();
+    let manifest = Manifest::open(photo_dir)?;
+    
+    let mut with_text = 0;
+    let mut without_text = 0;
+    let mut already_read = 0;
+    let mut not_selected = 0;
+    let mut errors = Vec::new();
+    
+    for (i, photo) in photos.iter().enumerate() {
+        if interrupted() {
+            break;
+        }
+        if show_progress {
+            println!("  OCR: {}/{} ({:.1}%)", i + 1, total, (i + 1) as f64 / total as f64 * 100.0);
+        }
+        
+        let frontmatter = match read_sidecar_metadata(&photo.sidecar_path) {
+            Ok(frontmatter) => frontmatter,
+            Err(e) => {
+                errors.push(format!("{}: {}", photo.filename, e));
+                continue;
+            }
+        };
+        if !options.force && frontmatter.contains_key("ocr") {
+            already_read += 1;
+            continue;
+        }
+        if !options.wants(&frontmatter) {
+            not_selected += 1;
+            continue;
+        }
+        
+        let context = PhotoContext { filename: photo.filename.clone(), ..Default::default() };
+        let text = convert_to_jpeg(&photo.path).and_then(|(jpeg, _)| match &options.backend {
+            Some(backend) => retry.run(|| backend.read_text(&jpeg, &context)),
+            None => run_tesseract(&jpeg, options.language.as_deref()),
+        });
+        let result = match text {
+            Ok(text) => OcrResult {
+                text,
+                engine: options.engine_name(),
+                generated: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+            },
+            Err(e) => {
+                errors.push(format!("{}: {:#}", photo.filename, e));
+                continue;
+            }
+        };
+        
+        if result.text.is_some() {
+            with_text += 1;
+        } else {
+            without_text += 1;
+        }
+        let updated = update_frontmatter(&photo.sidecar_path, &manifest, |frontmatter| {
+            store_ocr_result(frontmatter, &result);
+            true
+        });
+        if let Err(e) = updated {
+            errors.push(format!("{}: {}", photo.filename, e));
+        }
+    }
+    
+    println!("\n📊 OCR Summary:");
+    println!("  🔤 Text found: {}", with_text);
+    println!("  ⬜ No text: {}", without_text);
+    println!("  ⏭️  Read before: {}", already_read);
+    println!("  🎯 Not selected (rating/tags): {}", not_selected);
+    if !errors.is_empty() {
+        println!("  ❌ Errors: {}", errors.len());
+        for error in &errors {
+            println!("     {}", error);
+        }
+    }
+    if interrupted() {
+        println!("\n⏸️  Stopped early. Run ocr again to continue.");
+    }
+    println!("  Total time: {:.2}s", start_time.elapsed().as_secs_f64());
+    
+    Ok(())
+}
@@ -1,22 +1,29 @@
 use anyhow::{Result, Context, anyhow};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use image::ImageFormat;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use image::metadata::Orientation;
 use serde::{Deserialize, Serialize};
-use std::io::Cursor;
+use std::io::{BufRead, Cursor, Seek};
 use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::Instant;
+use crate::file_types::{registry, Reader};
+use crate::raw_preview::{extract_embedded_jpeg, raw_orientation};
+use crate::retry::TransientError;
+use crate::vision::{
+    analysis_schema, request_analysis, vision_client,
+    AiProvenance, BackendKind, ChatTurn, PhotoContext, VisionAnalysis, VisionBackend,
+};
 
-const OLLAMA_API_URL: &str = "http://localhost:11434/api/chat";
-const MODEL: &str = "qwen2.5vl";
-
-// Standard tags to consider
-const STANDARD_TAGS: &str = "landscape, portrait, street photography, nature, sunset, sunrise, \
-animal, bird, mountains, ocean, beach, boat, car, tree, flower, people, crowd, pet, city, \
-building, macro, insect, computer, electronics, tools, motorcycle, sign, street sign";
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:11434";
+pub const DEFAULT_MODEL: &str = "qwen2.5vl";
 
 #[derive(Debug, Serialize)]
 struct Message {
     role: String,
     content: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     images: Vec<String>,
 }
 
@@ -25,12 +32,12 @@ struct OllamaRequest {
     model: String,
     messages: Vec<Message>,
     stream: bool,
+    format: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
 struct OllamaResponse {
     message: MessageResponse,
-    done: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,102 +45,208 @@ struct MessageResponse {
     content: String,
 }
 
-#[derive(Debug, Clone)]
-pub struct VisionAnalysis {
-    pub description: String,
-    pub tags: Vec<String>,
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<ModelEntry>,
 }
 
-/// Analyze an image using Ollama's vision model
-pub fn analyze_image<P: AsRef<Path>>(image_path: P) -> Result<VisionAnalysis> {
-    let image_path = image_path.as_ref();
-    println!("  🔍 Analyzing image with AI: {}", image_path.display());
-    
-    // Load and convert image to JPEG
-    let jpeg_data = convert_to_jpeg(image_path)?;
-    println!("  📊 JPEG data size: {} bytes", jpeg_data.len());
-    let base64_image = BASE64.encode(&jpeg_data);
-    println!("  📊 Base64 size: {} chars", base64_image.len());
-    
-    // Craft the prompt
-    let prompt = format!(
-        "Analyze this photograph and provide:
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    name: String,
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ModelRequest {
+    model: String,
+    stream: bool,
+}
 
-1. A brief, descriptive caption (1-2 sentences) that captures what's shown in the image
-2. A list of relevant tags for searching and categorization
+#[derive(Debug, Deserialize)]
+struct ShowResponse {
+    #[serde(default)]
+    capabilities: Option<Vec<String>>,
+    #[serde(default)]
+    projector_info: Option<serde_json::Value>,
+}
 
-When tagging, consider these standard categories if applicable: {}
+#[derive(Debug, Deserialize)]
+struct PullResponse {
+    status: String,
+}
 
-Also add any other specific, relevant tags that would help someone find this image later. 
-Keep tags concise (1-2 words each) and focus on observable content, style, and mood.
+/// Vision analysis through Ollama's native chat API
+pub struct OllamaBackend {
+    endpoint: String,
+    model: String,
+    // Filled in by `check`
+    digest: OnceLock<String>,
+}
 
-Format your response as:
-DESCRIPTION: [your caption here]
-TAGS: [comma-separated list of tags]
+impl OllamaBackend {
+    pub fn new(endpoint: &str, model: &str) -> Self {
+        OllamaBackend {
+            endpoint: endpoint.to_string(),
+            model: model.to_string(),
+            digest: OnceLock::new(),
+        }
+    }
+}
+
+impl OllamaBackend {
+    /// Send a conversation about an image and return the reply, constrained to `format`
+    fn chat(
+        &self,
+        client: &reqwest::blocking::Client,
+        base64_image: &str,
+        turns: &[ChatTurn],
+        format: serde_json::Value,
+    ) -> Result<String> {
+        println!("  📤 Sending request to Ollama at {} (this may take a while)...", self.endpoint);
+        
+        // The image goes with the first turn; corrections are text only
+        let messages = turns.iter().enumerate()
+            .map(|(i, turn)| Message {
+                role: turn.role.to_string(),
+                content: turn.content.clone(),
+                images: if i == 0 { vec![base64_image.to_string()] } else { Vec::new() },
+            })
+            .collect();
+        let request = OllamaRequest {
+            model: self.model.clone(),
+            messages,
+            stream: false,
+            format,
+        };
+        
+        let response = client
+            .post(api_url(&self.endpoint, "chat"))
+            .json(&request)
+            .send()
+            .map_err(|e| TransientError(format!("Failed to send request to Ollama: {}", e)))?;
+        
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(TransientError(format!("Ollama API error: {}", status)).into());
+        }
+        if !status.is_success() {
+            return Err(anyhow!("Ollama API error: {}", status));
+        }
+        
+        let response_text = response.text()
+            .map_err(|e| TransientError(format!("Failed to read Ollama response: {}", e)))?;
+        let ollama_response: OllamaResponse = serde_json::from_str(&response_text)
+            .context("Failed to parse Ollama response")?;
+        Ok(ollama_response.message.content)
+    }
+}
 
-Be specific and accurate. Focus on what's actually visible in the image.",
-        STANDARD_TAGS
-    );
+impl VisionBackend for OllamaBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Ollama
+    }
     
-    // Create request using chat format
-    let message = Message {
-        role: "user".to_string(),
-        content: prompt,
-        images: vec![base64_image],
-    };
+    fn model(&self) -> &str {
+        &self.model
+    }
     
-    let request = OllamaRequest {
-        model: MODEL.to_string(),
-        messages: vec![message],
-        stream: false,
-    };
+    fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
     
-    // Send request with longer timeout for vision models
-    println!("  📤 Sending request to Ollama (this may take a while)...");
-    let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(600))  // 10 minutes - vision models can be slow
-        .build()?;
-    let response = client
-        .post(OLLAMA_API_URL)
-        .json(&request)
-        .send()
-        .context("Failed to send request to Ollama")?;
+    fn model_digest(&self) -> Option<String> {
+        self.digest.get().cloned()
+    }
     
-    if !response.status().is_success() {
-        return Err(anyhow!("Ollama API error: {}", response.status()));
+    fn analyze(&self, jpeg_data: &[u8], context: &PhotoContext) -> Result<VisionAnalysis> {
+        println!("  📊 JPEG data size: {} bytes", jpeg_data.len());
+        let base64_image = BASE64.encode(jpeg_data);
+        println!("  📊 Base64 size: {} chars", base64_image.len());
+        
+        // Send request with longer timeout for vision models
+        let client = vision_client()?;
+        
+        request_analysis(context, |turns| {
+            self.chat(&client, &base64_image, turns, analysis_schema())
+        })
     }
     
-    let response_text = response.text()?;
-    println!("  📥 Raw Ollama response: {}", &response_text[..200.min(response_text.len())]);
+    fn ask(&self, jpeg_data: &[u8], prompt: &str, schema: serde_json::Value) -> Result<String> {
+        let turns = [ChatTurn { role: "user", content: prompt.to_string() }];
+        self.chat(&vision_client()?, &BASE64.encode(jpeg_data), &turns, schema)
+    }
     
-    let ollama_response: OllamaResponse = serde_json::from_str(&response_text)
-        .context("Failed to parse Ollama response")?;
+    fn check(&self, pull_if_missing: bool) -> Result<()> {
+        if let Some(digest) = verify_model(&self.endpoint, &self.model, pull_if_missing)? {
+            let _ = self.digest.set(digest);
+        }
+        Ok(())
+    }
     
-    println!("  📝 Parsed response: {}", &ollama_response.message.content[..200.min(ollama_response.message.content.len())]);
+    fn reachable(&self) -> bool {
+        ollama_reachable(&self.endpoint)
+    }
+}
+
+/// Analyze an image using the given Ollama vision model on the local Ollama server
+pub fn analyze_image<P: AsRef<Path>>(image_path: P, model: &str) -> Result<VisionAnalysis> {
+    let image_path = image_path.as_ref();
+    println!("  🔍 Analyzing image with AI: {}", image_path.display());
     
-    // Parse the response
-    parse_vision_response(&ollama_response.message.content)
+    // Load and convert image to JPEG
+    let (jpeg_data, source) = convert_to_jpeg(image_path)?;
+    let context = PhotoContext {
+        filename: image_path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    let backend = OllamaBackend::new(DEFAULT_ENDPOINT, model);
+    let started = Instant::now();
+    let mut analysis = backend.analyze(&jpeg_data, &context)?;
+    analysis.provenance = AiProvenance::new(&backend, source, started.elapsed());
+    Ok(analysis)
+}
+
+/// Where the pixels sent to the model came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageSource {
+    /// Preview JPEG embedded in a RAW file
+    EmbeddedPreview,
+    /// Small thumbnail embedded in a RAW file, used when there is no preview
+    EmbeddedThumbnail,
+    /// The image itself, decoded in full
+    FullDecode,
+}
+
+impl ImageSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageSource::EmbeddedPreview => "embedded_preview",
+            ImageSource::EmbeddedThumbnail => "embedded_thumbnail",
+            ImageSource::FullDecode => "full_decode",
+        }
+    }
 }
 
 /// Convert an image to JPEG format in memory
-fn convert_to_jpeg<P: AsRef<Path>>(image_path: P) -> Result<Vec<u8>> {
+pub fn convert_to_jpeg<P: AsRef<Path>>(image_path: P) -> Result<(Vec<u8>, ImageSource)> {
     let path = image_path.as_ref();
     println!("  🖼️  Converting image to JPEG: {}", path.display());
     
-    // Check if this is a RAW format that the image crate can't handle
-    let extension = path.extension()
-        .and_then(|e| e.to_str())
-        .map(|e| e.to_lowercase())
-        .unwrap_or_default();
-    
-    let jpeg_bytes = match extension.as_str() {
-        "cr2" | "dng" | "nef" | "arw" | "orf" | "raf" | "pef" => {
-            // Try to extract embedded JPEG from RAW file
-            extract_jpeg_from_raw(path)?
+    // RAW formats can't be decoded by the image crate; use their embedded preview
+    let converted = match registry().identify(path).map(|(file_type, _)| file_type.reader) {
+        Some(Reader::EmbeddedPreview) => extract_jpeg_from_raw(path)?,
+        Some(Reader::MetadataOnly) => {
+            return Err(anyhow!("Can't decode {}; only its metadata is read", path.display()));
         }
         _ => {
             // Use image crate for supported formats
-            let image = image::open(path)
+            let reader = ImageReader::open(path)
+                .and_then(|reader| reader.with_guessed_format())
+                .with_context(|| format!("Failed to open image: {}", path.display()))?;
+            let image = decode_upright(reader, None)
                 .with_context(|| format!("Failed to open image: {}", path.display()))?;
             
             // Resize if too large (Ollama handles better with reasonable sizes)
@@ -148,59 +261,84 @@ fn convert_to_jpeg<P: AsRef<Path>>(image_path: P) -> Result<Vec<u8>> {
             resized.write_to(&mut buffer, ImageFormat::Jpeg)
                 .context("Failed to encode image as JPEG")?;
             
-            buffer.into_inner()
+            (buffer.into_inner(), ImageSource::FullDecode)
         }
     };
     
-    Ok(jpeg_bytes)
+    Ok(converted)
 }
 
 /// Extract embedded JPEG from RAW file using dcraw or exiftool
-fn extract_jpeg_from_raw<P: AsRef<Path>>(raw_path: P) -> Result<Vec<u8>> {
+fn extract_jpeg_from_raw<P: AsRef<Path>>(raw_path: P) -> Result<(Vec<u8>, ImageSource)> {
     let path = raw_path.as_ref();
+    let orientation = raw_orientation(path).and_then(Orientation::from_exif);
     
-    // First try with dcraw (if available)
-    if let Ok(output) = std::process::Command::new("dcraw")
-        .args(&["-e", "-c", path.to_str().unwrap()])
-        .output()
-    {
-        if output.status.success() && !output.stdout.is_empty() {
-            println!("  ✅ Extracted embedded JPEG using dcraw ({} bytes)", output.stdout.len());
-            return resize_jpeg_if_needed(output.stdout);
+    // Read the embedded JPEG directly; the external tools are only a fallback
+    // for layouts the built-in reader doesn't know
+    let embedded = extract_embedded_jpeg(path).unwrap_or_else(|e| {
+        println!("  ⚠️  Could not read RAW file structure: {}", e);
+        None
+    });
+    let thumbnail = match embedded {
+        Some(preview) if !preview.is_thumbnail() => {
+            println!("  ✅ Extracted embedded JPEG ({}x{}, {} bytes)", preview.width, preview.height, preview.data.len());
+            return Ok((resize_jpeg_if_needed(preview.data, orientation)?, ImageSource::EmbeddedPreview));
         }
+        thumbnail => thumbnail,
+    };
+    
+    // Try with dcraw (if available)
+    if let Ok(output) = Command::new("dcraw").args(["-e", "-c"]).arg(path).output()
+        && output.status.success() && !output.stdout.is_empty()
+    {
+        println!("  ✅ Extracted embedded JPEG using dcraw ({} bytes)", output.stdout.len());
+        return Ok((resize_jpeg_if_needed(output.stdout, orientation)?, ImageSource::EmbeddedPreview));
     }
     
     // Try with exiftool (more commonly available)
-    if let Ok(output) = std::process::Command::new("exiftool")
-        .args(&["-b", "-PreviewImage", path.to_str().unwrap()])
-        .output()
+    if let Ok(output) = Command::new("exiftool").args(["-b", "-PreviewImage"]).arg(path).output()
+        && output.status.success() && !output.stdout.is_empty()
     {
-        if output.status.success() && !output.stdout.is_empty() {
-            println!("  ✅ Extracted preview image using exiftool ({} bytes)", output.stdout.len());
-            return resize_jpeg_if_needed(output.stdout);
-        }
+        println!("  ✅ Extracted preview image using exiftool ({} bytes)", output.stdout.len());
+        return Ok((resize_jpeg_if_needed(output.stdout, orientation)?, ImageSource::EmbeddedPreview));
     }
     
-    // Try to get JPEG thumbnail as last resort
-    if let Ok(output) = std::process::Command::new("exiftool")
-        .args(&["-b", "-ThumbnailImage", path.to_str().unwrap()])
-        .output()
+    // Use the JPEG thumbnail as last resort
+    if let Some(thumbnail) = thumbnail {
+        println!("  ⚠️  Using thumbnail image (lower quality, {}x{})", thumbnail.width, thumbnail.height);
+        return Ok((resize_jpeg_if_needed(thumbnail.data, orientation)?, ImageSource::EmbeddedThumbnail));
+    }
+    if let Ok(output) = Command::new("exiftool").args(["-b", "-ThumbnailImage"]).arg(path).output()
+        && output.status.success() && !output.stdout.is_empty()
     {
-        if output.status.success() && !output.stdout.is_empty() {
-            println!("  ⚠️  Using thumbnail image (lower quality, {} bytes)", output.stdout.len());
-            return resize_jpeg_if_needed(output.stdout);
-        }
+        println!("  ⚠️  Using thumbnail image (lower quality, {} bytes)", output.stdout.len());
+        return Ok((resize_jpeg_if_needed(output.stdout, orientation)?, ImageSource::EmbeddedThumbnail));
     }
     
     Err(anyhow!(
-        "Unable to extract JPEG from RAW file. Please install dcraw or ensure exiftool is available."
+        "Unable to extract JPEG from RAW file: no embedded preview found, and neither dcraw nor exiftool could read it."
     ))
 }
 
-/// Resize JPEG data if it's too large for Ollama
-fn resize_jpeg_if_needed(jpeg_data: Vec<u8>) -> Result<Vec<u8>> {
+/// Decode an image and turn it upright according to its EXIF orientation, or
+/// `fallback` (the RAW file's orientation) when the image itself doesn't say.
+/// Re-encoding drops the EXIF, so the pixels have to be rotated here.
+fn decode_upright<R: BufRead + Seek>(reader: ImageReader<R>, fallback: Option<Orientation>) -> Result<DynamicImage> {
+    let mut decoder = reader.into_decoder()?;
+    let orientation = match decoder.orientation()? {
+        Orientation::NoTransforms => fallback.unwrap_or(Orientation::NoTransforms),
+        orientation => orientation,
+    };
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Resize JPEG data if it's too large for Ollama, turning it upright on the way
+fn resize_jpeg_if_needed(jpeg_data: Vec<u8>, orientation: Option<Orientation>) -> Result<Vec<u8>> {
     // Load the JPEG
-    let img = image::load_from_memory(&jpeg_data)
+    let reader = ImageReader::new(Cursor::new(&jpeg_data)).with_guessed_format()?;
+    let img = decode_upright(reader, orientation)
         .context("Failed to load extracted JPEG")?;
     
     // Resize if too large (be less aggressive - Ollama can handle larger images)
@@ -221,47 +359,119 @@ fn resize_jpeg_if_needed(jpeg_data: Vec<u8>) -> Result<Vec<u8>> {
     Ok(result)
 }
 
-/// Parse the model's response into structured data
-fn parse_vision_response(response: &str) -> Result<VisionAnalysis> {
-    let mut description = String::new();
-    let mut tags = Vec::new();
+/// Verify that Ollama is running and that `model` is pulled and vision-capable,
+/// returning the model's digest.
+///
+/// Fails with a message listing the installed models when the model is missing,
+/// unless `pull_if_missing` is set, in which case the model is pulled first.
+fn verify_model(endpoint: &str, model: &str, pull_if_missing: bool) -> Result<Option<String>> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()?;
     
-    for line in response.lines() {
-        let line = line.trim();
-        
-        if let Some(desc) = line.strip_prefix("DESCRIPTION:") {
-            description = desc.trim().to_string();
-            println!("  ✅ Found description: {}", description);
-        } else if let Some(tag_str) = line.strip_prefix("TAGS:") {
-            tags = tag_str
-                .split(',')
-                .map(|t| t.trim().to_lowercase())
-                .filter(|t| !t.is_empty())
-                .collect();
-            println!("  ✅ Found {} tags", tags.len());
+    let mut models = list_models(&client, endpoint)?;
+    if !models.iter().any(|m| model_name_matches(model, &m.name)) {
+        if !pull_if_missing {
+            let available: Vec<&str> = models.iter().map(|m| m.name.as_str()).collect();
+            let listing = if available.is_empty() {
+                "(none)".to_string()
+            } else {
+                available.join(", ")
+            };
+            return Err(anyhow!(
+                "Model '{}' is not available in Ollama at {}\n   Installed models: {}\n   Pull it with: ollama pull {} (or pass --ai-pull)",
+                model, endpoint, listing, model
+            ));
         }
+        pull_model(endpoint, model)?;
+        models = list_models(&client, endpoint)?;
     }
+    let digest = models.into_iter()
+        .find(|m| model_name_matches(model, &m.name))
+        .and_then(|m| m.digest);
     
-    if description.is_empty() {
-        // Fallback: use the entire response as description if parsing fails
-        description = response.trim().to_string();
-        println!("  ⚠️  Using fallback description (parsing failed)");
+    // Make sure the model can actually look at images
+    let response = client
+        .post(api_url(endpoint, "show"))
+        .json(&ModelRequest { model: model.to_string(), stream: false })
+        .send()
+        .context("Failed to query model details from Ollama")?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Ollama API error showing model '{}': {}", model, response.status()));
     }
+    let show: ShowResponse = response.json()
+        .context("Failed to parse Ollama model details")?;
     
-    println!("  📋 Final: desc={} chars, {} tags", description.len(), tags.len());
-    Ok(VisionAnalysis { description, tags })
+    let is_vision = match show.capabilities {
+        Some(capabilities) => capabilities.iter().any(|c| c == "vision"),
+        // Older Ollama versions don't report capabilities; vision models have a projector
+        None => show.projector_info.is_some(),
+    };
+    if !is_vision {
+        return Err(anyhow!(
+            "Model '{}' does not support images. Choose a vision model such as {} or llama3.2-vision",
+            model, DEFAULT_MODEL
+        ));
+    }
+    
+    Ok(digest)
 }
 
-/// Check if Ollama is running and the model is available
-pub fn check_ollama_available() -> Result<bool> {
-    let client = reqwest::blocking::Client::new();
-    
-    // Try to connect to Ollama
-    match client.get("http://localhost:11434/api/tags").send() {
-        Ok(response) if response.status().is_success() => {
-            // TODO: Could parse response to check if qwen2.5vl is actually available
-            Ok(true)
-        }
-        _ => Ok(false)
+fn list_models(client: &reqwest::blocking::Client, endpoint: &str) -> Result<Vec<ModelEntry>> {
+    let response = client.get(api_url(endpoint, "tags")).send().map_err(|e| anyhow!(
+        "Ollama is not reachable at {} ({})\n   Make sure Ollama is running: ollama serve",
+        endpoint, e
+    ))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Ollama API error listing models: {}", response.status()));
+    }
+    let tags: TagsResponse = response.json()
+        .context("Failed to parse Ollama model list")?;
+    Ok(tags.models)
+}
+
+/// Whether the Ollama server answers at all, used to detect when it comes back after an outage
+fn ollama_reachable(endpoint: &str) -> bool {
+    reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .ok()
+        .and_then(|client| client.get(api_url(endpoint, "tags")).send().ok())
+        .is_some_and(|response| response.status().is_success())
+}
+
+/// Pull a model through the Ollama API, blocking until the download finishes
+fn pull_model(endpoint: &str, model: &str) -> Result<()> {
+    println!("⬇️  Pulling model '{}' on {} (this may take a while)...", model, endpoint);
+    let client = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .build()?;
+    let response = client
+        .post(api_url(endpoint, "pull"))
+        .json(&ModelRequest { model: model.to_string(), stream: false })
+        .send()
+        .context("Failed to send pull request to Ollama")?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Ollama failed to pull '{}': {}", model, response.status()));
+    }
+    let pull: PullResponse = response.json()
+        .context("Failed to parse Ollama pull response")?;
+    if pull.status != "success" {
+        return Err(anyhow!("Ollama failed to pull '{}': {}", model, pull.status));
     }
-}
\ No newline at end of file
+    println!("✅ Pulled model '{}'", model);
+    Ok(())
+}
+
+/// Model names without an explicit tag refer to `:latest`
+pub fn model_name_matches(requested: &str, installed: &str) -> bool {
+    if requested == installed {
+        return true;
+    }
+    !requested.contains(':') && installed == format!("{}:latest", requested)
+}
+
+/// Build an Ollama API URL such as `http://localhost:11434/api/chat`
+fn api_url(endpoint: &str, api: &str) -> String {
+    format!("{}/api/{}", endpoint.trim_end_matches('/'), api)
+}
@@ -0,0 +1,203 @@
+use anyhow::{Result, Context, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+use crate::retry::TransientError;
+use crate::vision::{
+    analysis_schema, request_analysis, vision_client,
+    BackendKind, ChatTurn, PhotoContext, VisionAnalysis, VisionBackend,
+};
+
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:8080";
+
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    response_format: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatMessage {
+    role: String,
+    content: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChoiceMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+/// Vision analysis through any OpenAI-compatible `/v1/chat/completions` server,
+/// such as LM Studio, vLLM or llama.cpp's `llama-server`
+pub struct OpenAiBackend {
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiBackend {
+    pub fn new(endpoint: &str, model: &str, api_key: Option<&str>) -> Self {
+        OpenAiBackend {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            api_key: api_key.map(|k| k.to_string()),
+        }
+    }
+    
+    fn request(&self, client: &reqwest::blocking::Client, method: reqwest::Method, api: &str) -> reqwest::blocking::RequestBuilder {
+        let builder = client.request(method, format!("{}/v1/{}", self.endpoint, api));
+        match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+}
+
+impl OpenAiBackend {
+    /// Send a conversation about an image and return the reply, constrained to `schema`
+    fn chat(
+        &self,
+        client: &reqwest::blocking::Client,
+        image_url: &str,
+        turns: &[ChatTurn],
+        schema: serde_json::Value,
+    ) -> Result<String> {
+        println!("  📤 Sending request to {} (this may take a while)...", self.endpoint);
+        
+        // The image goes with the first turn; corrections are text only
+        let messages = turns.iter().enumerate()
+            .map(|(i, turn)| ChatMessage {
+                role: turn.role.to_string(),
+                content: if i == 0 {
+                    serde_json::json!([
+                        { "type": "text", "text": turn.content },
+                        { "type": "image_url", "image_url": { "url": image_url } }
+                    ])
+                } else {
+                    serde_json::Value::String(turn.content.clone())
+                },
+            })
+            .collect();
+        let request = ChatRequest {
+            model: self.model.clone(),
+            messages,
+            response_format: serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "name": "photo_analysis", "schema": schema }
+            }),
+        };
+        
+        let response = self.request(client, reqwest::Method::POST, "chat/completions")
+            .json(&request)
+            .send()
+            .map_err(|e| TransientError(format!("Failed to send request to {}: {}", self.endpoint, e)))?;
+        
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(TransientError(format!("API error from {}: {}", self.endpoint, status)).into());
+        }
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            return Err(anyhow!("API error from {}: {} {}", self.endpoint, status, body.trim()));
+        }
+        
+        let response_text = response.text()
+            .map_err(|e| TransientError(format!("Failed to read response from {}: {}", self.endpoint, e)))?;
+        let chat: ChatResponse = serde_json::from_str(&response_text)
+            .context("Failed to parse chat completion response")?;
+        chat.choices.into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .ok_or_else(|| anyhow!("Chat completion response has no content"))
+    }
+}
+
+impl VisionBackend for OpenAiBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Openai
+    }
+    
+    fn model(&self) -> &str {
+        &self.model
+    }
+    
+    fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+    
+    fn analyze(&self, jpeg_data: &[u8], context: &PhotoContext) -> Result<VisionAnalysis> {
+        let image_url = format!("data:image/jpeg;base64,{}", BASE64.encode(jpeg_data));
+        
+        let client = vision_client()?;
+        
+        request_analysis(context, |turns| {
+            self.chat(&client, &image_url, turns, analysis_schema())
+        })
+    }
+    
+    fn ask(&self, jpeg_data: &[u8], prompt: &str, schema: serde_json::Value) -> Result<String> {
+        let image_url = format!("data:image/jpeg;base64,{}", BASE64.encode(jpeg_data));
+        let turns = [ChatTurn { role: "user", content: prompt.to_string() }];
+        self.chat(&vision_client()?, &image_url, &turns, schema)
+    }
+    
+    fn check(&self, pull_if_missing: bool) -> Result<()> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()?;
+        
+        let response = self.request(&client, reqwest::Method::GET, "models")
+            .send()
+            .map_err(|e| anyhow!("Server is not reachable at {} ({})", self.endpoint, e))?;
+        if !response.status().is_success() {
+            return Err(anyhow!("API error listing models at {}: {}", self.endpoint, response.status()));
+        }
+        let models: ModelsResponse = response.json()
+            .context("Failed to parse model list")?;
+        
+        if !models.data.iter().any(|m| m.id == self.model) {
+            let available: Vec<&str> = models.data.iter().map(|m| m.id.as_str()).collect();
+            let hint = if pull_if_missing {
+                "\n   This server can't pull models; load the model in the server first"
+            } else {
+                ""
+            };
+            return Err(anyhow!(
+                "Model '{}' is not served at {}\n   Available models: {}{}",
+                self.model, self.endpoint, available.join(", "), hint
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    fn reachable(&self) -> bool {
+        reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .ok()
+            .and_then(|client| self.request(&client, reqwest::Method::GET, "models").send().ok())
+            .is_some_and(|response| response.status().is_success())
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{Result, Context};
+use std::fs;
+use std::path::Path;
+use crate::manifest::Manifest;
+use crate::photo_walker::PhotoWalker;
+use crate::sidecar_reader::has_notes;
+use crate::state::state_dir;
+
+/// Folder inside the state directory that archived sidecars are moved to
+const ARCHIVE_DIR_NAME: &str = "orphans";
+
+/// What to do with orphaned sidecars nobody wrote notes in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanAction {
+    List,
+    /// Move them to `.zim-photo/orphans/`, keeping their folders
+    Archive,
+    Delete,
+}
+
+/// List sidecars whose photo is gone, telling the ones with personal notes from
+/// the untouched generated ones, and archive or delete the untouched ones.
+/// Sidecars with notes are only ever listed.
+pub fn clean_orphans(photo_dir: &str, action: OrphanAction) -> Result<()> {
+    println!("🔍 Looking for orphaned sidecars in: {}\n", photo_dir);
+    
+    let root = Path::new(photo_dir);
+    let orphans = PhotoWalker::new(root, false).find_orphaned_sidecars()?;
+    if orphans.is_empty() {
+        println!("✅ No orphaned sidecars");
+        return Ok(());
+    }
+    
+    let mut with_notes = Vec::new();
+    let mut untouched = Vec::new();
+    let mut errors = Vec::new();
+    for sidecar in orphans {
+        match has_notes(&sidecar) {
+            Ok(true) => with_notes.push(sidecar),
+            Ok(false) => untouched.push(sidecar),
+            Err(e) => errors.push(format!("{}: {}", sidecar.display(), e)),
+        }
+    }
+    
+    if !with_notes.is_empty() {
+        println!("📝 With personal notes (kept; use `relink` if the photo was moved): {}", with_notes.len());
+        for sidecar in &with_notes {
+            println!("     {}", relative(root, sidecar));
+        }
+    }
+    if !untouched.is_empty() {
+        println!("📄 Generated, no notes: {}", untouched.len());
+        for sidecar in &untouched {
+            println!("     {}", relative(root, sidecar));
+        }
+    }
+    
+    if action != OrphanAction::List && !untouched.is_empty() {
+        let manifest = Manifest::open(root)?;
+        let archive = match action {
+            OrphanAction::Archive => Some(state_dir(root)?.join(ARCHIVE_DIR_NAME)),
+            _ => None,
+        };
+        
+        let mut removed = 0;
+        for sidecar in &untouched {
+            let result = match &archive {
+                Some(archive) => archive_sidecar(root, sidecar, archive),
+                None => fs::remove_file(sidecar)
+                    .with_context(|| format!("Failed to delete {}", sidecar.display())),
+            };
+            // Sidecars are named after their photo: IMG_1234.CR2.md
+            match result.and_then(|()| manifest.forget(&sidecar.with_extension(""))) {
+                Ok(()) => removed += 1,
+                Err(e) => errors.push(format!("{}: {}", sidecar.display(), e)),
+            }
+        }
+        
+        match &archive {
+            Some(archive) => println!("\n📦 Archived {} sidecars to {}", removed, archive.display()),
+            None => println!("\n🗑️  Deleted {} sidecars", removed),
+        }
+    } else if !untouched.is_empty() {
+        println!("\nArchive them with `zim-photo orphans --archive`, or delete them with `--delete`.");
+    }
+    
+    if !errors.is_empty() {
+        println!("\n❌ Errors: {}", errors.len());
+        for error in errors.iter().take(5) {
+            println!("     {}", error);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Move a sidecar into the archive under the same relative path
+fn archive_sidecar(root: &Path, sidecar: &Path, archive: &Path) -> Result<()> {
+    let target = archive.join(sidecar.strip_prefix(root).unwrap_or(sidecar));
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::rename(sidecar, &target)
+        .with_context(|| format!("Failed to move {} to {}", sidecar.display(), target.display()))
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).display().to_string()
+}
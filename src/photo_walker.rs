@@ -2,24 +2,15 @@ use anyhow::Result;
 use walkdir::WalkDir;
 use std::path::{Path, PathBuf};
 use std::collections::HashSet;
-
-/// Supported image extensions
-const IMAGE_EXTENSIONS: &[&str] = &[
-    "jpg", "jpeg", "JPG", "JPEG",
-    "cr2", "CR2",  // Canon RAW
-    "nef", "NEF",  // Nikon RAW
-    "dng", "DNG",  // Adobe Digital Negative
-    "pef", "PEF",  // Pentax RAW
-    "raf", "RAF",  // Fuji RAW
-    "tif", "tiff", "TIF", "TIFF",
-    "png", "PNG",
-];
+use crate::file_types::{registry, FileType};
+use crate::ignore_rules::IgnoreRules;
 
 #[derive(Debug, Clone)]
 pub struct PhotoFile {
     pub path: PathBuf,
     pub filename: String,
     pub extension: String,
+    pub file_type: FileType,
     pub sidecar_path: PathBuf,
     pub has_sidecar: bool,
 }
@@ -27,15 +18,12 @@ pub struct PhotoFile {
 impl PhotoFile {
     pub fn new(path: PathBuf) -> Option<Self> {
         let filename = path.file_name()?.to_str()?.to_string();
-        let extension = path.extension()?.to_str()?.to_string();
         
         // Check if this is a supported image type
-        if !IMAGE_EXTENSIONS.contains(&extension.as_str()) {
-            return None;
-        }
+        let (file_type, extension) = registry().identify(&path)?;
         
         // Generate sidecar path: append .md to the full filename
-        let sidecar_path = path.with_extension(format!("{}.md", extension));
+        let sidecar_path = path.with_file_name(format!("{}.md", filename));
         
         let has_sidecar = sidecar_path.exists();
         
@@ -43,6 +31,7 @@ impl PhotoFile {
             path,
             filename,
             extension,
+            file_type,
             sidecar_path,
             has_sidecar,
         })
@@ -64,51 +53,135 @@ impl PhotoWalker {
     
     /// Walk directory tree and find all image files
     pub fn find_photos(&self) -> Result<Vec<PhotoFile>> {
+        let walk = self.walk()?;
+        walk.counts.print_summary();
+        Ok(walk.photos)
+    }
+    
+    /// Find all image files, and count what was left out
+    pub fn walk(&self) -> Result<Walk> {
         let mut photos = Vec::new();
+        let counts = self.for_each_photo(|photo| {
+            photos.push(photo);
+            true
+        })?;
+        photos.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Walk { photos, counts })
+    }
+    
+    /// Hand each photo to `on_photo` as soon as it is found, by name, folder by
+    /// folder: a folder's photos all come before those of its subfolders.
+    /// Stops early when `on_photo` returns false.
+    pub fn for_each_photo(&self, mut on_photo: impl FnMut(PhotoFile) -> bool) -> Result<WalkCounts> {
+        let mut counts = WalkCounts::default();
         let mut seen_files = HashSet::new();
+        let mut rules = IgnoreRules::for_root(&self.root_path)?;
         
-        for entry in WalkDir::new(&self.root_path)
+        let mut entries = WalkDir::new(&self.root_path)
             .follow_links(false)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-        {
+            .sort_by(|a, b| {
+                a.file_type().is_dir().cmp(&b.file_type().is_dir())
+                    .then_with(|| a.file_name().cmp(b.file_name()))
+            })
+            .into_iter();
+        while let Some(entry) = entries.next() {
+            let Ok(entry) = entry else {
+                continue;
+            };
             let path = entry.path();
+            let is_dir = entry.file_type().is_dir();
             
-            // Skip hidden files and directories
-            if path.to_str().map_or(false, |s| s.contains("/.")) {
+            // Folders are pruned as a whole; only photos count as excluded files
+            if is_dir {
+                if let Some(rule) = rules.excluded_by(path, true)? {
+                    counts.excluded.directories += 1;
+                    *counts.excluded.by_rule.entry(rule).or_insert(0) += 1;
+                    entries.skip_current_dir();
+                }
                 continue;
             }
-            
-            // Skip @eaDir (Synology metadata directories)
-            if path.to_str().map_or(false, |s| s.contains("@eaDir")) {
+            if !entry.file_type().is_file() {
                 continue;
             }
             
             if let Some(photo) = PhotoFile::new(path.to_path_buf()) {
+                // Hidden files, .zimignore and --include/--exclude
+                let rule = match rules.excluded_by(path, false)? {
+                    Some(rule) => Some(rule),
+                    None if !rules.included(path) => Some("not matched by --include".to_string()),
+                    None => None,
+                };
+                if let Some(rule) = rule {
+                    counts.excluded.files += 1;
+                    *counts.excluded.by_rule.entry(rule).or_insert(0) += 1;
+                    continue;
+                }
+                
                 // Skip if we already have a sidecar and skip_existing is true
                 if self.skip_existing && photo.has_sidecar {
-                    println!("  ⏭️  Skipping {} (sidecar exists)", photo.filename);
+                    counts.skipped_existing += 1;
                     continue;
                 }
                 
                 // Track filename to identify duplicates
                 if !seen_files.insert(photo.filename.clone()) {
-                    println!("  ⚠️  Duplicate filename: {}", photo.filename);
+                    counts.duplicate_names += 1;
                 }
                 
-                photos.push(photo);
+                counts.photos += 1;
+                if !on_photo(photo) {
+                    break;
+                }
             }
         }
         
-        photos.sort_by(|a, b| a.path.cmp(&b.path));
-        Ok(photos)
+        Ok(counts)
+    }
+    
+    /// Sidecars whose photo is gone: `IMG_1234.CR2.md` without `IMG_1234.CR2`, in path order
+    pub fn find_orphaned_sidecars(&self) -> Result<Vec<PathBuf>> {
+        let mut orphans = Vec::new();
+        let mut rules = IgnoreRules::for_root(&self.root_path)?;
+        
+        let mut entries = WalkDir::new(&self.root_path)
+            .follow_links(false)
+            .sort_by_file_name()
+            .into_iter();
+        while let Some(entry) = entries.next() {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            if entry.file_type().is_dir() {
+                if rules.excluded_by(entry.path(), true)?.is_some() {
+                    entries.skip_current_dir();
+                }
+                continue;
+            }
+            
+            // Only notes named after a photo file count as sidecars
+            let path = entry.path();
+            let Some(photo_path) = path.to_str().and_then(|p| p.strip_suffix(".md")).map(PathBuf::from) else {
+                continue;
+            };
+            let is_photo_name = photo_path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| registry().by_extension(e).is_some());
+            if is_photo_name && !photo_path.exists() && rules.excluded_by(&photo_path, false)?.is_none() {
+                orphans.push(path.to_path_buf());
+            }
+        }
+        
+        Ok(orphans)
     }
     
     /// Get statistics about the photo collection
     pub fn get_stats(&self) -> Result<PhotoStats> {
-        let photos = self.find_photos()?;
-        let mut stats = PhotoStats::default();
+        let walk = self.walk()?;
+        let photos = walk.photos;
+        let mut stats = PhotoStats {
+            excluded: walk.counts.excluded,
+            ..Default::default()
+        };
         
         for photo in &photos {
             stats.total_files += 1;
@@ -117,7 +190,7 @@ impl PhotoWalker {
                 stats.with_sidecars += 1;
             }
             
-            *stats.by_extension.entry(photo.extension.clone()).or_insert(0) += 1;
+            *stats.by_extension.entry(photo.extension.to_lowercase()).or_insert(0) += 1;
         }
         
         stats.without_sidecars = stats.total_files - stats.with_sidecars;
@@ -125,12 +198,61 @@ impl PhotoWalker {
     }
 }
 
+/// The photos found by a walk
+pub struct Walk {
+    pub photos: Vec<PhotoFile>,
+    pub counts: WalkCounts,
+}
+
+/// What a walk found and left out
+#[derive(Debug, Default)]
+pub struct WalkCounts {
+    pub photos: usize,
+    /// Photos left out because they already have a sidecar
+    pub skipped_existing: usize,
+    /// Photos whose file name was already seen elsewhere in the tree
+    pub duplicate_names: usize,
+    pub excluded: Exclusions,
+}
+
+impl WalkCounts {
+    /// One line per kind of photo that was left out or needs attention
+    pub fn print_summary(&self) {
+        if self.skipped_existing > 0 {
+            println!("  ⏭️  Skipped {} photos that already have sidecars", self.skipped_existing);
+        }
+        if self.duplicate_names > 0 {
+            println!("  ℹ️  {} photos share a file name with another photo (`zim-photo dupes` finds real duplicates)", self.duplicate_names);
+        }
+        if self.excluded.total() > 0 {
+            println!("  🚫 Excluded {} files and {} folders (ignore rules)", self.excluded.files, self.excluded.directories);
+        }
+    }
+}
+
+/// What the ignore rules left out of a walk
+#[derive(Debug, Default)]
+pub struct Exclusions {
+    pub files: usize,
+    /// Folders skipped along with everything in them
+    pub directories: usize,
+    /// Files and folders excluded by each rule (`.zimignore` line, glob or built-in)
+    pub by_rule: std::collections::HashMap<String, usize>,
+}
+
+impl Exclusions {
+    pub fn total(&self) -> usize {
+        self.files + self.directories
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct PhotoStats {
     pub total_files: usize,
     pub with_sidecars: usize,
     pub without_sidecars: usize,
     pub by_extension: std::collections::HashMap<String, usize>,
+    pub excluded: Exclusions,
 }
 
 impl PhotoStats {
@@ -147,5 +269,14 @@ impl PhotoStats {
         for (ext, count) in extensions {
             println!("  {}: {} files", ext.to_uppercase(), count);
         }
+        
+        if self.excluded.total() > 0 {
+            println!("\n🚫 Excluded: {} files, {} folders", self.excluded.files, self.excluded.directories);
+            let mut rules: Vec<_> = self.excluded.by_rule.iter().collect();
+            rules.sort_by_key(|(rule, count)| (std::cmp::Reverse(**count), rule.as_str()));
+            for (rule, count) in rules {
+                println!("  {}: {}", rule, count);
+            }
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,387 @@
+use anyhow::{Result, Context};
+use chrono::Local;
+use image::{DynamicImage, GrayImage, imageops::FilterType};
+use serde_yaml::{Mapping, Value};
+use std::sync::Arc;
+use std::time::Instant;
+use crate::ai_queue::{install_interrupt_handler, interrupted};
+use crate::ollama_vision::convert_to_jpeg;
+use crate::photo_walker::PhotoWalker;
+use crate::retry::RetryPolicy;
+use crate::sequences::SequenceInfo;
+use crate::sidecar_writer::store_block;
+use crate::vision::VisionBackend;
+
+/// Images are scaled down to this size before measuring, which keeps the pass
+/// fast and makes sharpness comparable between cameras
+const ANALYSIS_SIZE: u32 = 1024;
+
+/// Laplacian variance below this is blurry, above the second threshold crisp
+const BLURRY_SHARPNESS: f64 = 50.0;
+const SOFT_SHARPNESS: f64 = 150.0;
+const CRISP_SHARPNESS: f64 = 600.0;
+
+/// Percent of pixels at the ends of the histogram that counts as badly clipped
+const CLIPPED_HIGHLIGHTS_PERCENT: f64 = 5.0;
+const CLIPPED_SHADOWS_PERCENT: f64 = 20.0;
+
+/// Estimated noise sigma (0-255 scale) above which a photo counts as noisy
+const NOISY_SIGMA: f64 = 8.0;
+
+/// Technical (and optionally aesthetic) quality of a photo, stored as `quality:` in the sidecar
+#[derive(Debug, Clone, Default)]
+pub struct QualityAssessment {
+    /// Variance of the Laplacian; higher is sharper
+    pub sharpness: f64,
+    /// Percent of pixels blown out to white
+    pub highlights_clipped: f64,
+    /// Percent of pixels crushed to black
+    pub shadows_clipped: f64,
+    /// Estimated noise standard deviation, 0-255 scale
+    pub noise: f64,
+    /// Model score from 1 to 10, when requested
+    pub aesthetic: Option<f64>,
+    /// Rating from 1 to 5 derived from the scores above
+    pub suggested_rating: i64,
+    /// Whether someone accepted the suggested rating as the photo's rating
+    pub accepted: bool,
+    pub assessed: String,
+}
+
+impl QualityAssessment {
+    /// Measure a decoded image
+    pub fn measure(image: &GrayImage) -> QualityAssessment {
+        let (highlights_clipped, shadows_clipped) = clipping(image);
+        let mut assessment = QualityAssessment {
+            sharpness: laplacian_variance(image),
+            highlights_clipped,
+            shadows_clipped,
+            noise: noise_sigma(image),
+            assessed: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+            ..Default::default()
+        };
+        assessment.suggested_rating = assessment.suggest_rating();
+        assessment
+    }
+    
+    /// Start from an average 3 and adjust for sharpness, clipping and noise. With an
+    /// aesthetic score, the result is averaged with the score mapped onto 1-5.
+    pub fn suggest_rating(&self) -> i64 {
+        let mut rating: f64 = 3.0;
+        if self.sharpness < BLURRY_SHARPNESS {
+            rating -= 2.0;
+        } else if self.sharpness < SOFT_SHARPNESS {
+            rating -= 1.0;
+        } else if self.sharpness > CRISP_SHARPNESS {
+            rating += 1.0;
+        }
+        if self.highlights_clipped > CLIPPED_HIGHLIGHTS_PERCENT {
+            rating -= 1.0;
+        }
+        if self.shadows_clipped > CLIPPED_SHADOWS_PERCENT {
+            rating -= 1.0;
+        }
+        if self.noise > NOISY_SIGMA {
+            rating -= 1.0;
+        }
+        
+        if let Some(aesthetic) = self.aesthetic {
+            rating = (rating + aesthetic / 2.0) / 2.0;
+        }
+        (rating.round() as i64).clamp(1, 5)
+    }
+    
+    /// Read a `quality:` block back from a sidecar's frontmatter
+    pub fn from_frontmatter(frontmatter: &Mapping) -> Option<QualityAssessment> {
+        let quality = frontmatter.get("quality")?.as_mapping()?;
+        let number = |key: &str| quality.get(key).and_then(|v| v.as_f64());
+        Some(QualityAssessment {
+            sharpness: number("sharpness").unwrap_or_default(),
+            highlights_clipped: number("highlights_clipped").unwrap_or_default(),
+            shadows_clipped: number("shadows_clipped").unwrap_or_default(),
+            noise: number("noise").unwrap_or_default(),
+            aesthetic: number("aesthetic"),
+            suggested_rating: quality.get("suggested_rating").and_then(|v| v.as_i64())?,
+            accepted: quality.get("accepted").and_then(|v| v.as_bool()).unwrap_or(false),
+            assessed: quality.get("assessed").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+        })
+    }
+    
+    /// The `quality:` block as written to the sidecar
+    pub fn to_yaml(&self) -> Mapping {
+        let round = |x: f64| (x * 100.0).round() / 100.0;
+        let mut quality = Mapping::new();
+        quality.insert("sharpness".into(), round(self.sharpness).into());
+        quality.insert("highlights_clipped".into(), round(self.highlights_clipped).into());
+        quality.insert("shadows_clipped".into(), round(self.shadows_clipped).into());
+        quality.insert("noise".into(), round(self.noise).into());
+        if let Some(aesthetic) = self.aesthetic {
+            quality.insert("aesthetic".into(), round(aesthetic).into());
+        }
+        quality.insert("suggested_rating".into(), self.suggested_rating.into());
+        if self.accepted {
+            quality.insert("accepted".into(), true.into());
+        }
+        quality.insert("assessed".into(), self.assessed.clone().into());
+        quality
+    }
+}
+
+/// Variance of the 4-neighbour Laplacian: low when there are no sharp edges
+fn laplacian_variance(image: &GrayImage) -> f64 {
+    let (width, height) = image.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    
+    let pixel = |x: u32, y: u32| image.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    let mut sum_squares = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1) - 4.0 * pixel(x, y);
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+        }
+    }
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / count;
+    sum_squares / count - mean * mean
+}
+
+/// Percent of pixels at the very top and bottom of the histogram
+fn clipping(image: &GrayImage) -> (f64, f64) {
+    let total = image.pixels().len().max(1) as f64;
+    let highlights = image.pixels().filter(|p| p[0] >= 250).count() as f64;
+    let shadows = image.pixels().filter(|p| p[0] <= 5).count() as f64;
+    (highlights / total * 100.0, shadows / total * 100.0)
+}
+
+/// Immerkær's fast noise estimate: the response to a mask that cancels out
+/// image structure, averaged over the frame
+fn noise_sigma(image: &GrayImage) -> f64 {
+    let (width, height) = image.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    
+    let pixel = |x: u32, y: u32| image.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let response = pixel(x - 1, y - 1) - 2.0 * pixel(x, y - 1) + pixel(x + 1, y - 1)
+                - 2.0 * pixel(x - 1, y) + 4.0 * pixel(x, y) - 2.0 * pixel(x + 1, y)
+                + pixel(x - 1, y + 1) - 2.0 * pixel(x, y + 1) + pixel(x + 1, y + 1);
+            sum += response.abs();
+        }
+    }
+    sum * (std::f64::consts::PI / 2.0).sqrt() / (6.0 * (width - 2) as f64 * (height - 2) as f64)
+}
+
+/// Decode a photo (RAW files through their embedded preview) and measure it
+fn assess_photo(path: &std::path::Path, aesthetic: Option<&dyn VisionBackend>, retry: &RetryPolicy) -> Result<QualityAssessment> {
+    let (jpeg, _) = convert_to_jpeg(path)?;
+    let image = image::load_from_memory(&jpeg).context("Failed to decode image for quality assessment")?;
+    
+    let mut assessment = QualityAssessment::measure(&analysis_image(&image));
+    if let Some(backend) = aesthetic {
+        assessment.aesthetic = Some(retry.run(|| backend.score_aesthetics(&jpeg))?);
+        assessment.suggested_rating = assessment.suggest_rating();
+    }
+    Ok(assessment)
+}
+
+/// Sharpness (variance of the Laplacian) of a decoded image, measured like `quality assess` does
+pub fn sharpness(image: &DynamicImage) -> f64 {
+    laplacian_variance(&analysis_image(image))
+}
+
+/// An image scaled down to the analysis size, in grey
+fn analysis_image(image: &DynamicImage) -> GrayImage {
+    if image.width() > ANALYSIS_SIZE || image.height() > ANALYSIS_SIZE {
+        image.resize(ANALYSIS_SIZE, ANALYSIS_SIZE, FilterType::Triangle).to_luma8()
+    } else {
+        image.to_luma8()
+    }
+}
+
+/// Store a quality block in the frontmatter, ahead of the GPS and source fields
+/// like a freshly generated sidecar
+fn store_quality(frontmatter: &mut Mapping, assessment: &QualityAssessment) {
+    store_block(frontmatter, "quality", Value::Mapping(assessment.to_yaml()));
+}
+
+/// Assess every photo with a sidecar under a directory and write the `quality:` blocks
+pub fn assess_directory(
+    photo_dir: &str,
+    aesthetic: Option<Arc<dyn VisionBackend>>,
+    force: bool,
+    sequence_top: Option<usize>,
+    show_progress: bool,
+) -> Result<()> {
+    use crate::sidecar_reader::read_sidecar_metadata;
+    use crate::manifest::Manifest;
+    use crate::sidecar_writer::update_frontmatter;
+    
+    println!("🔬 Assessing photo quality in: {}\n", photo_dir);
+    
+    let start_time = Instant::now();
+    install_interrupt_handler()?;
+    let retry = RetryPolicy::default();
+    
+    // Files that can't be decoded (PSD, HEIC) have nothing to measure
+    let photos: Vec<_> = PhotoWalker::new(photo_dir, false)
+        .find_photos()?
+        .into_iter()
+        .filter(|p| p.has_sidecar && p.file_type.can_preview())
+        .collect();
+    let total = photos.len();
+    let manifest = Manifest::open(photo_dir)?;
+    
+    let mut assessed = 0;
+    let mut already_assessed = 0;
+    let mut outside_top = 0;
+    let mut suggestions = [0usize; 5];
+    let mut errors = Vec::new();
+    
+    for (i, photo) in photos.iter().enumerate() {
+        if interrupted() {
+            break;
+        }
+        if show_progress {
+            println!("  Quality: {}/{} ({:.1}%)", i + 1, total, (i + 1) as f64 / total as f64 * 100.0);
+        }
+        
+        let frontmatter = match read_sidecar_metadata(&photo.sidecar_path) {
+            Ok(frontmatter) => frontmatter,
+            Err(e) => {
+                errors.push(format!("{}: {}", photo.filename, e));
+                continue;
+            }
+        };
+        let previous = QualityAssessment::from_frontmatter(&frontmatter);
+        if !force && previous.is_some() {
+            already_assessed += 1;
+            continue;
+        }
+        if let (Some(top), Some(sequence)) = (sequence_top, SequenceInfo::from_frontmatter(&frontmatter))
+            && sequence.rank > top
+        {
+            outside_top += 1;
+            continue;
+        }
+        
+        let mut assessment = match assess_photo(&photo.path, aesthetic.as_deref(), &retry) {
+            Ok(assessment) => assessment,
+            Err(e) => {
+                errors.push(format!("{}: {:#}", photo.filename, e));
+                continue;
+            }
+        };
+        // Reassessing doesn't undo an accepted rating
+        assessment.accepted = previous.is_some_and(|p| p.accepted);
+        
+        if let Err(e) = update_frontmatter(&photo.sidecar_path, &manifest, |frontmatter| {
+            store_quality(frontmatter, &assessment);
+            true
+        }) {
+            errors.push(format!("{}: {}", photo.filename, e));
+            continue;
+        }
+        assessed += 1;
+        suggestions[(assessment.suggested_rating - 1) as usize] += 1;
+    }
+    
+    println!("\n📊 Quality Summary:");
+    println!("  ✅ Assessed: {}", assessed);
+    println!("  ⏭️  Assessed before: {}", already_assessed);
+    if outside_top > 0 {
+        println!("  ⏭️  Not among the sharpest of their sequence: {}", outside_top);
+    }
+    if assessed > 0 {
+        println!("  ⭐ Suggested ratings:");
+        for (stars, count) in suggestions.iter().enumerate().rev() {
+            println!("     {} {}", "★".repeat(stars + 1), count);
+        }
+    }
+    if !errors.is_empty() {
+        println!("  ❌ Errors: {}", errors.len());
+        for error in &errors {
+            println!("     {}", error);
+        }
+    }
+    if interrupted() {
+        println!("\n⏸️  Stopped early. Run quality assess again to continue.");
+    }
+    println!("  Total time: {:.2}s", start_time.elapsed().as_secs_f64());
+    println!("\nReview the suggestions with `zim-photo quality accept --dry-run`.");
+    
+    Ok(())
+}
+
+/// Turn suggested ratings into real ratings for photos nobody has rated yet
+pub fn accept_suggestions(photo_dir: &str, min_rating: Option<i64>, dry_run: bool) -> Result<()> {
+    use crate::manifest::Manifest;
+    use crate::sidecar_writer::update_frontmatter;
+    
+    let photos = PhotoWalker::new(photo_dir, false).find_photos()?;
+    let manifest = Manifest::open(photo_dir)?;
+    let mut accepted = 0;
+    let mut errors = Vec::new();
+    
+    for photo in photos.iter().filter(|p| p.has_sidecar) {
+        let result = update_frontmatter(&photo.sidecar_path, &manifest, |frontmatter| {
+            if frontmatter.contains_key("rating") {
+                return false;
+            }
+            let Some(mut assessment) = QualityAssessment::from_frontmatter(frontmatter) else {
+                return false;
+            };
+            if min_rating.is_some_and(|min| assessment.suggested_rating < min) {
+                return false;
+            }
+            
+            println!("  {} {}", "★".repeat(assessment.suggested_rating as usize), photo.path.display());
+            accepted += 1;
+            if dry_run {
+                return false;
+            }
+            assessment.accepted = true;
+            set_rating(frontmatter, assessment.suggested_rating);
+            store_quality(frontmatter, &assessment);
+            true
+        });
+        if let Err(e) = result {
+            errors.push(format!("{}: {}", photo.filename, e));
+        }
+    }
+    
+    if dry_run {
+        println!("\n🔍 {} suggested ratings would be accepted (dry run, nothing was written)", accepted);
+    } else {
+        println!("\n✅ Accepted {} suggested ratings", accepted);
+    }
+    for error in &errors {
+        println!("  ❌ {}", error);
+    }
+    Ok(())
+}
+
+/// Add a rating where a generated sidecar puts it: after the camera settings
+fn set_rating(frontmatter: &mut Mapping, rating: i64) {
+    const BEFORE: &[&str] = &["color_label", "keywords", "title", "caption", "location", "ai_description"];
+    
+    let old = std::mem::take(frontmatter);
+    let mut pending = Some(rating);
+    for (key, value) in old {
+        if key.as_str().is_some_and(|k| BEFORE.contains(&k))
+            && let Some(rating) = pending.take()
+        {
+            frontmatter.insert("rating".into(), rating.into());
+        }
+        frontmatter.insert(key, value);
+    }
+    if let Some(rating) = pending {
+        frontmatter.insert("rating".into(), rating.into());
+    }
+}
@@ -0,0 +1,487 @@
+use anyhow::{Result, Context};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Previews smaller than this on their longest side are only thumbnails
+const THUMBNAIL_MAX_SIZE: u32 = 640;
+
+/// Give up on files whose IFDs form a cycle or are otherwise absurd
+const MAX_IFDS: usize = 64;
+const MAX_ENTRIES: u16 = 1024;
+
+/// How far into a JPEG to look for its frame header
+const HEADER_SCAN_BYTES: u64 = 64 * 1024;
+
+// TIFF tags that lead to embedded JPEGs
+const TAG_JPG_FROM_RAW: u16 = 0x002E;
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_MAKER_NOTE: u16 = 0x927C;
+
+// Olympus keeps its large preview in the MakerNote's camera settings directory
+const TAG_OLYMPUS_CAMERA_SETTINGS: u16 = 0x2020;
+const TAG_OLYMPUS_PREVIEW_START: u16 = 0x0101;
+const TAG_OLYMPUS_PREVIEW_LENGTH: u16 = 0x0102;
+
+/// A JPEG embedded in a RAW file
+pub struct EmbeddedJpeg {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl EmbeddedJpeg {
+    /// Whether this is only a small thumbnail rather than a usable preview
+    pub fn is_thumbnail(&self) -> bool {
+        self.width.max(self.height) < THUMBNAIL_MAX_SIZE
+    }
+}
+
+/// Find the largest JPEG embedded in a RAW file without external tools.
+///
+/// Handles TIFF-based formats (CR2, NEF, DNG, PEF, ARW, ORF, RW2, 3FR) by walking IFD0, the
+/// IFD chain, SubIFDs and the EXIF IFD (and for ORF, the Olympus MakerNote), and
+/// Fujifilm RAF files through the preview offset in their header. Returns `None`
+/// when no baseline JPEG is found.
+pub fn extract_embedded_jpeg(path: &Path) -> Result<Option<EmbeddedJpeg>> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let file_len = file.metadata()?.len();
+    
+    let mut header = [0u8; 92];
+    if file.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+    
+    let candidates = if header.starts_with(b"FUJIFILMCCD-RAW") {
+        // Big-endian offset and length of the preview JPEG, right after the header fields
+        let offset = u32::from_be_bytes(header[84..88].try_into()?) as u64;
+        let length = u32::from_be_bytes(header[88..92].try_into()?) as u64;
+        vec![(offset, length)]
+    } else {
+        let Some(mut tiff) = Tiff::new(&mut file, &header) else {
+            return Ok(None);
+        };
+        tiff.jpeg_candidates()
+    };
+    
+    // Compare the candidates by their frame headers, then read only the largest
+    let mut best: Option<(u64, u64, u32, u32)> = None;
+    for (offset, length) in candidates {
+        if length < 4 || offset.saturating_add(length) > file_len {
+            continue;
+        }
+        let mut prefix = vec![0u8; length.min(HEADER_SCAN_BYTES) as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        if file.read_exact(&mut prefix).is_err() {
+            continue;
+        }
+        let Some((width, height)) = baseline_jpeg_size(&prefix) else {
+            continue;
+        };
+        if best.is_none_or(|(_, _, w, h)| width as u64 * height as u64 > w as u64 * h as u64) {
+            best = Some((offset, length, width, height));
+        }
+    }
+    
+    let Some((offset, length, width, height)) = best else {
+        return Ok(None);
+    };
+    let mut data = vec![0u8; length as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)
+        .with_context(|| format!("Failed to read embedded JPEG from {}", path.display()))?;
+    Ok(Some(EmbeddedJpeg { data, width, height }))
+}
+
+/// The EXIF orientation (1-8) recorded in a TIFF-based RAW file's first IFD.
+/// Embedded previews are stored as the sensor saw them and rarely carry their own.
+pub fn raw_orientation(path: &Path) -> Option<u8> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 8];
+    file.read_exact(&mut header).ok()?;
+    let mut tiff = Tiff::new(&mut file, &header)?;
+    let ifd0 = tiff.u32_from(&header[4..8]) as u64;
+    let orientation = tiff.read_ifd(ifd0)?.orientation?;
+    u8::try_from(orientation).ok()
+}
+
+/// Just enough of a TIFF reader to follow the IFDs of a RAW file
+struct Tiff<'a> {
+    file: &'a mut File,
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    /// Recognize the TIFF header, including Olympus' "IIRO"/"IIRS" and Panasonic's "IIU" variants
+    fn new(file: &'a mut File, header: &[u8]) -> Option<Self> {
+        let big_endian = match &header[0..2] {
+            b"II" => false,
+            b"MM" => true,
+            _ => return None,
+        };
+        let tiff = Tiff { file, big_endian };
+        match tiff.u16_from(&header[2..4]) {
+            42 | 0x4F52 | 0x5352 | 0x55 => Some(tiff),
+            _ => None,
+        }
+    }
+    
+    fn u16_from(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+    }
+    
+    fn u32_from(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    }
+    
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Option<()> {
+        self.file.seek(SeekFrom::Start(offset)).ok()?;
+        self.file.read_exact(buffer).ok()
+    }
+    
+    /// (offset, length) of every JPEG-looking blob the IFDs point at
+    fn jpeg_candidates(&mut self) -> Vec<(u64, u64)> {
+        let mut header = [0u8; 8];
+        if self.read_at(0, &mut header).is_none() {
+            return Vec::new();
+        }
+        let mut pending = vec![self.u32_from(&header[4..8]) as u64];
+        let mut visited = HashSet::new();
+        let mut candidates = Vec::new();
+        
+        while let Some(offset) = pending.pop() {
+            if offset == 0 || visited.len() >= MAX_IFDS || !visited.insert(offset) {
+                continue;
+            }
+            let Some(ifd) = self.read_ifd(offset) else {
+                continue;
+            };
+            
+            if let (Some(start), Some(length)) = (ifd.jpeg_offset, ifd.jpeg_length) {
+                candidates.push((start, length));
+            }
+            candidates.extend(ifd.jpg_from_raw);
+            if let Some(maker_note) = ifd.maker_note {
+                candidates.extend(self.olympus_preview(maker_note));
+            }
+            // JPEG strips hold CR2 previews (old-style, compression 6) and DNG previews
+            // (compression 7, which is also used for the lossless raw data)
+            if matches!(ifd.compression, Some(6 | 7))
+                && let ([start], [length]) = (ifd.strip_offsets.as_slice(), ifd.strip_byte_counts.as_slice())
+            {
+                candidates.push((*start, *length));
+            }
+            pending.extend(ifd.children);
+            pending.push(ifd.next);
+        }
+        candidates
+    }
+    
+    fn read_ifd(&mut self, offset: u64) -> Option<Ifd> {
+        let mut count = [0u8; 2];
+        self.read_at(offset, &mut count)?;
+        let count = self.u16_from(&count);
+        if count == 0 || count > MAX_ENTRIES {
+            return None;
+        }
+        
+        let mut entries = vec![0u8; count as usize * 12 + 4];
+        self.read_at(offset + 2, &mut entries)?;
+        let mut ifd = Ifd {
+            next: self.u32_from(&entries[count as usize * 12..]) as u64,
+            ..Default::default()
+        };
+        
+        for entry in entries[..count as usize * 12].chunks_exact(12) {
+            let tag = self.u16_from(&entry[0..2]);
+            match tag {
+                TAG_COMPRESSION => ifd.compression = self.values(entry).first().copied(),
+                TAG_ORIENTATION => ifd.orientation = self.values(entry).first().copied(),
+                TAG_STRIP_OFFSETS => ifd.strip_offsets = self.values(entry),
+                TAG_STRIP_BYTE_COUNTS => ifd.strip_byte_counts = self.values(entry),
+                TAG_JPEG_OFFSET => ifd.jpeg_offset = self.values(entry).first().copied(),
+                TAG_JPEG_LENGTH => ifd.jpeg_length = self.values(entry).first().copied(),
+                TAG_SUB_IFDS | TAG_EXIF_IFD => ifd.children.extend(self.values(entry)),
+                TAG_MAKER_NOTE => ifd.maker_note = Some(self.u32_from(&entry[8..12]) as u64),
+                // Panasonic stores the whole preview JPEG as the tag's value
+                TAG_JPG_FROM_RAW => {
+                    let length = self.u32_from(&entry[4..8]) as u64;
+                    ifd.jpg_from_raw = Some((self.u32_from(&entry[8..12]) as u64, length));
+                }
+                _ => {}
+            }
+        }
+        Some(ifd)
+    }
+    
+    /// (offset, length) of the preview in an Olympus MakerNote. Newer ones start with
+    /// "OLYMPUS" or "OM SYSTEM", their own byte order and offsets relative to the
+    /// MakerNote; older ones with "OLYMP" and offsets relative to the TIFF header.
+    fn olympus_preview(&mut self, maker_note: u64) -> Option<(u64, u64)> {
+        let mut header = [0u8; 16];
+        self.read_at(maker_note, &mut header)?;
+        let (base, ifd, byte_order) = if header.starts_with(b"OLYMPUS\0") {
+            (maker_note, maker_note + 12, Some(&header[8..10]))
+        } else if header.starts_with(b"OM SYSTEM\0") {
+            (maker_note, maker_note + 16, Some(&header[12..14]))
+        } else if header.starts_with(b"OLYMP\0") {
+            (0, maker_note + 8, None)
+        } else {
+            return None;
+        };
+        
+        let file_big_endian = self.big_endian;
+        match byte_order {
+            Some(b"II") => self.big_endian = false,
+            Some(b"MM") => self.big_endian = true,
+            _ => {}
+        }
+        let preview = self.olympus_settings_preview(base, ifd);
+        self.big_endian = file_big_endian;
+        preview
+    }
+    
+    /// The preview listed in the camera settings directory of an Olympus MakerNote
+    fn olympus_settings_preview(&mut self, base: u64, maker_note_ifd: u64) -> Option<(u64, u64)> {
+        let settings = self.find_entry(maker_note_ifd, TAG_OLYMPUS_CAMERA_SETTINGS)?;
+        // A LONG or IFD entry, or in older models an UNDEFINED block, all pointing at the directory
+        let settings = base + self.u32_from(&settings[8..12]) as u64;
+        let start = self.find_entry(settings, TAG_OLYMPUS_PREVIEW_START)?;
+        let length = self.find_entry(settings, TAG_OLYMPUS_PREVIEW_LENGTH)?;
+        Some((base + self.u32_from(&start[8..12]) as u64, self.u32_from(&length[8..12]) as u64))
+    }
+    
+    /// The raw 12-byte entry for a tag in the IFD at `offset`
+    fn find_entry(&mut self, offset: u64, tag: u16) -> Option<[u8; 12]> {
+        let mut count = [0u8; 2];
+        self.read_at(offset, &mut count)?;
+        let count = self.u16_from(&count);
+        if count == 0 || count > MAX_ENTRIES {
+            return None;
+        }
+        let mut entries = vec![0u8; count as usize * 12];
+        self.read_at(offset + 2, &mut entries)?;
+        entries.chunks_exact(12)
+            .find(|entry| self.u16_from(&entry[0..2]) == tag)
+            .and_then(|entry| entry.try_into().ok())
+    }
+    
+    /// The SHORT, LONG or IFD values of an entry, whether stored inline or at an offset
+    fn values(&mut self, entry: &[u8]) -> Vec<u64> {
+        let field_type = self.u16_from(&entry[2..4]);
+        let count = self.u32_from(&entry[4..8]) as usize;
+        let size = match field_type {
+            3 => 2,
+            4 | 13 => 4,
+            _ => return Vec::new(),
+        };
+        if count == 0 || count > 64 {
+            return Vec::new();
+        }
+        
+        let bytes = if count * size <= 4 {
+            entry[8..8 + count * size].to_vec()
+        } else {
+            let mut bytes = vec![0u8; count * size];
+            if self.read_at(self.u32_from(&entry[8..12]) as u64, &mut bytes).is_none() {
+                return Vec::new();
+            }
+            bytes
+        };
+        bytes.chunks_exact(size)
+            .map(|b| if size == 2 { self.u16_from(b) as u64 } else { self.u32_from(b) as u64 })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Ifd {
+    next: u64,
+    compression: Option<u64>,
+    orientation: Option<u64>,
+    strip_offsets: Vec<u64>,
+    strip_byte_counts: Vec<u64>,
+    jpeg_offset: Option<u64>,
+    jpeg_length: Option<u64>,
+    jpg_from_raw: Option<(u64, u64)>,
+    maker_note: Option<u64>,
+    children: Vec<u64>,
+}
+
+/// Width and height of a JPEG the image crate can decode, read from its frame header.
+/// Lossless JPEG (used for the raw data itself in DNG and CR2) is rejected.
+fn baseline_jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        match marker {
+            // Baseline, extended sequential and progressive frames
+            0xC0..=0xC2 => {
+                let frame = data.get(pos + 4..pos + 9)?;
+                let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                return (width > 0 && height > 0).then_some((width, height));
+            }
+            // Any other frame type (lossless, hierarchical, arithmetic)
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return None,
+            // Start of scan without a frame header
+            0xDA => return None,
+            _ => pos += 2 + length,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use std::io::Cursor;
+    
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+            .unwrap();
+        data
+    }
+    
+    fn entry(out: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: u32) {
+        out.extend(tag.to_le_bytes());
+        out.extend(field_type.to_le_bytes());
+        out.extend(count.to_le_bytes());
+        out.extend(value.to_le_bytes());
+    }
+    
+    /// A little-endian TIFF RAW with a thumbnail in IFD0 and a SubIFD holding one
+    /// strip with the given compression: 6 for a large JPEG preview, 1 for raw data
+    fn tiff(strip_compression: u32) -> Vec<u8> {
+        let thumbnail = jpeg(160, 120);
+        let preview = jpeg(1024, 768);
+        let thumbnail_at = 92u32;
+        let preview_at = thumbnail_at + thumbnail.len() as u32;
+        
+        let mut out = b"II*\0\x08\0\0\0".to_vec();
+        out.extend(3u16.to_le_bytes());
+        entry(&mut out, TAG_SUB_IFDS, 4, 1, 50);
+        entry(&mut out, TAG_JPEG_OFFSET, 4, 1, thumbnail_at);
+        entry(&mut out, TAG_JPEG_LENGTH, 4, 1, thumbnail.len() as u32);
+        out.extend(0u32.to_le_bytes());
+        
+        out.extend(3u16.to_le_bytes());
+        entry(&mut out, TAG_COMPRESSION, 3, 1, strip_compression);
+        entry(&mut out, TAG_STRIP_OFFSETS, 4, 1, preview_at);
+        entry(&mut out, TAG_STRIP_BYTE_COUNTS, 4, 1, preview.len() as u32);
+        out.extend(0u32.to_le_bytes());
+        assert_eq!(out.len() as u32, thumbnail_at);
+        
+        out.extend(thumbnail);
+        out.extend(preview);
+        out
+    }
+    
+    /// A little-endian ORF with a thumbnail in IFD0 and the large preview only in
+    /// the MakerNote, which starts with `maker_header`
+    fn orf(maker_header: &[u8]) -> Vec<u8> {
+        let thumbnail = jpeg(160, 120);
+        let preview = jpeg(1024, 768);
+        let maker_note = 68u32;
+        let header_len = maker_header.len() as u32;
+        let maker_ifd = maker_note + header_len;
+        let settings = maker_ifd + 18;
+        let thumbnail_at = settings + 30;
+        let preview_at = thumbnail_at + thumbnail.len() as u32;
+        // Newer MakerNotes count offsets from their own start, "OLYMP" ones from the file's
+        let base = if maker_header.starts_with(b"OLYMP\0") { 0 } else { maker_note };
+        
+        let mut out = b"IIRO\x08\0\0\0".to_vec();
+        out.extend(3u16.to_le_bytes());
+        entry(&mut out, TAG_JPEG_OFFSET, 4, 1, thumbnail_at);
+        entry(&mut out, TAG_JPEG_LENGTH, 4, 1, thumbnail.len() as u32);
+        entry(&mut out, TAG_EXIF_IFD, 4, 1, 50);
+        out.extend(0u32.to_le_bytes());
+        
+        out.extend(1u16.to_le_bytes());
+        entry(&mut out, TAG_MAKER_NOTE, 7, thumbnail_at - maker_note, maker_note);
+        out.extend(0u32.to_le_bytes());
+        
+        out.extend(maker_header);
+        out.extend(1u16.to_le_bytes());
+        entry(&mut out, TAG_OLYMPUS_CAMERA_SETTINGS, 13, 1, settings - base);
+        out.extend(0u32.to_le_bytes());
+        out.extend(2u16.to_le_bytes());
+        entry(&mut out, TAG_OLYMPUS_PREVIEW_START, 4, 1, preview_at - base);
+        entry(&mut out, TAG_OLYMPUS_PREVIEW_LENGTH, 4, 1, preview.len() as u32);
+        out.extend(0u32.to_le_bytes());
+        assert_eq!(out.len() as u32, thumbnail_at);
+        
+        out.extend(thumbnail);
+        out.extend(preview);
+        out
+    }
+    
+    fn extract(name: &str, data: &[u8]) -> Option<EmbeddedJpeg> {
+        let path = std::env::temp_dir().join(format!("zim-photo-raw-{}-{}.raw", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        let jpeg = extract_embedded_jpeg(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        jpeg
+    }
+    
+    #[test]
+    fn picks_the_largest_preview() {
+        let jpeg = extract("preview", &tiff(6)).unwrap();
+        assert_eq!((jpeg.width, jpeg.height), (1024, 768));
+        assert!(!jpeg.is_thumbnail());
+        assert!(jpeg.data.starts_with(&[0xFF, 0xD8]));
+    }
+    
+    #[test]
+    fn falls_back_to_the_ifd0_thumbnail() {
+        let jpeg = extract("thumbnail", &tiff(1)).unwrap();
+        assert_eq!((jpeg.width, jpeg.height), (160, 120));
+        assert!(jpeg.is_thumbnail());
+    }
+    
+    #[test]
+    fn picks_the_largest_preview_from_the_olympus_maker_note() {
+        for (name, header) in [
+            ("olympus", &b"OLYMPUS\0II\x03\0"[..]),
+            ("om-system", &b"OM SYSTEM\0\0\0II\x04\0"[..]),
+            ("olymp", &b"OLYMP\0\x01\0"[..]),
+        ] {
+            let jpeg = extract(name, &orf(header)).unwrap();
+            assert_eq!((jpeg.width, jpeg.height), (1024, 768), "{}", name);
+            assert!(!jpeg.is_thumbnail());
+            assert!(jpeg.data.starts_with(&[0xFF, 0xD8]));
+        }
+    }
+    
+    #[test]
+    fn ignores_files_that_are_not_tiff() {
+        assert!(extract("not-tiff", b"this is not a raw file at all, just some text that is long enough to read").is_none());
+    }
+}
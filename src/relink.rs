@@ -0,0 +1,348 @@
+use anyhow::Result;
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::ai_cache::content_hash;
+use crate::exif_reader::read_exif;
+use crate::manifest::Manifest;
+use crate::photo_walker::{PhotoFile, PhotoWalker};
+use crate::sidecar_reader::read_sidecar_metadata;
+use crate::sidecar_writer::update_frontmatter;
+
+/// Why a photo is taken to be the one an orphaned sidecar belongs to, weakest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchKind {
+    /// Same file name, camera and exposure settings, for sidecars written before
+    /// capture times were recorded
+    NameAndSettings,
+    /// Same file name and capture time (and no conflicting camera serial)
+    NameAndTime,
+    /// Same capture time and camera serial, under a new name
+    TimeAndSerial,
+    /// Same content as when the sidecar was written
+    ContentHash,
+}
+
+impl MatchKind {
+    fn describe(&self) -> &'static str {
+        match self {
+            MatchKind::NameAndSettings => "name, camera and settings",
+            MatchKind::NameAndTime => "name and capture time",
+            MatchKind::TimeAndSerial => "capture time and camera serial",
+            MatchKind::ContentHash => "content hash",
+        }
+    }
+}
+
+/// What is known about the photo an orphaned sidecar was written for
+struct Orphan {
+    sidecar: PathBuf,
+    /// Where the photo used to be
+    old_photo: PathBuf,
+    filename: String,
+    captured: Option<String>,
+    serial: Option<String>,
+    camera: Option<String>,
+    /// Exposure settings as written in the sidecar's `settings`
+    settings: BTreeMap<String, String>,
+    /// Size and content hash from the manifest
+    recorded: Option<(i64, String)>,
+}
+
+impl Orphan {
+    /// Nothing recorded that a photo could be recognized by
+    fn unmatchable(&self) -> bool {
+        self.recorded.is_none() && self.captured.is_none() && (self.camera.is_none() || self.settings.is_empty())
+    }
+}
+
+/// A candidate's EXIF, in the form it's written to sidecars
+#[derive(Default)]
+struct ExifFacts {
+    captured: Option<String>,
+    serial: Option<String>,
+    camera: Option<String>,
+    settings: BTreeMap<String, String>,
+}
+
+/// A photo without a sidecar, read only as far as the orphans need
+struct Candidate {
+    photo: PhotoFile,
+    size: i64,
+    hash: OnceCell<Option<String>>,
+    exif: OnceCell<ExifFacts>,
+}
+
+impl Candidate {
+    fn hash(&self) -> Option<&str> {
+        self.hash.get_or_init(|| content_hash(&self.photo.path).ok()).as_deref()
+    }
+    
+    fn exif(&self) -> &ExifFacts {
+        self.exif.get_or_init(|| {
+            let Ok(exif) = read_exif(&self.photo.path) else {
+                return ExifFacts::default();
+            };
+            let fields = exif.to_hashmap();
+            // The same keys and formatting as the sidecar's `settings`
+            let settings = [("iso", "iso"), ("aperture", "aperture"), ("shutter_speed", "shutter"), ("focal_length", "focal_length")]
+                .into_iter()
+                .filter_map(|(field, key)| Some((key.to_string(), fields.get(field)?.clone())))
+                .collect();
+            ExifFacts {
+                captured: fields.get("captured").cloned(),
+                serial: exif.camera_serial.clone(),
+                camera: exif.camera_make.zip(exif.camera_model).map(|(make, model)| format!("{} {}", make, model)),
+                settings,
+            }
+        })
+    }
+    
+    fn matches(&self, orphan: &Orphan) -> Option<MatchKind> {
+        if let Some((size, hash)) = &orphan.recorded
+            && self.size == *size
+            && self.hash() == Some(hash.as_str())
+        {
+            return Some(MatchKind::ContentHash);
+        }
+        
+        let exif = self.exif();
+        if orphan.captured.is_none() {
+            let same_settings = orphan.camera.is_some() && !orphan.settings.is_empty()
+                && exif.camera == orphan.camera && exif.settings == orphan.settings;
+            return (same_settings && self.photo.filename == orphan.filename).then_some(MatchKind::NameAndSettings);
+        }
+        if exif.captured != orphan.captured {
+            return None;
+        }
+        match (&exif.serial, &orphan.serial) {
+            (Some(a), Some(b)) if a != b => None,
+            _ if self.photo.filename == orphan.filename => Some(MatchKind::NameAndTime),
+            (Some(_), Some(_)) => Some(MatchKind::TimeAndSerial),
+            _ => None,
+        }
+    }
+}
+
+/// Find sidecars whose photo is gone and move them next to the photo they belong
+/// to, wherever in the tree it ended up
+pub fn relink_sidecars(photo_dir: &str, dry_run: bool) -> Result<()> {
+    println!("🔗 Relinking orphaned sidecars in: {}\n", photo_dir);
+    
+    let walker = PhotoWalker::new(photo_dir, false);
+    let manifest = Manifest::open(photo_dir)?;
+    let orphans = walker.find_orphaned_sidecars()?;
+    if orphans.is_empty() {
+        println!("✅ No orphaned sidecars");
+        return Ok(());
+    }
+    
+    let mut candidates: Vec<Option<Candidate>> = walker.walk()?.photos
+        .into_iter()
+        .filter(|p| !p.has_sidecar)
+        .map(|photo| {
+            let size = fs::metadata(&photo.path).map_or(-1, |m| m.len() as i64);
+            Some(Candidate { photo, size, hash: OnceCell::new(), exif: OnceCell::new() })
+        })
+        .collect();
+    println!("📸 {} orphaned sidecars, {} photos without a sidecar\n", orphans.len(), candidates.iter().flatten().count());
+    
+    let mut relinked = 0;
+    let mut ambiguous = 0;
+    let mut unmatched = Vec::new();
+    let mut unmatchable = Vec::new();
+    let mut errors = Vec::new();
+    
+    for sidecar in orphans {
+        let orphan = match read_orphan(&sidecar, &manifest) {
+            Ok(orphan) => orphan,
+            Err(e) => {
+                errors.push(format!("{}: {}", sidecar.display(), e));
+                continue;
+            }
+        };
+        if orphan.unmatchable() {
+            unmatchable.push(relative(photo_dir, &sidecar));
+            continue;
+        }
+        
+        let matches: Vec<(usize, MatchKind)> = candidates.iter()
+            .enumerate()
+            .filter_map(|(i, c)| Some((i, c.as_ref()?.matches(&orphan)?)))
+            .collect();
+        let Some(best) = matches.iter().map(|(_, kind)| *kind).max() else {
+            unmatched.push(relative(photo_dir, &sidecar));
+            continue;
+        };
+        let mut best_matches: Vec<usize> = matches.iter()
+            .filter(|(_, kind)| *kind == best)
+            .map(|(i, _)| *i)
+            .collect();
+        let match_count = best_matches.len();
+        // Several copies of the same content: the one still called the same wins
+        if match_count > 1 {
+            best_matches.retain(|i| candidates[*i].as_ref().is_some_and(|c| c.photo.filename == orphan.filename));
+        }
+        let [index] = best_matches[..] else {
+            ambiguous += 1;
+            println!("  ❓ {}: {} photos match by {}, left alone",
+                relative(photo_dir, &sidecar), match_count, best.describe());
+            continue;
+        };
+        
+        let Some(candidate) = candidates[index].take() else {
+            continue;
+        };
+        let photo = candidate.photo;
+        println!("  🔗 {} → {} ({})",
+            relative(photo_dir, &sidecar), relative(photo_dir, &photo.path), best.describe());
+        if dry_run {
+            relinked += 1;
+            continue;
+        }
+        match move_sidecar(&orphan, &photo, &manifest) {
+            Ok(()) => relinked += 1,
+            Err(e) => errors.push(format!("{}: {}", sidecar.display(), e)),
+        }
+    }
+    
+    println!("\n📊 Relink Summary:");
+    if dry_run {
+        println!("  🔗 Would relink: {}", relinked);
+    } else {
+        println!("  🔗 Relinked: {}", relinked);
+    }
+    if ambiguous > 0 {
+        println!("  ❓ Ambiguous, left alone: {}", ambiguous);
+    }
+    if !unmatched.is_empty() {
+        println!("  ⚠️  No matching photo: {}", unmatched.len());
+        for sidecar in unmatched.iter().take(5) {
+            println!("     {}", sidecar);
+        }
+        if unmatched.len() > 5 {
+            println!("     ... and {} more", unmatched.len() - 5);
+        }
+    }
+    if !unmatchable.is_empty() {
+        // No capture time, camera settings or manifest entry to go by
+        println!("  ⚠️  Unmatchable, written before capture times were recorded: {}", unmatchable.len());
+        for sidecar in unmatchable.iter().take(5) {
+            println!("     {}", sidecar);
+        }
+        if unmatchable.len() > 5 {
+            println!("     ... and {} more", unmatchable.len() - 5);
+        }
+    }
+    if !errors.is_empty() {
+        println!("  ❌ Errors: {}", errors.len());
+        for error in errors.iter().take(5) {
+            println!("     {}", error);
+        }
+    }
+    
+    Ok(())
+}
+
+fn read_orphan(sidecar: &Path, manifest: &Manifest) -> Result<Orphan> {
+    let frontmatter = read_sidecar_metadata(sidecar)?;
+    let get = |key: &str| frontmatter.get(key).and_then(|v| v.as_str()).map(str::to_string);
+    let settings = frontmatter.get("settings")
+        .and_then(|v| v.as_mapping())
+        .map(|settings| settings.iter()
+            .filter_map(|(key, value)| Some((key.as_str()?.to_string(), yaml_text(value)?)))
+            .collect())
+        .unwrap_or_default();
+    
+    // Sidecars are named after their photo: IMG_1234.CR2.md
+    let old_photo = sidecar.with_extension("");
+    let filename = get("filename")
+        .or_else(|| old_photo.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    Ok(Orphan {
+        sidecar: sidecar.to_path_buf(),
+        recorded: manifest.recorded(&old_photo)?,
+        old_photo,
+        filename,
+        captured: get("captured"),
+        serial: get("camera_serial"),
+        camera: get("camera"),
+        settings,
+    })
+}
+
+/// A scalar as text, whether it was written quoted or not
+fn yaml_text(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn move_sidecar(orphan: &Orphan, photo: &PhotoFile, manifest: &Manifest) -> Result<()> {
+    if photo.sidecar_path.exists() {
+        return Err(anyhow::anyhow!("{} already exists", photo.sidecar_path.display()));
+    }
+    fs::rename(&orphan.sidecar, &photo.sidecar_path)?;
+    manifest.moved(&orphan.old_photo, &photo.path)?;
+    if orphan.filename != photo.filename {
+        update_frontmatter(&photo.sidecar_path, manifest, |frontmatter| {
+            frontmatter.insert("filename".into(), photo.filename.clone().into());
+            true
+        })?;
+    }
+    Ok(())
+}
+
+fn relative(root: &str, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).display().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn orphan(captured: Option<&str>, settings: &[(&str, &str)]) -> Orphan {
+        Orphan {
+            sidecar: PathBuf::from("old/DSC_0001.NEF.md"),
+            old_photo: PathBuf::from("old/DSC_0001.NEF"),
+            filename: "DSC_0001.NEF".to_string(),
+            captured: captured.map(str::to_string),
+            serial: None,
+            camera: Some("NIKON Z 6".to_string()),
+            settings: settings.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            recorded: None,
+        }
+    }
+    
+    fn candidate(name: &str, settings: &[(&str, &str)]) -> Candidate {
+        let facts = ExifFacts {
+            captured: Some("2024-05-01T10:00:00".to_string()),
+            serial: None,
+            camera: Some("NIKON Z 6".to_string()),
+            settings: settings.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        };
+        Candidate {
+            photo: PhotoFile::new(PathBuf::from("new").join(name)).unwrap(),
+            size: 1,
+            hash: OnceCell::from(None),
+            exif: OnceCell::from(facts),
+        }
+    }
+    
+    #[test]
+    fn sidecars_without_a_capture_time_match_by_name_and_settings() {
+        let settings = [("iso", "400"), ("aperture", "f/2.8")];
+        let old = orphan(None, &settings);
+        assert_eq!(candidate("DSC_0001.NEF", &settings).matches(&old), Some(MatchKind::NameAndSettings));
+        assert_eq!(candidate("DSC_0001.NEF", &[("iso", "800"), ("aperture", "f/2.8")]).matches(&old), None);
+        assert_eq!(candidate("DSC_0002.NEF", &settings).matches(&old), None);
+        assert!(!old.unmatchable());
+        
+        // Recorded capture times still decide when there are some
+        assert_eq!(candidate("DSC_0001.NEF", &[]).matches(&orphan(Some("2024-05-01T10:00:00"), &[])), Some(MatchKind::NameAndTime));
+        assert!(orphan(None, &[]).unmatchable());
+    }
+}
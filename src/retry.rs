@@ -0,0 +1,165 @@
+use anyhow::Result;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::ai_queue::interrupted;
+
+/// A failure worth retrying: the server was unreachable, timed out or returned a 5xx
+#[derive(Debug)]
+pub struct TransientError(pub String);
+
+impl fmt::Display for TransientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<TransientError>().is_some()
+}
+
+/// How often and how patiently to retry transient failures
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff: the delay doubles with every retry, up to `max_delay`
+    pub fn delay_for(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.initial_delay.saturating_mul(factor).min(self.max_delay)
+    }
+    
+    /// Run `op`, retrying transient errors with backoff
+    pub fn run<T>(&self, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut retry = 0;
+        loop {
+            match op() {
+                Ok(value) => return Ok(value),
+                Err(e) if is_transient(&e) && retry < self.max_retries && !interrupted() => {
+                    let delay = self.delay_for(retry);
+                    retry += 1;
+                    println!("  ⏳ {} - retrying in {}s ({}/{})", e, delay.as_secs(), retry, self.max_retries);
+                    sleep_interruptible(delay);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Takes a service out of rotation when it keeps failing instead of burning through
+/// the queue, and says when to check whether it's back
+pub struct CircuitBreaker {
+    threshold: u32,
+    consecutive_failures: u32,
+    probe_interval: Duration,
+    last_probe: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, probe_interval: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            consecutive_failures: 0,
+            probe_interval,
+            last_probe: None,
+        }
+    }
+    
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.last_probe = None;
+    }
+    
+    /// Record a transient failure; returns true while the breaker is tripped
+    pub fn record_failure(&mut self) -> bool {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures == self.threshold {
+            self.last_probe = Some(Instant::now());
+        }
+        self.is_open()
+    }
+    
+    /// Whether the service is out of rotation
+    pub fn is_open(&self) -> bool {
+        self.consecutive_failures >= self.threshold
+    }
+    
+    /// Whether it's time to check on the service of an open breaker again; a true
+    /// answer counts as checking, so only one caller probes at a time
+    pub fn probe_due(&mut self) -> bool {
+        match self.last_probe {
+            Some(probed) if probed.elapsed() < self.probe_interval => false,
+            _ => {
+                self.last_probe = Some(Instant::now());
+                true
+            }
+        }
+    }
+    
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+    
+    pub fn probe_interval(&self) -> Duration {
+        self.probe_interval
+    }
+}
+
+/// Sleep in short slices so Ctrl-C isn't held up by a long backoff
+pub fn sleep_interruptible(duration: Duration) {
+    let step = Duration::from_millis(250);
+    let mut remaining = duration;
+    while !remaining.is_zero() && !interrupted() {
+        let slice = remaining.min(step);
+        thread::sleep(slice);
+        remaining -= slice;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn circuit_breaker_trips_and_resets() {
+        let mut breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        assert!(!breaker.record_failure());
+        assert!(!breaker.record_failure());
+        assert!(breaker.record_failure());
+        assert!(breaker.is_open());
+        // Just tripped, so the first probe waits for the interval
+        assert!(!breaker.probe_due());
+        
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert_eq!(breaker.consecutive_failures(), 0);
+    }
+    
+    #[test]
+    fn probes_once_the_interval_has_passed() {
+        let mut breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        assert!(breaker.record_failure());
+        assert!(!breaker.probe_due());
+        thread::sleep(Duration::from_millis(30));
+        assert!(breaker.probe_due());
+        // That counted as the probe, so the next caller waits again
+        assert!(!breaker.probe_due());
+    }
+}
@@ -0,0 +1,372 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Instant;
+use crate::ai_queue::{install_interrupt_handler, interrupted};
+use crate::dupes::{difference_hash, preview_image};
+use crate::exif_reader::read_exif;
+use crate::groups::{PhotoGroup, group_photos};
+use crate::manifest::Manifest;
+use crate::photo_walker::PhotoWalker;
+use crate::quality::sharpness;
+use crate::sidecar_reader::read_sidecar_metadata;
+use crate::sidecar_writer::{store_block, update_frontmatter};
+
+/// Frames further apart than this (in seconds) start a new sequence by default
+pub const DEFAULT_MAX_GAP: f64 = 1.0;
+
+/// Neighbouring frames whose perceptual hashes differ in more bits than this belong
+/// to different sequences by default; looser than for duplicates, since the
+/// subject moves during a burst
+pub const DEFAULT_MAX_DISTANCE: u32 = 12;
+
+/// Fewer frames than this are not a sequence by default
+pub const DEFAULT_MIN_FRAMES: usize = 3;
+
+/// A photo's place in a burst or sequence, stored as `sequence:` in the sidecar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceInfo {
+    /// Capture time and name of the first frame: `20240315-143000-IMG_1234`
+    pub id: String,
+    /// Position in the sequence, from 1
+    pub frame: usize,
+    pub frames: usize,
+    /// 1 for the sharpest frame of the sequence
+    pub rank: usize,
+}
+
+impl SequenceInfo {
+    /// Read a `sequence:` block back from a sidecar's frontmatter
+    pub fn from_frontmatter(frontmatter: &Mapping) -> Option<SequenceInfo> {
+        let sequence = frontmatter.get("sequence")?.as_mapping()?;
+        let number = |key: &str| sequence.get(key).and_then(|v| v.as_u64()).map(|n| n as usize);
+        Some(SequenceInfo {
+            id: sequence.get("id")?.as_str()?.to_string(),
+            frame: number("frame")?,
+            frames: number("frames")?,
+            rank: number("rank")?,
+        })
+    }
+    
+    /// The `sequence:` block as written to the sidecar
+    pub fn to_yaml(&self) -> Mapping {
+        let mut sequence = Mapping::new();
+        sequence.insert("id".into(), self.id.clone().into());
+        sequence.insert("frame".into(), (self.frame as u64).into());
+        sequence.insert("frames".into(), (self.frames as u64).into());
+        sequence.insert("rank".into(), (self.rank as u64).into());
+        sequence
+    }
+}
+
+/// One picture of a possible sequence: a photo, or a RAW+JPEG pair with its edits
+struct Frame {
+    photos: PhotoGroup,
+    captured: DateTime<Local>,
+    /// Serial number, or make and model when the camera doesn't record one
+    camera: String,
+    dhash: Option<u64>,
+    sharpness: f64,
+}
+
+/// Find bursts and sequences: frames from the same camera taken at most `max_gap`
+/// seconds apart that look alike, and write their `sequence:` to the sidecars.
+/// Frames are ranked by sharpness, so `update --ai-sequence-top` and
+/// `quality assess --sequence-top` can skip all but the best of each.
+pub fn detect_sequences(
+    photo_dir: &str,
+    max_gap: f64,
+    max_distance: u32,
+    min_frames: usize,
+    dry_run: bool,
+    show_progress: bool,
+) -> Result<()> {
+    println!("🎞️  Looking for bursts and sequences in: {}\n", photo_dir);
+    
+    let start_time = Instant::now();
+    install_interrupt_handler()?;
+    let manifest = Manifest::open(photo_dir)?;
+    
+    // Only photos with a sidecar have somewhere to record their sequence
+    let groups: Vec<PhotoGroup> = group_photos(PhotoWalker::new(photo_dir, false).find_photos()?)
+        .into_iter()
+        .filter(|group| sidecars(group).next().is_some())
+        .collect();
+    let total = groups.len();
+    
+    let mut frames = Vec::new();
+    let mut no_capture_time = 0;
+    let mut errors = Vec::new();
+    for (i, photos) in groups.iter().enumerate() {
+        if interrupted() {
+            break;
+        }
+        if show_progress && ((i + 1) % 50 == 0 || i + 1 == total) {
+            println!("  Reading capture times: {}/{} ({:.1}%)", i + 1, total, (i + 1) as f64 / total as f64 * 100.0);
+        }
+        let exif = match read_exif(&photos.master.path) {
+            Ok(exif) => exif,
+            Err(_) => {
+                no_capture_time += 1;
+                continue;
+            }
+        };
+        let Some(captured) = exif.date_taken else {
+            no_capture_time += 1;
+            continue;
+        };
+        let camera = exif.camera_serial
+            .or_else(|| Some(format!("{} {}", exif.camera_make?, exif.camera_model?)))
+            .unwrap_or_default();
+        frames.push(Frame { photos: photos.clone(), captured, camera, dhash: None, sharpness: 0.0 });
+    }
+    frames.sort_by(|a, b| {
+        a.camera.cmp(&b.camera)
+            .then(a.captured.cmp(&b.captured))
+            .then_with(|| a.photos.master.path.cmp(&b.photos.master.path))
+    });
+    
+    // Runs of frames close enough in time, then split where the picture changes
+    let mut sequences: Vec<Vec<Frame>> = Vec::new();
+    for mut run in split_by_time(frames, max_gap) {
+        if interrupted() {
+            break;
+        }
+        if run.len() < min_frames {
+            continue;
+        }
+        for frame in &mut run {
+            match preview_image(&frame.photos.master) {
+                Ok(Some(image)) => {
+                    frame.dhash = Some(difference_hash(&image));
+                    frame.sharpness = sharpness(&image);
+                }
+                Ok(None) => {}
+                Err(e) => errors.push(format!("{}: {:#}", frame.photos.master.filename, e)),
+            }
+        }
+        sequences.extend(split_by_similarity(run, max_distance).into_iter().filter(|s| s.len() >= min_frames));
+    }
+    
+    let mut members = HashSet::new();
+    let mut updated = 0;
+    let mut frame_count = 0;
+    for sequence in &sequences {
+        let first = &sequence[0];
+        let id = format!("{}-{}", first.captured.format("%Y%m%d-%H%M%S"), stem(&first.photos.master.filename));
+        let sharpest = ranks(sequence);
+        let duration = (sequence[sequence.len() - 1].captured - first.captured).num_milliseconds() as f64 / 1000.0;
+        println!("  🎞️  {}: {} frames over {:.1}s, sharpest {}",
+            id, sequence.len(), duration, sequence[sharpest[0]].photos.master.filename);
+        frame_count += sequence.len();
+        
+        for (frame, photos) in sequence.iter().enumerate() {
+            let info = SequenceInfo {
+                id: id.clone(),
+                frame: frame + 1,
+                frames: sequence.len(),
+                rank: sharpest.iter().position(|&i| i == frame).unwrap_or(frame) + 1,
+            };
+            for sidecar in sidecars(&photos.photos) {
+                members.insert(sidecar.to_path_buf());
+                match write_sequence(sidecar, &info, dry_run, &manifest) {
+                    Ok(true) => updated += 1,
+                    Ok(false) => {}
+                    Err(e) => errors.push(format!("{}: {}", sidecar.display(), e)),
+                }
+            }
+        }
+    }
+    
+    // Photos that are no longer part of a sequence, e.g. after changing --max-gap
+    let mut removed = 0;
+    if !interrupted() {
+        for sidecar in groups.iter().flat_map(sidecars).filter(|s| !members.contains(*s)) {
+            match remove_sequence(sidecar, dry_run, &manifest) {
+                Ok(true) => removed += 1,
+                Ok(false) => {}
+                Err(e) => errors.push(format!("{}: {}", sidecar.display(), e)),
+            }
+        }
+    }
+    
+    println!("\n📊 Sequence Summary:");
+    println!("  🎞️  Sequences: {} ({} frames)", sequences.len(), frame_count);
+    if dry_run {
+        println!("  ✏️  Sidecars that would be updated: {}", updated + removed);
+    } else {
+        println!("  ✏️  Sidecars updated: {}", updated);
+        if removed > 0 {
+            println!("  🧹 No longer in a sequence: {}", removed);
+        }
+    }
+    if no_capture_time > 0 {
+        println!("  ⏭️  Without a capture time: {}", no_capture_time);
+    }
+    if !errors.is_empty() {
+        println!("  ❌ Errors: {}", errors.len());
+        for error in errors.iter().take(5) {
+            println!("     {}", error);
+        }
+    }
+    if interrupted() {
+        println!("\n⏸️  Stopped early. Run sequences again to finish.");
+    }
+    println!("  Total time: {:.2}s", start_time.elapsed().as_secs_f64());
+    
+    Ok(())
+}
+
+/// Split sorted frames wherever the camera changes or more than `max_gap` seconds pass
+fn split_by_time(frames: Vec<Frame>, max_gap: f64) -> Vec<Vec<Frame>> {
+    let mut runs: Vec<Vec<Frame>> = Vec::new();
+    for frame in frames {
+        let continues = runs.last().and_then(|run| run.last()).is_some_and(|previous| {
+            previous.camera == frame.camera
+                && (frame.captured - previous.captured).num_milliseconds() as f64 <= max_gap * 1000.0
+        });
+        match runs.last_mut() {
+            Some(run) if continues => run.push(frame),
+            _ => runs.push(vec![frame]),
+        }
+    }
+    runs
+}
+
+/// Split a run of frames where one looks too different from the one before it.
+/// Frames that couldn't be looked at stay with their neighbours.
+fn split_by_similarity(run: Vec<Frame>, max_distance: u32) -> Vec<Vec<Frame>> {
+    let mut sequences: Vec<Vec<Frame>> = Vec::new();
+    for frame in run {
+        let continues = sequences.last().and_then(|sequence| sequence.last()).is_some_and(|previous| {
+            match (previous.dhash, frame.dhash) {
+                (Some(a), Some(b)) => (a ^ b).count_ones() <= max_distance,
+                _ => true,
+            }
+        });
+        match sequences.last_mut() {
+            Some(sequence) if continues => sequence.push(frame),
+            _ => sequences.push(vec![frame]),
+        }
+    }
+    sequences
+}
+
+/// Frame positions from sharpest to softest; the earlier frame wins a tie
+fn ranks(sequence: &[Frame]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..sequence.len()).collect();
+    order.sort_by(|&a, &b| sequence[b].sharpness.total_cmp(&sequence[a].sharpness).then(a.cmp(&b)));
+    order
+}
+
+/// Sidecars of the files of a group that have one
+fn sidecars(photos: &PhotoGroup) -> impl Iterator<Item = &Path> {
+    std::iter::once(&photos.master)
+        .chain(&photos.companions)
+        .filter(|photo| photo.has_sidecar)
+        .map(|photo| photo.sidecar_path.as_path())
+}
+
+/// Store a sequence in a sidecar unless it's already there; whether it wasn't
+fn write_sequence(sidecar: &Path, info: &SequenceInfo, dry_run: bool, manifest: &Manifest) -> Result<bool> {
+    if dry_run {
+        let frontmatter = read_sidecar_metadata(sidecar)?;
+        return Ok(SequenceInfo::from_frontmatter(&frontmatter).as_ref() != Some(info));
+    }
+    update_frontmatter(sidecar, manifest, |frontmatter| {
+        if SequenceInfo::from_frontmatter(frontmatter).as_ref() == Some(info) {
+            return false;
+        }
+        store_block(frontmatter, "sequence", Value::Mapping(info.to_yaml()));
+        true
+    })
+}
+
+/// Drop a sequence from a sidecar; whether there was one
+fn remove_sequence(sidecar: &Path, dry_run: bool, manifest: &Manifest) -> Result<bool> {
+    if dry_run {
+        return Ok(read_sidecar_metadata(sidecar)?.contains_key("sequence"));
+    }
+    update_frontmatter(sidecar, manifest, |frontmatter| frontmatter.remove("sequence").is_some())
+}
+
+fn stem(filename: &str) -> String {
+    Path::new(filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| filename.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use crate::photo_walker::PhotoFile;
+    
+    fn frame(name: &str, seconds: f64, camera: &str, dhash: Option<u64>, sharpness: f64) -> Frame {
+        let start = Local.with_ymd_and_hms(2024, 7, 1, 18, 0, 0).unwrap();
+        let photo = PhotoFile::new(std::env::temp_dir().join(name)).unwrap();
+        Frame {
+            photos: PhotoGroup::from(photo),
+            captured: start + Duration::milliseconds((seconds * 1000.0) as i64),
+            camera: camera.to_string(),
+            dhash,
+            sharpness,
+        }
+    }
+    
+    fn names(runs: &[Vec<Frame>]) -> Vec<Vec<String>> {
+        runs.iter()
+            .map(|run| run.iter().map(|f| f.photos.master.filename.clone()).collect())
+            .collect()
+    }
+    
+    #[test]
+    fn splits_on_time_gaps_and_camera_changes() {
+        let frames = vec![
+            frame("a.jpg", 0.0, "X100", None, 0.0),
+            frame("b.jpg", 0.5, "X100", None, 0.0),
+            frame("c.jpg", 1.5, "X100", None, 0.0),
+            frame("d.jpg", 4.0, "X100", None, 0.0),
+            frame("e.jpg", 4.2, "Z6", None, 0.0),
+        ];
+        assert_eq!(names(&split_by_time(frames, 1.0)), vec![
+            vec!["a.jpg", "b.jpg", "c.jpg"],
+            vec!["d.jpg"],
+            vec!["e.jpg"],
+        ]);
+    }
+    
+    #[test]
+    fn splits_where_a_frame_looks_different() {
+        let run = vec![
+            frame("a.jpg", 0.0, "X100", Some(0b0000), 0.0),
+            frame("b.jpg", 0.1, "X100", Some(0b0001), 0.0),
+            frame("c.jpg", 0.2, "X100", None, 0.0),
+            frame("d.jpg", 0.3, "X100", Some(0b1111_0001), 0.0),
+            frame("e.jpg", 0.4, "X100", Some(0b1111_0011), 0.0),
+        ];
+        // c couldn't be hashed, so it stays with b; d is compared with c and stays too
+        assert_eq!(names(&split_by_similarity(run, 1)), vec![vec!["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg"]]);
+        
+        let run = vec![
+            frame("a.jpg", 0.0, "X100", Some(0b0000), 0.0),
+            frame("b.jpg", 0.1, "X100", Some(0b0001), 0.0),
+            frame("c.jpg", 0.2, "X100", Some(0b1111_0001), 0.0),
+        ];
+        assert_eq!(names(&split_by_similarity(run, 1)), vec![vec!["a.jpg", "b.jpg"], vec!["c.jpg"]]);
+    }
+    
+    #[test]
+    fn ranks_sharpest_first_and_earlier_frames_win_ties() {
+        let sequence = vec![
+            frame("a.jpg", 0.0, "X100", None, 10.0),
+            frame("b.jpg", 0.1, "X100", None, 30.0),
+            frame("c.jpg", 0.2, "X100", None, 10.0),
+            frame("d.jpg", 0.3, "X100", None, 20.0),
+        ];
+        assert_eq!(ranks(&sequence), vec![1, 3, 0, 2]);
+    }
+}
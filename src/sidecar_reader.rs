@@ -2,6 +2,11 @@ use anyhow::{Result, Context};
 use std::fs;
 use std::path::Path;
 use serde_yaml::Value;
+use crate::ocr::{read_ocr_result, OcrResult};
+use crate::quality::QualityAssessment;
+use crate::sequences::SequenceInfo;
+use crate::sidecar_writer::default_body;
+use crate::vision::{AiProvenance, VisionAnalysis};
 
 /// Read metadata from an existing sidecar file
 pub fn read_sidecar_metadata(sidecar_path: &Path) -> Result<serde_yaml::Mapping> {
@@ -32,12 +37,100 @@ pub fn get_sidecar_rating(sidecar_path: &Path) -> Option<i64> {
         .and_then(|v| v.as_i64())
 }
 
-/// Check if sidecar has AI metadata
-pub fn has_ai_metadata(sidecar_path: &Path) -> bool {
+/// Whether anything was written below the frontmatter besides the generated title
+/// and placeholder
+pub fn has_notes(sidecar_path: &Path) -> Result<bool> {
+    let content = fs::read_to_string(sidecar_path)
+        .with_context(|| format!("Failed to read sidecar: {}", sidecar_path.display()))?;
+    let Some((_, body)) = content.strip_prefix("---\n").and_then(|rest| rest.split_once("\n---\n")) else {
+        // Not one of ours: someone's own notes file
+        return Ok(true);
+    };
+    
+    // The title comes from the photo's name, which the sidecar's name still carries
+    let filename = sidecar_path.with_extension("");
+    let filename = filename.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    Ok(body.trim() != default_body(filename).trim())
+}
+
+/// Read previously generated AI analysis back from a sidecar, so rewriting
+/// the sidecar doesn't throw away expensive results
+pub fn read_ai_analysis(sidecar_path: &Path) -> Option<VisionAnalysis> {
+    let metadata = read_sidecar_metadata(sidecar_path).ok()?;
+    let description = metadata.get("ai_description")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let tags = string_list(&metadata, "ai_tags");
+    
+    if description.is_empty() && tags.is_empty() {
+        return None;
+    }
+    
+    Some(VisionAnalysis {
+        description,
+        tags,
+        subjects: string_list(&metadata, "ai_subjects"),
+        setting: string_value(&metadata, "ai_setting"),
+        mood: string_value(&metadata, "ai_mood"),
+        dominant_colors: string_list(&metadata, "ai_colors"),
+        text_in_image: string_value(&metadata, "ai_text_in_image"),
+        provenance: read_provenance(&metadata),
+    })
+}
+
+/// Read the result of an earlier OCR pass back from a sidecar
+pub fn read_ocr(sidecar_path: &Path) -> Option<OcrResult> {
+    read_ocr_result(&read_sidecar_metadata(sidecar_path).ok()?)
+}
+
+/// Read the result of an earlier quality pass back from a sidecar
+pub fn read_quality(sidecar_path: &Path) -> Option<QualityAssessment> {
+    QualityAssessment::from_frontmatter(&read_sidecar_metadata(sidecar_path).ok()?)
+}
+
+/// Read the sequence a photo was found to be part of back from a sidecar
+pub fn read_sequence(sidecar_path: &Path) -> Option<SequenceInfo> {
+    SequenceInfo::from_frontmatter(&read_sidecar_metadata(sidecar_path).ok()?)
+}
+
+/// Files listed as companions of the photo in its sidecar
+pub fn read_companions(sidecar_path: &Path) -> Vec<String> {
     read_sidecar_metadata(sidecar_path)
-        .ok()
-        .map(|metadata| {
-            metadata.contains_key("ai_description") || metadata.contains_key("ai_tags")
+        .map(|metadata| string_list(&metadata, "companions"))
+        .unwrap_or_default()
+}
+
+fn read_provenance(metadata: &serde_yaml::Mapping) -> AiProvenance {
+    let Some(ai) = metadata.get("ai").and_then(|v| v.as_mapping()) else {
+        return AiProvenance::default();
+    };
+    
+    AiProvenance {
+        backend: string_value(ai, "backend").unwrap_or_default(),
+        model: string_value(ai, "model").unwrap_or_default(),
+        model_digest: string_value(ai, "model_digest"),
+        prompt_version: string_value(ai, "prompt_version"),
+        generated: string_value(ai, "generated"),
+        source_image: string_value(ai, "source_image"),
+        duration_secs: ai.get("duration_secs").and_then(|v| v.as_f64()),
+    }
+}
+
+fn string_value(metadata: &serde_yaml::Mapping, key: &str) -> Option<String> {
+    metadata.get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
+fn string_list(metadata: &serde_yaml::Mapping, key: &str) -> Vec<String> {
+    metadata.get(key)
+        .and_then(|v| v.as_sequence())
+        .map(|items| {
+            items.iter()
+                .filter_map(|v| v.as_str())
+                .map(|s| s.to_string())
+                .collect()
         })
-        .unwrap_or(false)
-}
\ No newline at end of file
+        .unwrap_or_default()
+}
@@ -2,11 +2,117 @@ use anyhow::{Result, Context};
 use std::fs;
 use std::path::Path;
 use std::time::Instant;
+use crate::groups::{PhotoGroup, group_of, group_photos};
+use crate::manifest::Manifest;
 use crate::metadata_merger::PhotoMetadata;
+use crate::ollama_vision::model_name_matches;
+use crate::photo_walker::PhotoFile;
+use crate::ai_queue::{AiQueue, install_interrupt_handler, interrupted};
+use crate::retry::RetryPolicy;
+use crate::vision::{AiProvenance, ContextField, VisionAnalysis, VisionBackend};
+use crate::vocabulary::{TagReview, UnknownTags, Vocabulary};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+/// Failed AI jobs are retried until they have been attempted this many times
+pub const DEFAULT_MAX_ATTEMPTS: i64 = 3;
+
+/// Why an AI job was skipped when its sidecar has notes or edits to keep
+const HAND_EDITED: &str = "sidecar edited by hand since it was written (update --force to redo it)";
+
+/// Settings for the AI stage of a run
+#[derive(Clone)]
+pub struct AiOptions {
+    /// One backend per endpoint; requests are spread across them round-robin
+    pub backends: Vec<Arc<dyn VisionBackend>>,
+    pub workers: usize,
+    pub min_rating: Option<i32>,
+    pub max_attempts: i64,
+    pub retry: RetryPolicy,
+    pub reanalyze: ReanalyzePolicy,
+    /// Only analyze the N sharpest frames of each burst or sequence
+    pub sequence_top: Option<usize>,
+    /// Metadata fields passed to the model along with the image
+    pub context_fields: Vec<ContextField>,
+    /// Controlled vocabulary applied to the AI tags
+    pub vocabulary: Option<Vocabulary>,
+    /// Reuse results for photos whose content was analyzed before
+    pub use_cache: bool,
+}
+
+/// Which photos that already have AI data should be analyzed again
+#[derive(Debug, Clone, Default)]
+pub struct ReanalyzePolicy {
+    /// Re-analyze results generated before this date (or of unknown age)
+    pub older_than: Option<NaiveDate>,
+    /// Re-analyze results from a different model, or a different build of the same model
+    pub model_changed: bool,
+}
+
+impl ReanalyzePolicy {
+    pub fn is_active(&self) -> bool {
+        self.older_than.is_some() || self.model_changed
+    }
+}
+
+impl AiOptions {
+    /// Whether an earlier analysis should be replaced under the re-analysis policy
+    pub fn is_stale(&self, previous: &AiProvenance) -> bool {
+        if let Some(cutoff) = self.reanalyze.older_than {
+            let generated = previous.generated.as_deref()
+                .and_then(|g| NaiveDateTime::parse_from_str(g, "%Y-%m-%dT%H:%M:%S").ok());
+            match generated {
+                Some(generated) if generated.date() >= cutoff => {}
+                _ => return true,
+            }
+        }
+        
+        if self.reanalyze.model_changed && let Some(backend) = self.backends.first() {
+            // `qwen2.5vl` and `qwen2.5vl:latest` are the same model
+            let model = backend.model();
+            if !model_name_matches(model, &previous.model) && !model_name_matches(&previous.model, model) {
+                return true;
+            }
+            if let (Some(previous_digest), Some(digest)) = (&previous.model_digest, backend.model_digest())
+                && *previous_digest != digest
+            {
+                return true;
+            }
+        }
+        
+        false
+    }
+    
+    pub fn model_name(&self) -> String {
+        self.backends.first()
+            .map(|b| format!("{} ({})", b.model(), b.kind().name()))
+            .unwrap_or_default()
+    }
+}
 
 pub struct SidecarWriter {
     pub files_written: usize,
     pub files_skipped: usize,
+    pub ai_analyzed: usize,
+    pub ai_below_rating: usize,
+    /// Frames of a sequence not among its sharpest
+    pub ai_outside_top: usize,
+    pub ai_failed: usize,
+    pub ai_cached: usize,
+    /// Photos that changed since their sidecar was written
+    pub sources_changed: usize,
+    /// Sidecars edited by hand since they were written, left alone
+    pub hand_edited: usize,
+    /// Hand-edited sidecars kept although their photo changed
+    pub kept_despite_changes: Vec<String>,
+    /// Files listed in their master's sidecar instead of getting their own
+    pub companions: usize,
+    /// Companions with a sidecar of their own from before they were grouped
+    pub companion_sidecars: usize,
     pub errors: Vec<String>,
 }
 
@@ -15,48 +121,65 @@ impl SidecarWriter {
         SidecarWriter {
             files_written: 0,
             files_skipped: 0,
+            ai_analyzed: 0,
+            ai_below_rating: 0,
+            ai_outside_top: 0,
+            ai_failed: 0,
+            ai_cached: 0,
+            sources_changed: 0,
+            hand_edited: 0,
+            kept_despite_changes: Vec::new(),
+            companions: 0,
+            companion_sidecars: 0,
             errors: Vec::new(),
         }
     }
     
-    /// Write a sidecar .md file for a photo
-    pub fn write_sidecar(&mut self, sidecar_path: &Path, metadata: &PhotoMetadata, force: bool) -> Result<()> {
-        // Skip if sidecar already exists and not forcing
-        if sidecar_path.exists() && !force {
+    /// Write a sidecar .md file for a photo and record it in the manifest
+    pub fn write_sidecar(&mut self, photo: &PhotoFile, metadata: &PhotoMetadata, force: bool, manifest: &Manifest) -> Result<()> {
+        if write_sidecar_file(&photo.sidecar_path, metadata, force)? {
+            self.files_written += 1;
+            manifest.record(&photo.path, &photo.sidecar_path)?;
+        } else {
             self.files_skipped += 1;
-            return Ok(());
         }
-        
-        // Generate the content
-        let yaml_frontmatter = metadata.to_yaml_frontmatter()
-            .context("Failed to generate YAML frontmatter")?;
-        
-        // Add a default title based on filename (can be edited later)
-        let title = Path::new(&metadata.filename)
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("Untitled")
-            .replace('_', " ")
-            .replace('-', " ");
-        
-        let content = format!(
-            "{}\n# {}\n\n<!-- Add your personal notes about this photo here -->\n",
-            yaml_frontmatter,
-            title
-        );
-        
-        // Write the file
-        fs::write(sidecar_path, content)
-            .with_context(|| format!("Failed to write sidecar: {}", sidecar_path.display()))?;
-        
-        self.files_written += 1;
         Ok(())
     }
     
     pub fn print_summary(&self, elapsed: std::time::Duration) {
         println!("\n📊 Sidecar Generation Summary:");
         println!("  ✅ Files written: {}", self.files_written);
-        println!("  ⏭️  Files skipped (already exist): {}", self.files_skipped);
+        println!("  ⏭️  Files skipped (unchanged): {}", self.files_skipped);
+        if self.sources_changed > 0 {
+            println!("  🔁 Photos changed since their sidecar was written: {}", self.sources_changed);
+        }
+        if self.hand_edited > 0 {
+            println!("  ✋ Sidecars edited by hand, left alone: {} (use --force to overwrite)", self.hand_edited);
+            for filename in self.kept_despite_changes.iter().take(5) {
+                println!("     Photo changed since: {}", filename);
+            }
+            if self.kept_despite_changes.len() > 5 {
+                println!("     ... and {} more", self.kept_despite_changes.len() - 5);
+            }
+        }
+        if self.companions > 0 {
+            println!("  🔗 Companion files listed in their master's sidecar: {}", self.companions);
+            if self.companion_sidecars > 0 {
+                println!("     {} still have a sidecar of their own from before they were grouped", self.companion_sidecars);
+            }
+        }
+        
+        if self.ai_analyzed + self.ai_below_rating + self.ai_outside_top + self.ai_failed > 0 {
+            println!("  🤖 AI analyzed: {}", self.ai_analyzed);
+            if self.ai_cached > 0 {
+                println!("  ♻️  AI results from cache: {}", self.ai_cached);
+            }
+            println!("  ⏭️  AI skipped (below rating): {}", self.ai_below_rating);
+            if self.ai_outside_top > 0 {
+                println!("  ⏭️  AI skipped (not among the sharpest of their sequence): {}", self.ai_outside_top);
+            }
+            println!("  ⚠️  AI failed: {}", self.ai_failed);
+        }
         
         if !self.errors.is_empty() {
             println!("  ❌ Errors: {}", self.errors.len());
@@ -80,17 +203,241 @@ impl SidecarWriter {
     }
 }
 
+/// The notes part of a new sidecar: a title based on the filename (can be edited
+/// later) and a placeholder for personal notes
+pub fn default_body(filename: &str) -> String {
+    let title = Path::new(filename)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Untitled")
+        .replace('_', " ")
+        .replace('-', " ");
+    format!("\n# {}\n\n<!-- Add your personal notes about this photo here -->\n", title)
+}
+
+/// Write a sidecar .md file for a photo; returns false if it already exists and
+/// isn't forced
+fn write_sidecar_file(sidecar_path: &Path, metadata: &PhotoMetadata, force: bool) -> Result<bool> {
+    // Skip if sidecar already exists and not forcing
+    if sidecar_path.exists() && !force {
+        return Ok(false);
+    }
+    
+    // Generate the content
+    let yaml_frontmatter = metadata.to_yaml_frontmatter()
+        .context("Failed to generate YAML frontmatter")?;
+    
+    let content = format!("{}{}", yaml_frontmatter, default_body(&metadata.filename));
+    
+    write_atomically(sidecar_path, &content)?;
+    Ok(true)
+}
+
+/// Write a file via a temporary file renamed into place, so a crash never leaves
+/// a half-written sidecar behind
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = Path::new(&tmp_name);
+    fs::write(tmp_path, content)
+        .with_context(|| format!("Failed to write sidecar: {}", tmp_path.display()))?;
+    fs::rename(tmp_path, path)
+        .with_context(|| format!("Failed to write sidecar: {}", path.display()))?;
+    Ok(())
+}
+
+/// Store a block such as `quality:` in the frontmatter, replacing an earlier one,
+/// ahead of the GPS and source fields like a freshly generated sidecar
+pub fn store_block(frontmatter: &mut serde_yaml::Mapping, key: &str, block: serde_yaml::Value) {
+    let old = std::mem::take(frontmatter);
+    let mut pending = Some(block);
+    for (existing_key, value) in old {
+        if existing_key.as_str() == Some(key) {
+            continue;
+        }
+        if matches!(existing_key.as_str(), Some("gps" | "_metadata_sources"))
+            && let Some(block) = pending.take()
+        {
+            frontmatter.insert(key.into(), block);
+        }
+        frontmatter.insert(existing_key, value);
+    }
+    if let Some(block) = pending {
+        frontmatter.insert(key.into(), block);
+    }
+}
+
+/// Set the `companions:` list in the frontmatter, right after the filename like in
+/// a freshly generated sidecar, or drop it when there are none
+pub fn store_companions(frontmatter: &mut serde_yaml::Mapping, companions: &[String]) {
+    let old = std::mem::take(frontmatter);
+    let mut pending = (!companions.is_empty()).then(|| companions.to_vec());
+    for (key, value) in old {
+        match key.as_str() {
+            Some("companions") => continue,
+            Some("filename") => {
+                frontmatter.insert(key, value);
+                if let Some(companions) = pending.take() {
+                    frontmatter.insert("companions".into(), companions.into());
+                }
+            }
+            _ => {
+                frontmatter.insert(key, value);
+            }
+        }
+    }
+    if let Some(companions) = pending {
+        frontmatter.insert("companions".into(), companions.into());
+    }
+}
+
+/// Replace the `ai_*` fields and the `ai:` provenance in the frontmatter with those
+/// of a fresh analysis, leaving every other field as it is
+pub fn store_ai_fields(frontmatter: &mut serde_yaml::Mapping, fresh: &serde_yaml::Mapping) {
+    let is_ai = |key: &serde_yaml::Value| key.as_str().is_some_and(|k| k == "ai" || k.starts_with("ai_"));
+    let fields: Vec<_> = fresh.iter()
+        .filter(|(key, _)| is_ai(key))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    
+    // The new fields go where the old ones were, or ahead of the OCR, quality,
+    // sequence, GPS and source fields like in a freshly generated sidecar
+    let old = std::mem::take(frontmatter);
+    let mut pending = Some(fields);
+    for (key, mut value) in old {
+        let follows = matches!(
+            key.as_str(),
+            Some("text_in_image" | "ocr" | "quality" | "sequence" | "gps" | "_metadata_sources")
+        );
+        if (is_ai(&key) || follows) && let Some(fields) = pending.take() {
+            frontmatter.extend(fields);
+        }
+        if is_ai(&key) {
+            continue;
+        }
+        if key.as_str() == Some("_metadata_sources")
+            && let Some(sources) = value.as_str()
+            && !sources.split(", ").any(|source| source == "ai")
+        {
+            value = format!("{}, ai", sources).into();
+        }
+        frontmatter.insert(key, value);
+    }
+    if let Some(fields) = pending {
+        frontmatter.extend(fields);
+    }
+}
+
+/// Edit the YAML frontmatter of an existing sidecar in place, keeping the notes
+/// below it untouched. `edit` returns whether it changed anything; the file is
+/// only rewritten if it did, and the manifest follows the change.
+pub fn update_frontmatter(
+    sidecar_path: &Path,
+    manifest: &Manifest,
+    edit: impl FnOnce(&mut serde_yaml::Mapping) -> bool,
+) -> Result<bool> {
+    let content = fs::read_to_string(sidecar_path)
+        .with_context(|| format!("Failed to read sidecar: {}", sidecar_path.display()))?;
+    let (yaml, body) = content.strip_prefix("---\n")
+        .and_then(|rest| rest.split_once("\n---\n"))
+        .ok_or_else(|| anyhow::anyhow!("Invalid sidecar format - missing YAML frontmatter"))?;
+    
+    let mut frontmatter: serde_yaml::Mapping = serde_yaml::from_str(yaml)
+        .context("Failed to parse YAML frontmatter")?;
+    if !edit(&mut frontmatter) {
+        return Ok(false);
+    }
+    
+    let yaml = serde_yaml::to_string(&frontmatter)?;
+    let updated = format!("---\n{}---\n{}", yaml, body);
+    write_atomically(sidecar_path, &updated)?;
+    // Sidecars are named after their photo: IMG_1234.CR2.md
+    manifest.sidecar_edited(&sidecar_path.with_extension(""), &content, &updated)?;
+    Ok(true)
+}
+
+/// Apply the tag vocabulary to freshly generated AI tags, recording unknown tags
+/// for review if the vocabulary asks for it
+fn normalize_ai_tags(analysis: &mut VisionAnalysis, ai: &AiOptions, review: Option<&TagReview>, photo: &Path) -> Result<()> {
+    let Some(vocabulary) = &ai.vocabulary else {
+        return Ok(());
+    };
+    
+    let normalized = vocabulary.normalize(&analysis.tags);
+    if let Some(review) = review {
+        review.record(photo, &normalized.unknown)?;
+    }
+    analysis.tags = normalized.tags;
+    Ok(())
+}
+
+/// What the update pass did with one photo
+enum UpdateOutcome {
+    Written,
+    Skipped,
+    /// Needs AI analysis; its sidecar is written once analyzed
+    QueueForAi,
+    /// The sidecar was edited by hand since it was written, so it's left alone
+    HandEdited,
+    Failed(String),
+}
+
+/// One photo handled by an update worker
+struct UpdatedPhoto {
+    index: usize,
+    photo: PhotoFile,
+    outcome: UpdateOutcome,
+    /// AI is enabled but the photo is rated too low for it
+    below_rating: bool,
+    /// AI is enabled but the photo isn't among the sharpest frames of its sequence
+    outside_top: bool,
+    /// The photo changed since its sidecar was written
+    source_changed: bool,
+    /// Companions in the photo's group, and how many of them have their own sidecar
+    companions: usize,
+    companion_sidecars: usize,
+}
+
+/// Photos found but not yet processed; keeps the walker from running far ahead
+const UPDATE_QUEUE_SIZE: usize = 256;
+
+/// What an update pass works with, shared by `update` and `watch`
+pub struct UpdateContext<'a> {
+    /// The Lightroom catalog, if there is one
+    pub catalog_path: Option<&'a str>,
+    pub manifest: &'a Manifest,
+    /// Where photos that need AI analysis are queued, when AI is enabled
+    pub queue: Option<&'a AiQueue>,
+    pub skip_existing: bool,
+    pub show_progress: bool,
+    pub jobs: usize,
+    pub ai: Option<&'a AiOptions>,
+    /// Give RAW+JPEG pairs and edits one sidecar, on the master of their group
+    pub group: bool,
+}
+
+/// Worker threads to use: as asked, or one per CPU
+pub fn default_jobs(jobs: Option<usize>) -> usize {
+    jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get())).max(1)
+}
+
 /// Process a directory and generate all sidecar files
+///
+/// The walker streams photos to `jobs` threads that each read EXIF data, look the
+/// photo up in their own catalog connection and write its sidecar. Results are
+/// tallied in walk order, so the summary is the same however the work was split.
+/// Photos that need AI analysis are recorded in the persistent AI queue first and
+/// analyzed afterwards, so an interrupted run can be picked up with `ai resume`.
 pub fn process_directory(
     photo_dir: &str,
     catalog_path: &str,
     skip_existing: bool,
     show_progress: bool,
-    use_ai: bool,
-    ai_min_rating: Option<i32>,
+    jobs: Option<usize>,
+    group: bool,
+    ai: Option<&AiOptions>,
 ) -> Result<()> {
     use crate::photo_walker::PhotoWalker;
-    use crate::metadata_merger::extract_metadata_verbose;
     use rusqlite::Connection;
     
     println!("🚀 Starting sidecar generation for: {}\n", photo_dir);
@@ -98,96 +445,731 @@ pub fn process_directory(
     let start_time = Instant::now();
     let mut writer = SidecarWriter::new();
     
-    // Open Lightroom catalog
-    let lr_conn = Connection::open(catalog_path)?;
+    Connection::open(catalog_path)?;
     println!("✅ Connected to Lightroom catalog");
     
-    // Find all photos
-    let walker = PhotoWalker::new(photo_dir, skip_existing);
-    let photos = walker.find_photos()?;
-    let total_photos = photos.len();
+    let queue = if let Some(ai) = ai {
+        println!("🤖 AI vision analysis enabled with {} (this will be slower)", ai.model_name());
+        Some(AiQueue::open(photo_dir)?)
+    } else {
+        None
+    };
+    install_interrupt_handler()?;
+    let jobs = default_jobs(jobs);
+    if group {
+        println!("🔗 Grouping RAW+JPEG pairs and edits under one sidecar");
+    }
+    println!("📸 Processing photos as they are found ({} workers)\n", jobs);
     
-    println!("📸 Found {} photos to process", total_photos);
-    if use_ai {
-        println!("🤖 AI vision analysis enabled (this will be slower)");
+    // Photos with sidecars are walked too: the manifest tells which of them changed
+    let manifest = Manifest::open(photo_dir)?;
+    let context = UpdateContext {
+        catalog_path: Some(catalog_path),
+        manifest: &manifest,
+        queue: queue.as_ref(),
+        skip_existing,
+        show_progress,
+        jobs,
+        ai,
+        group,
+    };
+    let walker = PhotoWalker::new(photo_dir, false);
+    let (walk_counts, ai_queued) = update_photos(&context, &mut writer, |found| {
+        walker.for_each_photo(found)
+    })?;
+    let walk_counts = walk_counts?;
+    
+    if show_progress {
+        println!(); // New line after progress
     }
-    println!();
+    walk_counts.print_summary();
+    println!("📸 Found {} photos to process", walk_counts.photos);
     
-    // Process each photo
-    for (index, photo) in photos.iter().enumerate() {
-        if show_progress && (index % 10 == 0 || index == total_photos - 1) {
-            print!("\r  Processing: {}/{} ({:.1}%)", 
-                index + 1, 
-                total_photos, 
-                (index + 1) as f64 / total_photos as f64 * 100.0
-            );
-            use std::io::{self, Write};
-            io::stdout().flush()?;
+    if ai_queued > 0 {
+        println!("📋 Queued {} photos for AI analysis", ai_queued);
+    }
+    run_queued_ai(&context, &mut writer)?;
+    
+    let elapsed = start_time.elapsed();
+    writer.print_summary(elapsed);
+    
+    Ok(())
+}
+
+/// Run the update pass over the photos `source` hands to its callback (which says
+/// whether to go on), on `context.jobs` threads, tallying results into `writer` in
+/// the order they were found. Returns what `source` returned and how many photos
+/// were queued for AI.
+///
+/// When grouping, `source` must hand over a folder's photos one after the other.
+pub fn update_photos<T: Send>(
+    context: &UpdateContext,
+    writer: &mut SidecarWriter,
+    source: impl FnOnce(&dyn Fn(PhotoFile) -> bool) -> T + Send,
+) -> Result<(T, usize)> {
+    use rusqlite::Connection;
+    
+    // One catalog connection per worker, since a connection can't be shared between threads
+    let lr_conns = (0..context.jobs)
+        .map(|_| context.catalog_path.map(Connection::open).transpose())
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (manifest, skip_existing, ai, group) = (context.manifest, context.skip_existing, context.ai, context.group);
+    
+    let found = AtomicUsize::new(0);
+    // Set when tallying fails, so the walk stops and the workers let the rest go
+    let stopped = AtomicBool::new(false);
+    let (photo_tx, photo_rx) = mpsc::sync_channel::<(usize, PhotoGroup)>(UPDATE_QUEUE_SIZE);
+    let photo_rx = Mutex::new(photo_rx);
+    let (done_tx, done_rx) = mpsc::channel::<UpdatedPhoto>();
+    
+    let mut ai_queued = 0;
+    let source_result = thread::scope(|scope| -> Result<T> {
+        let (found, stopped) = (&found, &stopped);
+        let producer = scope.spawn(move || {
+            let send = |photos: PhotoGroup| {
+                let index = found.fetch_add(1, Ordering::Relaxed);
+                !interrupted() && !stopped.load(Ordering::Relaxed) && photo_tx.send((index, photos)).is_ok()
+            };
+            if !group {
+                return source(&|photo| send(photo.into()));
+            }
+            
+            // A folder's groups are complete once the next folder starts
+            let send_folder = |photos: Vec<PhotoFile>| group_photos(photos).into_iter().all(&send);
+            let folder: RefCell<Vec<PhotoFile>> = RefCell::new(Vec::new());
+            let result = source(&|photo| {
+                let mut folder = folder.borrow_mut();
+                let go_on = match folder.first() {
+                    Some(first) if first.path.parent() != photo.path.parent() => send_folder(folder.split_off(0)),
+                    _ => true,
+                };
+                folder.push(photo);
+                go_on
+            });
+            send_folder(folder.take());
+            result
+        });
+        
+        for lr_conn in lr_conns {
+            let done_tx = done_tx.clone();
+            let photo_rx = &photo_rx;
+            scope.spawn(move || {
+                loop {
+                    let Ok((index, photos)) = photo_rx.lock().unwrap().recv() else {
+                        break;
+                    };
+                    // Keep draining the queue so the walker is never stuck sending
+                    if interrupted() || stopped.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    let updated = update_photo(index, photos, lr_conn.as_ref(), manifest, skip_existing, ai, group);
+                    if done_tx.send(updated).is_err() {
+                        break;
+                    }
+                }
+            });
         }
+        drop(done_tx);
         
-        // Extract metadata - first without AI to get rating
-        let mut metadata = match extract_metadata_verbose(photo, Some(&lr_conn), false, false) {
+        // Tally results in walk order, holding early finishers until their turn
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+        let mut processed = 0;
+        let mut tally = |updated: UpdatedPhoto| -> Result<()> {
+            processed += 1;
+            if updated.below_rating {
+                writer.ai_below_rating += 1;
+            }
+            if updated.outside_top {
+                writer.ai_outside_top += 1;
+            }
+            if updated.source_changed {
+                writer.sources_changed += 1;
+            }
+            writer.companions += updated.companions;
+            writer.companion_sidecars += updated.companion_sidecars;
+            match updated.outcome {
+                UpdateOutcome::Written => writer.files_written += 1,
+                UpdateOutcome::Skipped => writer.files_skipped += 1,
+                UpdateOutcome::QueueForAi => {
+                    if let Some(queue) = context.queue {
+                        queue.enqueue(&updated.photo.path)?;
+                        ai_queued += 1;
+                    }
+                }
+                UpdateOutcome::HandEdited if updated.source_changed => {
+                    writer.hand_edited += 1;
+                    writer.kept_despite_changes.push(updated.photo.filename);
+                }
+                UpdateOutcome::HandEdited => writer.hand_edited += 1,
+                UpdateOutcome::Failed(e) => writer.errors.push(format!("{}: {}", updated.photo.filename, e)),
+            }
+            
+            if context.show_progress && (processed % 10 == 0 || processed == found.load(Ordering::Relaxed)) {
+                print!("\r  Processing: {}/{} found so far", processed, found.load(Ordering::Relaxed));
+                use std::io::{self, Write};
+                io::stdout().flush()?;
+            }
+            Ok(())
+        };
+        let mut tally_result = Ok(());
+        let mut tally_next = |updated: UpdatedPhoto| {
+            if tally_result.is_ok() {
+                tally_result = tally(updated);
+                if tally_result.is_err() {
+                    stopped.store(true, Ordering::Relaxed);
+                }
+            }
+        };
+        for updated in done_rx {
+            pending.insert(updated.index, updated);
+            while let Some(updated) = pending.remove(&next_index) {
+                next_index += 1;
+                tally_next(updated);
+            }
+        }
+        // Anything left follows a gap left by an interrupted run
+        for (_, updated) in pending {
+            tally_next(updated);
+        }
+        
+        let source_result = producer.join().map_err(|_| anyhow::anyhow!("Photo walker panicked"))?;
+        tally_result.map(|_| source_result)
+    })?;
+    
+    Ok((source_result, ai_queued))
+}
+
+/// Analyze whatever the update pass queued, when AI is enabled
+pub fn run_queued_ai(context: &UpdateContext, writer: &mut SidecarWriter) -> Result<()> {
+    use rusqlite::Connection;
+    
+    if let (Some(queue), Some(ai)) = (context.queue, context.ai) {
+        let lr_conn = context.catalog_path.map(Connection::open).transpose()?;
+        run_ai_queue(queue, lr_conn.as_ref(), context.manifest, ai, context.group, context.show_progress, writer)?;
+    }
+    Ok(())
+}
+
+/// Extract a photo's metadata and write its sidecar, or decide it needs AI first.
+///
+/// Unless forced, a photo that already has a sidecar is only redone when the photo
+/// changed since the sidecar was written (or when it's due for AI re-analysis), and
+/// never when someone edited the sidecar by hand in the meantime. When grouping,
+/// the sidecar is the master's, and is also redone when its companions changed.
+fn update_photo(
+    index: usize,
+    photos: PhotoGroup,
+    lr_conn: Option<&rusqlite::Connection>,
+    manifest: &Manifest,
+    skip_existing: bool,
+    ai: Option<&AiOptions>,
+    group: bool,
+) -> UpdatedPhoto {
+    use crate::metadata_merger::extract_metadata_verbose;
+    use crate::sidecar_reader::{get_sidecar_rating, read_ai_analysis, read_companions, read_ocr, read_quality, read_sequence};
+    
+    let companions = photos.companion_names();
+    let mut updated = UpdatedPhoto {
+        index,
+        companions: companions.len(),
+        companion_sidecars: photos.companions.iter().filter(|c| c.has_sidecar).count(),
+        photo: photos.master,
+        outcome: UpdateOutcome::Skipped,
+        below_rating: false,
+        outside_top: false,
+        source_changed: false,
+    };
+    let photo = &updated.photo;
+    
+    let mut rewrite = !skip_existing || !photo.sidecar_path.exists();
+    if !rewrite {
+        match manifest.check(&photo.path, &photo.sidecar_path) {
+            // A sidecar from before the manifest: take it as it is from now on
+            Ok(None) => {
+                if let Err(e) = manifest.record(&photo.path, &photo.sidecar_path) {
+                    updated.outcome = UpdateOutcome::Failed(e.to_string());
+                    return updated;
+                }
+            }
+            Ok(Some(check)) => {
+                updated.source_changed = check.source_changed;
+                if check.sidecar_edited {
+                    updated.outcome = UpdateOutcome::HandEdited;
+                    return updated;
+                }
+                rewrite = check.source_changed;
+            }
+            Err(e) => {
+                updated.outcome = UpdateOutcome::Failed(e.to_string());
+                return updated;
+            }
+        }
+        
+        // A changed group only changes the list, so the rest of the sidecar is kept
+        if group && !rewrite && read_companions(&photo.sidecar_path) != companions {
+            let listed = update_frontmatter(&photo.sidecar_path, manifest, |frontmatter| {
+                store_companions(frontmatter, &companions);
+                true
+            });
+            if let Err(e) = listed {
+                updated.outcome = UpdateOutcome::Failed(e.to_string());
+                return updated;
+            }
+            updated.outcome = UpdateOutcome::Written;
+        }
+        
+        // Re-analysis needs to look at photos that are otherwise up to date
+        if !rewrite && !ai.is_some_and(|ai| ai.reanalyze.is_active()) {
+            return updated;
+        }
+    }
+    
+    // Extract metadata - first without AI to get rating
+    let mut metadata = match extract_metadata_verbose(photo, lr_conn, false, false) {
+        Ok(m) => m,
+        Err(e) => {
+            updated.outcome = UpdateOutcome::Failed(format!("Failed to extract metadata: {}", e));
+            return updated;
+        }
+    };
+    metadata.companions = companions;
+    
+    // Check if we should use AI based on rating
+    let should_use_ai = if let Some(ai) = ai {
+        if let Some(min_rating) = ai.min_rating {
+            // First try to get rating from existing sidecar (if it exists)
+            let rating = if photo.sidecar_path.exists() {
+                get_sidecar_rating(&photo.sidecar_path)
+                    .map(|r| r as f64)
+            } else {
+                None
+            };
+            
+            // Fall back to Lightroom rating if no sidecar
+            let rating = rating.or_else(|| {
+                metadata.lightroom_data.get("lr_rating")
+                    .and_then(|r| r.parse::<f64>().ok())
+            });
+            
+            rating.map(|r| r >= min_rating as f64).unwrap_or(false)
+        } else {
+            // No rating filter, use AI for all
+            true
+        }
+    } else {
+        false
+    };
+    
+    // Queue photos that still need AI; their sidecars are written once analyzed
+    let existing_ai = if photo.sidecar_path.exists() {
+        read_ai_analysis(&photo.sidecar_path)
+    } else {
+        None
+    };
+    updated.below_rating = ai.is_some() && !should_use_ai && existing_ai.is_none();
+    
+    // Of a burst, only the sharpest frames are worth the model's time
+    let sequence = if photo.sidecar_path.exists() {
+        read_sequence(&photo.sidecar_path)
+    } else {
+        None
+    };
+    let outside_top = match (ai.and_then(|ai| ai.sequence_top), &sequence) {
+        (Some(top), Some(sequence)) => sequence.rank > top,
+        _ => false,
+    };
+    updated.outside_top = should_use_ai && outside_top && existing_ai.is_none();
+    let should_use_ai = should_use_ai && !outside_top;
+    // An analysis of a photo that has changed since describes the old picture
+    let needs_ai = should_use_ai && photo.file_type.can_preview() && match (&existing_ai, ai) {
+        (Some(existing), Some(ai)) => updated.source_changed || ai.is_stale(&existing.provenance),
+        _ => true,
+    };
+    
+    // Keep AI, OCR, quality and sequence results from an earlier run when rewriting the sidecar
+    let sidecar_exists = photo.sidecar_path.exists();
+    if sidecar_exists {
+        metadata.ocr = read_ocr(&photo.sidecar_path);
+        metadata.quality = read_quality(&photo.sidecar_path);
+    }
+    metadata.sequence = sequence;
+    if existing_ai.is_some() || metadata.ocr.is_some() || metadata.quality.is_some() {
+        metadata.ai_analysis = existing_ai;
+        metadata.merge();
+    }
+    
+    // The analysis only fills in the AI fields of an existing sidecar, so one that is
+    // due for a rewrite gets it now, keeping the earlier analysis until then
+    if needs_ai {
+        if rewrite && sidecar_exists {
+            let written = write_sidecar_file(&photo.sidecar_path, &metadata, true)
+                .and_then(|_| manifest.record(&photo.path, &photo.sidecar_path));
+            if let Err(e) = written {
+                updated.outcome = UpdateOutcome::Failed(e.to_string());
+                return updated;
+            }
+        }
+        updated.outcome = UpdateOutcome::QueueForAi;
+        return updated;
+    }
+    
+    // Write sidecar
+    updated.outcome = match write_sidecar_file(&photo.sidecar_path, &metadata, rewrite)
+        .and_then(|written| {
+            if written {
+                manifest.record(&photo.path, &photo.sidecar_path)?;
+            }
+            Ok(written)
+        })
+    {
+        Ok(true) => UpdateOutcome::Written,
+        // Unless only the companions list was updated above
+        Ok(false) => std::mem::replace(&mut updated.outcome, UpdateOutcome::Skipped),
+        Err(e) => UpdateOutcome::Failed(e.to_string()),
+    };
+    updated
+}
+
+/// Resume AI analysis from the persistent queue of a photo directory
+pub fn resume_ai_queue(
+    photo_dir: &str,
+    catalog_path: &str,
+    show_progress: bool,
+    group: bool,
+    ai: &AiOptions,
+) -> Result<()> {
+    use rusqlite::Connection;
+    
+    println!("🔄 Resuming AI analysis for: {}\n", photo_dir);
+    
+    let start_time = Instant::now();
+    let mut writer = SidecarWriter::new();
+    
+    // The catalog is optional here: after the first pass it may be gone
+    let lr_conn = if Path::new(catalog_path).exists() {
+        println!("✅ Connected to Lightroom catalog");
+        Some(Connection::open(catalog_path)?)
+    } else {
+        println!("ℹ️  Lightroom catalog not found, using EXIF and AI data only");
+        None
+    };
+    
+    install_interrupt_handler()?;
+    let queue = AiQueue::open(photo_dir)?;
+    let manifest = Manifest::open(photo_dir)?;
+    run_ai_queue(&queue, lr_conn.as_ref(), &manifest, ai, group, show_progress, &mut writer)?;
+    
+    let elapsed = start_time.elapsed();
+    writer.print_summary(elapsed);
+    
+    Ok(())
+}
+
+/// Analyze every remaining job in the queue and write its sidecar, stopping
+/// cleanly after the images in flight when interrupted. When grouping, a photo's
+/// analysis goes into its master's sidecar along with the list of companions.
+fn run_ai_queue(
+    queue: &AiQueue,
+    lr_conn: Option<&rusqlite::Connection>,
+    manifest: &Manifest,
+    ai: &AiOptions,
+    group: bool,
+    show_progress: bool,
+    writer: &mut SidecarWriter,
+) -> Result<()> {
+    use crate::metadata_merger::extract_metadata_verbose;
+    use crate::ai_pipeline::{run_pipeline, AiTask};
+    use crate::ai_cache::AiCache;
+    use crate::sidecar_reader::{read_ocr, read_quality, read_sequence};
+    
+    // Metadata is read up front so it can go into the prompt, then reused for the sidecar
+    let mut tasks = Vec::new();
+    let mut photos = Vec::new();
+    for job in queue.remaining(ai.max_attempts)? {
+        if !job.path.exists() {
+            queue.mark_failed(&job.path, "photo no longer exists")?;
+            continue;
+        }
+        let Some(photo) = PhotoFile::new(job.path.clone()) else {
+            queue.mark_failed(&job.path, "not a supported photo")?;
+            continue;
+        };
+        // The group may have changed since the photo was queued
+        let companions = if group {
+            match group_of(photo.clone(), queue.root()) {
+                Ok(photos) if photos.master.path != photo.path => {
+                    let message = format!("grouped under {}, which is analyzed instead", photos.master.filename);
+                    queue.mark_failed(&photo.path, &message)?;
+                    continue;
+                }
+                Ok(photos) => photos.companion_names(),
+                Err(e) => {
+                    writer.errors.push(format!("{}: {}", photo.filename, e));
+                    queue.mark_failed(&photo.path, &e.to_string())?;
+                    continue;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let mut metadata = match extract_metadata_verbose(&photo, lr_conn, false, false) {
             Ok(m) => m,
             Err(e) => {
                 writer.errors.push(format!("{}: Failed to extract metadata: {}", photo.filename, e));
+                queue.mark_failed(&photo.path, &e.to_string())?;
                 continue;
             }
         };
-        
-        // Check if we should use AI based on rating
-        let should_use_ai = if use_ai {
-            if let Some(min_rating) = ai_min_rating {
-                // First try to get rating from existing sidecar (if it exists)
-                let rating = if photo.sidecar_path.exists() {
-                    use crate::sidecar_reader::get_sidecar_rating;
-                    get_sidecar_rating(&photo.sidecar_path)
-                        .map(|r| r as f64)
-                } else {
-                    None
-                };
-                
-                // Fall back to Lightroom rating if no sidecar
-                let rating = rating.or_else(|| {
-                    metadata.lightroom_data.get("lr_rating")
-                        .and_then(|r| r.parse::<f64>().ok())
-                });
-                
-                rating.map(|r| r >= min_rating as f64).unwrap_or(false)
-            } else {
-                // No rating filter, use AI for all
-                true
+        metadata.companions = companions;
+        // Analyzing is wasted on a sidecar whose AI fields can't be written
+        match sidecar_hand_edited(&photo, manifest) {
+            Ok(false) => {}
+            Ok(true) => {
+                writer.hand_edited += 1;
+                queue.mark_failed(&photo.path, HAND_EDITED)?;
+                continue;
             }
-        } else {
-            false
+            Err(e) => {
+                writer.errors.push(format!("{}: {}", photo.filename, e));
+                queue.mark_failed(&photo.path, &e.to_string())?;
+                continue;
+            }
+        }
+        if photo.sidecar_path.exists() {
+            metadata.ocr = read_ocr(&photo.sidecar_path);
+            metadata.quality = read_quality(&photo.sidecar_path);
+            metadata.sequence = read_sequence(&photo.sidecar_path);
+        }
+        if job.attempts > 0 {
+            println!("  🔁 Retrying {} (attempt {})", job.path.display(), job.attempts + 1);
+        }
+        tasks.push(AiTask {
+            index: tasks.len(),
+            path: job.path,
+            context: metadata.ai_context(&ai.context_fields),
+        });
+        photos.push(Some((photo, metadata)));
+    }
+    
+    let total_jobs = tasks.len();
+    if total_jobs == 0 {
+        return Ok(());
+    }
+    let endpoints: Vec<&str> = ai.backends.iter().map(|b| b.endpoint()).collect();
+    println!("🤖 Analyzing {} photos with AI ({} at a time via {})\n",
+        total_jobs, ai.workers.max(1), endpoints.join(", "));
+    
+    let review = match &ai.vocabulary {
+        Some(vocabulary) if vocabulary.unknown == UnknownTags::Review => Some(TagReview::open(queue.root())?),
+        _ => None,
+    };
+    
+    // A broken cache only costs time, so it never stops the run
+    let cache = if ai.use_cache {
+        AiCache::open(queue.root())
+            .inspect_err(|e| println!("  ⚠️  AI cache unavailable: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    
+    let mut completed = 0;
+    run_pipeline(tasks, ai, cache.as_ref(), |outcome| {
+        completed += 1;
+        if show_progress {
+            println!("  AI: {}/{} ({:.1}%)", 
+                completed, 
+                total_jobs, 
+                completed as f64 / total_jobs as f64 * 100.0
+            );
+        }
+        
+        let Some((photo, mut metadata)) = photos[outcome.index].take() else {
+            return Ok(());
         };
         
-        // If we should use AI and haven't already, get AI analysis
-        if should_use_ai && metadata.ai_analysis.is_none() {
-            // Check if sidecar already has AI data
-            use crate::sidecar_reader::has_ai_metadata;
-            let already_has_ai = photo.sidecar_path.exists() && has_ai_metadata(&photo.sidecar_path);
-            
-            if !already_has_ai {
-                use crate::ollama_vision::analyze_image;
-                if let Ok(analysis) = analyze_image(&photo.path) {
-                    metadata.ai_analysis = Some(analysis);
-                    metadata.merge(); // Re-merge to include AI data
+        match outcome.result {
+            Ok(mut analysis) => {
+                normalize_ai_tags(&mut analysis, ai, review.as_ref(), &photo.path)?;
+                metadata.ai_analysis = Some(analysis);
+                metadata.merge(); // Re-merge to include AI data
+                match store_analysis(&photo, &metadata, manifest) {
+                    Ok(true) => {
+                        writer.files_written += 1;
+                        writer.ai_analyzed += 1;
+                        if outcome.cached {
+                            writer.ai_cached += 1;
+                        }
+                        queue.mark_done(&photo.path)?;
+                    }
+                    // Edited while the photo was being analyzed
+                    Ok(false) => {
+                        writer.hand_edited += 1;
+                        queue.mark_failed(&photo.path, HAND_EDITED)?;
+                    }
+                    Err(e) => {
+                        writer.errors.push(format!("{}: {}", photo.filename, e));
+                        queue.mark_failed(&photo.path, &e.to_string())?;
+                    }
                 }
             }
+            Err(e) => {
+                let message = format!("{:#}", e);
+                writer.ai_failed += 1;
+                writer.errors.push(format!("{}: AI analysis failed: {}", photo.filename, message));
+                queue.mark_failed(&photo.path, &message)?;
+                
+                // Still record the EXIF/Lightroom metadata, without clobbering an existing sidecar
+                if let Err(e) = writer.write_sidecar(&photo, &metadata, false, manifest) {
+                    writer.errors.push(format!("{}: {}", photo.filename, e));
+                }
+            }
+        }
+        Ok(())
+    })?;
+    
+    if interrupted() {
+        let counts = queue.counts()?;
+        println!("\n⏸️  Stopped with {} photos still pending. Continue with: zim-photo ai resume", counts.pending);
+    }
+    
+    Ok(())
+}
+
+/// Write a fresh analysis to a photo's sidecar: a whole new sidecar if it has none,
+/// otherwise only its AI fields, keeping the notes and any other field as they are.
+/// Returns false, writing nothing, if the sidecar was edited by hand since it was written.
+fn store_analysis(photo: &PhotoFile, metadata: &PhotoMetadata, manifest: &Manifest) -> Result<bool> {
+    if !photo.sidecar_path.exists() {
+        write_sidecar_file(&photo.sidecar_path, metadata, true)?;
+    } else if sidecar_hand_edited(photo, manifest)? {
+        return Ok(false);
+    } else {
+        let fresh = metadata.frontmatter();
+        update_frontmatter(&photo.sidecar_path, manifest, |frontmatter| {
+            store_ai_fields(frontmatter, &fresh);
+            true
+        })?;
+    }
+    manifest.record(&photo.path, &photo.sidecar_path)?;
+    Ok(true)
+}
+
+/// Whether a photo's sidecar was edited by hand since it was last written
+fn sidecar_hand_edited(photo: &PhotoFile, manifest: &Manifest) -> Result<bool> {
+    if !photo.sidecar_path.exists() {
+        return Ok(false);
+    }
+    Ok(manifest.check(&photo.path, &photo.sidecar_path)?.is_some_and(|check| check.sidecar_edited))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vision::{BackendKind, PhotoContext, VisionAnalysis};
+    
+    /// A backend that only has a name and a digest
+    struct Model {
+        name: &'static str,
+        digest: Option<&'static str>,
+    }
+    
+    impl VisionBackend for Model {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Ollama
+        }
+        
+        fn model(&self) -> &str {
+            self.name
+        }
+        
+        fn endpoint(&self) -> &str {
+            "test"
+        }
+        
+        fn model_digest(&self) -> Option<String> {
+            self.digest.map(str::to_string)
+        }
+        
+        fn analyze(&self, _jpeg_data: &[u8], _context: &PhotoContext) -> Result<VisionAnalysis> {
+            unimplemented!()
+        }
+        
+        fn ask(&self, _jpeg_data: &[u8], _prompt: &str, _schema: serde_json::Value) -> Result<String> {
+            unimplemented!()
+        }
+        
+        fn check(&self, _pull_if_missing: bool) -> Result<()> {
+            Ok(())
         }
         
-        // Write sidecar
-        if let Err(e) = writer.write_sidecar(&photo.sidecar_path, &metadata, !skip_existing) {
-            writer.errors.push(format!("{}: {}", photo.filename, e));
+        fn reachable(&self) -> bool {
+            true
         }
     }
     
-    if show_progress {
-        println!(); // New line after progress
+    fn ai_options(model: Model, reanalyze: ReanalyzePolicy) -> AiOptions {
+        AiOptions {
+            backends: vec![Arc::new(model)],
+            workers: 1,
+            min_rating: None,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry: RetryPolicy::default(),
+            reanalyze,
+            sequence_top: None,
+            context_fields: Vec::new(),
+            vocabulary: None,
+            use_cache: false,
+        }
     }
     
-    let elapsed = start_time.elapsed();
-    writer.print_summary(elapsed);
+    fn provenance(model: &str, digest: Option<&str>, generated: Option<&str>) -> AiProvenance {
+        AiProvenance {
+            backend: "ollama".to_string(),
+            model: model.to_string(),
+            model_digest: digest.map(str::to_string),
+            generated: generated.map(str::to_string),
+            ..Default::default()
+        }
+    }
     
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn nothing_is_stale_without_a_policy() {
+        let options = ai_options(Model { name: "llava", digest: None }, ReanalyzePolicy::default());
+        assert!(!options.is_stale(&provenance("qwen2.5vl", None, None)));
+    }
+    
+    #[test]
+    fn results_before_the_cutoff_or_of_unknown_age_are_stale() {
+        let policy = ReanalyzePolicy { older_than: NaiveDate::from_ymd_opt(2024, 6, 1), model_changed: false };
+        let options = ai_options(Model { name: "llava", digest: None }, policy);
+        assert!(options.is_stale(&provenance("llava", None, Some("2024-05-31T23:59:59"))));
+        assert!(!options.is_stale(&provenance("llava", None, Some("2024-06-01T00:00:00"))));
+        assert!(options.is_stale(&provenance("llava", None, None)));
+        assert!(options.is_stale(&provenance("llava", None, Some("last week"))));
+    }
+    
+    #[test]
+    fn model_changes_ignore_the_latest_tag() {
+        let policy = ReanalyzePolicy { older_than: None, model_changed: true };
+        let options = ai_options(Model { name: "qwen2.5vl", digest: None }, policy.clone());
+        assert!(!options.is_stale(&provenance("qwen2.5vl", None, None)));
+        assert!(!options.is_stale(&provenance("qwen2.5vl:latest", None, None)));
+        assert!(options.is_stale(&provenance("qwen2.5vl:7b", None, None)));
+        assert!(options.is_stale(&provenance("llava", None, None)));
+        
+        let options = ai_options(Model { name: "qwen2.5vl:latest", digest: None }, policy);
+        assert!(!options.is_stale(&provenance("qwen2.5vl", None, None)));
+    }
+    
+    #[test]
+    fn a_new_build_of_the_same_model_is_stale() {
+        let policy = ReanalyzePolicy { older_than: None, model_changed: true };
+        let options = ai_options(Model { name: "llava", digest: Some("sha256:new") }, policy);
+        assert!(options.is_stale(&provenance("llava", Some("sha256:old"), None)));
+        assert!(!options.is_stale(&provenance("llava", Some("sha256:new"), None)));
+        // Without a recorded digest there is nothing to compare
+        assert!(!options.is_stale(&provenance("llava", None, None)));
+    }
+}
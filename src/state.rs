@@ -0,0 +1,23 @@
+use anyhow::{Result, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the directory holding zim-photo's working state inside a photo root.
+/// It starts with a dot, so the photo walker never descends into it.
+pub const STATE_DIR_NAME: &str = ".zim-photo";
+
+/// Get (and create if needed) the state directory for a photo root
+pub fn state_dir(photo_root: &Path) -> Result<PathBuf> {
+    let dir = photo_root.join(STATE_DIR_NAME);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Path of a photo relative to the photo root, used as a stable key in state databases
+pub fn relative_key(photo_root: &Path, path: &Path) -> String {
+    path.strip_prefix(photo_root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
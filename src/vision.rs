@@ -0,0 +1,576 @@
+use anyhow::{Result, Context, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use chrono::Local;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::ollama_vision::{ImageSource, OllamaBackend};
+use crate::openai_vision::OpenAiBackend;
+
+// Standard tags to consider
+const STANDARD_TAGS: &str = "landscape, portrait, street photography, nature, sunset, sunrise, \
+animal, bird, mountains, ocean, beach, boat, car, tree, flower, people, crowd, pet, city, \
+building, macro, insect, computer, electronics, tools, motorcycle, sign, street sign";
+
+// How many times to ask the model again after a reply that doesn't match the schema
+const MAX_SCHEMA_ATTEMPTS: usize = 3;
+
+/// Which kind of server performs the vision analysis
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// Ollama's native `/api/chat`
+    #[default]
+    Ollama,
+    /// Any OpenAI-compatible `/v1/chat/completions` server (LM Studio, vLLM, llama-server)
+    Openai,
+    /// Deterministic offline results, for trying out the pipeline
+    Fake,
+}
+
+impl BackendKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::Ollama => "ollama",
+            BackendKind::Openai => "openai",
+            BackendKind::Fake => "fake",
+        }
+    }
+    
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            BackendKind::Ollama | BackendKind::Openai => crate::ollama_vision::DEFAULT_MODEL,
+            BackendKind::Fake => "fake",
+        }
+    }
+    
+    pub fn default_endpoint(&self) -> &'static str {
+        match self {
+            BackendKind::Ollama => crate::ollama_vision::DEFAULT_ENDPOINT,
+            BackendKind::Openai => crate::openai_vision::DEFAULT_ENDPOINT,
+            BackendKind::Fake => "fake",
+        }
+    }
+}
+
+/// Metadata that can be passed to the model alongside the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextField {
+    /// Capture date and time
+    Date,
+    /// Camera body and lens
+    Camera,
+    /// Keywords assigned in Lightroom
+    Keywords,
+    /// Title and caption written by a person
+    Title,
+    /// Place name from the Lightroom location fields
+    Location,
+    /// GPS coordinates
+    Gps,
+}
+
+/// What we know about the photo being analyzed, beyond its pixels.
+/// Only the fields enabled with `--ai-context` are filled in.
+#[derive(Debug, Clone, Default)]
+pub struct PhotoContext {
+    pub filename: String,
+    pub captured: Option<String>,
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    pub keywords: Vec<String>,
+    pub title: Option<String>,
+    pub caption: Option<String>,
+    pub location: Option<String>,
+    pub gps: Option<(f64, f64)>,
+}
+
+impl PhotoContext {
+    /// Known facts as prompt lines, most useful first
+    fn facts(&self) -> Vec<String> {
+        let mut facts = Vec::new();
+        if let Some(ref location) = self.location {
+            facts.push(format!("Location: {}", location));
+        }
+        if let Some((latitude, longitude)) = self.gps {
+            facts.push(format!("GPS coordinates: {:.5}, {:.5}", latitude, longitude));
+        }
+        if let Some(ref title) = self.title {
+            facts.push(format!("Title: {}", title));
+        }
+        if let Some(ref caption) = self.caption {
+            facts.push(format!("Caption: {}", caption));
+        }
+        if !self.keywords.is_empty() {
+            facts.push(format!("Keywords: {}", self.keywords.join(", ")));
+        }
+        if let Some(ref captured) = self.captured {
+            facts.push(format!("Captured: {}", captured));
+        }
+        if let Some(ref camera) = self.camera {
+            facts.push(format!("Camera: {}", camera));
+        }
+        if let Some(ref lens) = self.lens {
+            facts.push(format!("Lens: {}", lens));
+        }
+        facts
+    }
+}
+
+/// How an analysis was produced, so results can be selected for re-analysis later
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AiProvenance {
+    pub backend: String,
+    pub model: String,
+    pub model_digest: Option<String>,
+    pub prompt_version: Option<String>,
+    pub generated: Option<String>,
+    pub source_image: Option<String>,
+    pub duration_secs: Option<f64>,
+}
+
+impl AiProvenance {
+    /// Provenance for an analysis that just finished on `backend`
+    pub fn new(backend: &dyn VisionBackend, source: ImageSource, duration: Duration) -> Self {
+        AiProvenance {
+            backend: backend.kind().name().to_string(),
+            model: backend.model().to_string(),
+            model_digest: backend.model_digest(),
+            prompt_version: Some(prompt_version()),
+            generated: Some(Local::now().format("%Y-%m-%dT%H:%M:%S").to_string()),
+            source_image: Some(source.name().to_string()),
+            duration_secs: Some((duration.as_secs_f64() * 10.0).round() / 10.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisionAnalysis {
+    pub description: String,
+    pub tags: Vec<String>,
+    pub subjects: Vec<String>,
+    pub setting: Option<String>,
+    pub mood: Option<String>,
+    pub dominant_colors: Vec<String>,
+    pub text_in_image: Option<String>,
+    pub provenance: AiProvenance,
+}
+
+/// A vision model that can describe an image
+pub trait VisionBackend: Send + Sync {
+    fn kind(&self) -> BackendKind;
+    fn model(&self) -> &str;
+    fn endpoint(&self) -> &str;
+    
+    /// Content digest of the model weights, when the server reports one
+    fn model_digest(&self) -> Option<String> {
+        None
+    }
+    
+    /// Describe a JPEG image
+    fn analyze(&self, jpeg_data: &[u8], context: &PhotoContext) -> Result<VisionAnalysis>;
+    
+    /// Send a single prompt with a JPEG image and return the reply, constrained to `schema`
+    fn ask(&self, jpeg_data: &[u8], prompt: &str, schema: serde_json::Value) -> Result<String>;
+    
+    /// Transcribe the legible text in a JPEG image, if there is any
+    fn read_text(&self, jpeg_data: &[u8], _context: &PhotoContext) -> Result<Option<String>> {
+        parse_ocr_response(&self.ask(jpeg_data, ocr_prompt(), ocr_schema())?)
+    }
+    
+    /// Aesthetic score of a JPEG image, from 1 (poor) to 10 (outstanding)
+    fn score_aesthetics(&self, jpeg_data: &[u8]) -> Result<f64> {
+        parse_aesthetic_response(&self.ask(jpeg_data, aesthetic_prompt(), aesthetic_schema())?)
+    }
+    
+    /// Make sure the server is up and the model usable, before a long run starts
+    fn check(&self, pull_if_missing: bool) -> Result<()>;
+    
+    /// Whether the server answers at all, used to detect when it comes back after an outage
+    fn reachable(&self) -> bool;
+}
+
+/// Build one backend per endpoint for the configured server kind
+pub fn create_backends(
+    kind: BackendKind,
+    model: &str,
+    endpoints: &[String],
+    api_key: Option<&str>,
+) -> Vec<Arc<dyn VisionBackend>> {
+    endpoints.iter()
+        .map(|endpoint| -> Arc<dyn VisionBackend> {
+            match kind {
+                BackendKind::Ollama => Arc::new(OllamaBackend::new(endpoint, model)),
+                BackendKind::Openai => Arc::new(OpenAiBackend::new(endpoint, model, api_key)),
+                BackendKind::Fake => Arc::new(FakeBackend::new(model)),
+            }
+        })
+        .collect()
+}
+
+/// One turn of the conversation with the model
+pub struct ChatTurn {
+    pub role: &'static str,
+    pub content: String,
+}
+
+/// Ask for an analysis and keep correcting the model until its reply matches the schema.
+///
+/// `send` receives the whole conversation so far (the first turn is the prompt the
+/// image belongs to) and returns the text of the model's reply.
+pub fn request_analysis(
+    context: &PhotoContext,
+    mut send: impl FnMut(&[ChatTurn]) -> Result<String>,
+) -> Result<VisionAnalysis> {
+    let mut turns = vec![ChatTurn { role: "user", content: analysis_prompt(context) }];
+    
+    for attempt in 1..=MAX_SCHEMA_ATTEMPTS {
+        let content = send(&turns)?;
+        
+        match parse_vision_response(&content) {
+            Ok(analysis) => return Ok(analysis),
+            Err(e) if attempt < MAX_SCHEMA_ATTEMPTS => {
+                println!("  ⚠️  Response did not match the schema ({}), asking again", e);
+                turns.push(ChatTurn { role: "assistant", content });
+                turns.push(ChatTurn {
+                    role: "user",
+                    content: format!(
+                        "Your previous reply was not valid: {}. Reply again with only a JSON object \
+                        matching the requested schema, with a non-empty description and tags list.",
+                        e
+                    ),
+                });
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    
+    Err(anyhow!("Model did not return a valid analysis after {} attempts", MAX_SCHEMA_ATTEMPTS))
+}
+
+/// Short hash of the prompt template and schema; changes whenever either is edited
+pub fn prompt_version() -> String {
+    let template = analysis_prompt(&PhotoContext::default());
+    let digest = Sha256::new()
+        .chain_update(template.as_bytes())
+        .chain_update(CONTEXT_INSTRUCTIONS.as_bytes())
+        .chain_update(analysis_schema().to_string().as_bytes())
+        .finalize();
+    format!("{:x}", digest)[..12].to_string()
+}
+
+/// Short hash of the whole prompt sent for a photo, its `--ai-context` facts included,
+/// and the schema; cached results are only reused for the same prompt
+pub fn prompt_hash(context: &PhotoContext) -> String {
+    let digest = Sha256::new()
+        .chain_update(analysis_prompt(context).as_bytes())
+        .chain_update(analysis_schema().to_string().as_bytes())
+        .finalize();
+    format!("{:x}", digest)[..16].to_string()
+}
+
+/// Introduces the known metadata in the prompt
+const CONTEXT_INSTRUCTIONS: &str = "This is what is already known about the photo. \
+Use it to name specific places, subjects and events, but only describe what is actually visible:";
+
+fn analysis_prompt(context: &PhotoContext) -> String {
+    let mut prompt = format!(
+        "Analyze this photograph and describe it as a JSON object with these fields:
+
+- description: a brief, descriptive caption (1-2 sentences) that captures what's shown in the image
+- tags: relevant tags for searching and categorization
+- subjects: the main subjects of the photo
+- setting: where the photo was taken (e.g. beach, forest, city street, indoors)
+- mood: the overall mood or atmosphere
+- dominant_colors: the most prominent colors
+- text_in_image: any legible text in the image, or null if there is none
+
+When tagging, consider these standard categories if applicable: {}
+
+Also add any other specific, relevant tags that would help someone find this image later. 
+Keep tags concise (1-2 words each) and focus on observable content, style, and mood.
+
+Be specific and accurate. Focus on what's actually visible in the image.",
+        STANDARD_TAGS
+    );
+    
+    let facts = context.facts();
+    if !facts.is_empty() {
+        prompt.push_str("\n\n");
+        prompt.push_str(CONTEXT_INSTRUCTIONS);
+        for fact in facts {
+            prompt.push_str("\n- ");
+            prompt.push_str(&fact);
+        }
+    }
+    
+    prompt
+}
+
+/// JSON schema the reply is constrained to, matching `StructuredAnalysis`
+pub fn analysis_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "description": { "type": "string" },
+            "tags": { "type": "array", "items": { "type": "string" } },
+            "subjects": { "type": "array", "items": { "type": "string" } },
+            "setting": { "type": "string" },
+            "mood": { "type": "string" },
+            "dominant_colors": { "type": "array", "items": { "type": "string" } },
+            "text_in_image": { "type": ["string", "null"] }
+        },
+        "required": ["description", "tags", "subjects", "setting", "mood", "dominant_colors", "text_in_image"]
+    })
+}
+
+/// The typed object the model is asked to return
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StructuredAnalysis {
+    description: String,
+    tags: Vec<String>,
+    #[serde(default)]
+    subjects: Vec<String>,
+    #[serde(default)]
+    setting: Option<String>,
+    #[serde(default)]
+    mood: Option<String>,
+    #[serde(default)]
+    dominant_colors: Vec<String>,
+    #[serde(default)]
+    text_in_image: Option<String>,
+}
+
+/// Parse and validate the model's JSON reply
+fn parse_vision_response(response: &str) -> Result<VisionAnalysis> {
+    let structured: StructuredAnalysis = serde_json::from_str(response.trim())
+        .context("reply is not a JSON object matching the schema")?;
+    
+    let description = structured.description.trim().to_string();
+    if description.is_empty() {
+        return Err(anyhow!("description is empty"));
+    }
+    
+    let tags = clean_list(structured.tags);
+    if tags.is_empty() {
+        return Err(anyhow!("tags list is empty"));
+    }
+    
+    let analysis = VisionAnalysis {
+        description,
+        tags,
+        subjects: clean_list(structured.subjects),
+        setting: clean_text(structured.setting),
+        mood: clean_text(structured.mood),
+        dominant_colors: clean_list(structured.dominant_colors),
+        text_in_image: clean_text(structured.text_in_image),
+        provenance: AiProvenance::default(),
+    };
+    
+    println!("  📋 Final: desc={} chars, {} tags", analysis.description.len(), analysis.tags.len());
+    Ok(analysis)
+}
+
+/// Lowercase, trim and drop empty or repeated entries
+fn clean_list(items: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for item in items {
+        let item = item.trim().to_lowercase();
+        if !item.is_empty() && !cleaned.contains(&item) {
+            cleaned.push(item);
+        }
+    }
+    cleaned
+}
+
+/// HTTP client for vision requests, which can take minutes on a CPU
+pub fn vision_client() -> Result<reqwest::blocking::Client> {
+    Ok(reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(600))  // 10 minutes - vision models can be slow
+        .build()?)
+}
+
+/// Prompt for the dedicated OCR pass
+pub fn ocr_prompt() -> &'static str {
+    "Transcribe all legible text in this photograph exactly as written: signs, menus, \
+whiteboards, documents, labels and screens. Keep the original line breaks and language. \
+Reply with a JSON object whose `text` field holds the transcription, or null if there is no legible text."
+}
+
+/// JSON schema for the OCR reply
+pub fn ocr_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "text": { "type": ["string", "null"] }
+        },
+        "required": ["text"]
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct OcrReply {
+    text: Option<String>,
+}
+
+pub fn parse_ocr_response(response: &str) -> Result<Option<String>> {
+    let reply: OcrReply = serde_json::from_str(response.trim())
+        .context("OCR reply is not a JSON object with a text field")?;
+    Ok(clean_text(reply.text))
+}
+
+/// Prompt for the aesthetic score used by the quality pass
+pub fn aesthetic_prompt() -> &'static str {
+    "Rate the aesthetic quality of this photograph from 1 (poor) to 10 (outstanding), the way \
+an experienced photo editor culling a shoot would: consider composition, light, subject, \
+moment and technical execution. Reply with a JSON object whose `score` field holds the rating."
+}
+
+/// JSON schema for the aesthetic score reply
+pub fn aesthetic_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "score": { "type": "number", "minimum": 1, "maximum": 10 }
+        },
+        "required": ["score"]
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct AestheticReply {
+    score: f64,
+}
+
+pub fn parse_aesthetic_response(response: &str) -> Result<f64> {
+    let reply: AestheticReply = serde_json::from_str(response.trim())
+        .context("Aesthetic reply is not a JSON object with a score field")?;
+    if !(1.0..=10.0).contains(&reply.score) {
+        return Err(anyhow!("Aesthetic score {} is outside 1-10", reply.score));
+    }
+    Ok(reply.score)
+}
+
+/// Trim optional text, treating blank strings as absent
+fn clean_text(text: Option<String>) -> Option<String> {
+    text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+/// Offline backend returning the same analysis for the same input, for tests and dry runs
+pub struct FakeBackend {
+    model: String,
+}
+
+impl FakeBackend {
+    pub fn new(model: &str) -> Self {
+        FakeBackend { model: model.to_string() }
+    }
+}
+
+impl VisionBackend for FakeBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Fake
+    }
+    
+    fn model(&self) -> &str {
+        &self.model
+    }
+    
+    fn endpoint(&self) -> &str {
+        "fake"
+    }
+    
+    fn analyze(&self, jpeg_data: &[u8], context: &PhotoContext) -> Result<VisionAnalysis> {
+        // A cheap, stable checksum so different images get different results
+        let checksum = jpeg_data.iter().fold(0u32, |acc, &b| acc.wrapping_mul(31).wrapping_add(b as u32));
+        Ok(VisionAnalysis {
+            description: format!("Placeholder description of {}.", context.filename),
+            tags: vec!["fake".to_string(), format!("checksum {:08x}", checksum)],
+            subjects: Vec::new(),
+            setting: context.location.clone(),
+            mood: None,
+            dominant_colors: Vec::new(),
+            text_in_image: None,
+            provenance: AiProvenance::default(),
+        })
+    }
+    
+    fn ask(&self, _jpeg_data: &[u8], _prompt: &str, _schema: serde_json::Value) -> Result<String> {
+        Err(anyhow!("The fake backend only answers the built-in prompts"))
+    }
+    
+    fn read_text(&self, jpeg_data: &[u8], context: &PhotoContext) -> Result<Option<String>> {
+        // Every other image "contains text", so both paths can be tried
+        Ok(jpeg_data.len().is_multiple_of(2).then(|| format!("FAKE TEXT FROM {}", context.filename.to_uppercase())))
+    }
+    
+    fn score_aesthetics(&self, jpeg_data: &[u8]) -> Result<f64> {
+        Ok(1.0 + (jpeg_data.len() % 10) as f64)
+    }
+    
+    fn check(&self, _pull_if_missing: bool) -> Result<()> {
+        Ok(())
+    }
+    
+    fn reachable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn parses_a_reply_and_cleans_it_up() {
+        let reply = r#"{
+            "description": "  Two kids running along a beach at dusk. ",
+            "tags": ["Beach", "sunset", "beach", " ", "Kids"],
+            "subjects": ["children"],
+            "setting": "beach",
+            "mood": "  ",
+            "dominant_colors": ["orange"],
+            "text_in_image": null
+        }"#;
+        let analysis = parse_vision_response(reply).unwrap();
+        assert_eq!(analysis.description, "Two kids running along a beach at dusk.");
+        assert_eq!(analysis.tags, vec!["beach", "sunset", "kids"]);
+        assert_eq!(analysis.setting.as_deref(), Some("beach"));
+        assert_eq!(analysis.mood, None);
+        assert_eq!(analysis.text_in_image, None);
+    }
+    
+    #[test]
+    fn rejects_replies_that_miss_the_schema() {
+        // Not JSON, an unknown field, an empty description and no tags
+        assert!(parse_vision_response("A beach at dusk").is_err());
+        assert!(parse_vision_response(r#"{"description": "Beach", "tags": ["beach"], "rating": 5}"#).is_err());
+        assert!(parse_vision_response(r#"{"description": " ", "tags": ["beach"]}"#).is_err());
+        assert!(parse_vision_response(r#"{"description": "Beach", "tags": [" "]}"#).is_err());
+    }
+    
+    #[test]
+    fn fake_backend_is_deterministic() {
+        let backend = FakeBackend::new("fake");
+        let context = PhotoContext { filename: "IMG_0001.jpg".to_string(), ..Default::default() };
+        let first = backend.analyze(b"same pixels", &context).unwrap();
+        let second = backend.analyze(b"same pixels", &context).unwrap();
+        let other = backend.analyze(b"other pixels", &context).unwrap();
+        assert_eq!(first.description, second.description);
+        assert_eq!(first.tags, second.tags);
+        assert_ne!(first.tags, other.tags);
+        assert_eq!(backend.read_text(b"ab", &context).unwrap(), backend.read_text(b"ab", &context).unwrap());
+        assert_eq!(backend.score_aesthetics(b"abc").unwrap(), backend.score_aesthetics(b"abc").unwrap());
+    }
+    
+    #[test]
+    fn prompt_hash_follows_the_context() {
+        let plain = PhotoContext::default();
+        let titled = PhotoContext { title: Some("Rainier from Paradise".to_string()), ..Default::default() };
+        assert_eq!(prompt_hash(&plain), prompt_hash(&plain.clone()));
+        assert_ne!(prompt_hash(&plain), prompt_hash(&titled));
+    }
+}
@@ -0,0 +1,456 @@
+use anyhow::{Result, Context, anyhow};
+use chrono::Local;
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::state::{state_dir, relative_key};
+
+/// What to do with AI tags that aren't in the vocabulary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownTags {
+    /// Keep them as they are (after cleanup)
+    #[default]
+    Keep,
+    /// Remove them
+    Drop,
+    /// Keep them, and list them in the review queue so they can be added or banned
+    Review,
+}
+
+/// One canonical tag in the vocabulary file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TagEntry {
+    /// Other spellings that mean the same thing
+    synonyms: Vec<String>,
+    /// Broader tag that is added whenever this one is present
+    parent: Option<String>,
+}
+
+/// The vocabulary file as written by the user
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct VocabularyFile {
+    unknown: UnknownTags,
+    tags: HashMap<String, Option<TagEntry>>,
+    banned: Vec<String>,
+}
+
+/// Canonical tags, synonyms, hierarchy and banned tags applied to AI tags
+#[derive(Debug, Clone, Default)]
+pub struct Vocabulary {
+    pub unknown: UnknownTags,
+    /// Cleaned-up spelling (canonical or synonym) to canonical tag
+    canonical: HashMap<String, String>,
+    /// The same spellings with spaces removed, so "sun set" finds "sunset"
+    squashed: HashMap<String, String>,
+    /// Canonical tag to its parent
+    parents: HashMap<String, String>,
+    banned: HashSet<String>,
+}
+
+/// Result of normalizing one photo's tags
+#[derive(Debug, Default)]
+pub struct NormalizedTags {
+    pub tags: Vec<String>,
+    /// Tags not found in the vocabulary (kept or dropped according to `unknown`)
+    pub unknown: Vec<String>,
+}
+
+impl Vocabulary {
+    /// Load the given vocabulary file, or `vocabulary.yaml` next to the config file
+    /// if it exists. Returns `None` when there is no vocabulary to apply.
+    pub fn load(path: Option<&Path>) -> Result<Option<Vocabulary>> {
+        let path = match path {
+            Some(path) => {
+                if !path.exists() {
+                    return Err(anyhow!("Vocabulary file not found: {}", path.display()));
+                }
+                path.to_path_buf()
+            }
+            None => match crate::config::config_dir().map(|dir| dir.join("vocabulary.yaml")) {
+                Some(path) if path.exists() => path,
+                _ => return Ok(None),
+            },
+        };
+        
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read vocabulary: {}", path.display()))?;
+        let file: VocabularyFile = serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid vocabulary file: {}", path.display()))?;
+        Vocabulary::from_file(file)
+            .with_context(|| format!("Invalid vocabulary file: {}", path.display()))
+            .map(Some)
+    }
+    
+    fn from_file(file: VocabularyFile) -> Result<Vocabulary> {
+        let mut vocabulary = Vocabulary {
+            unknown: file.unknown,
+            banned: file.banned.iter().map(|t| clean_tag(t)).collect(),
+            ..Default::default()
+        };
+        
+        // Every canonical tag first, so a synonym that is also a tag of its own is
+        // caught whatever order the file lists them in
+        let mut tags: Vec<_> = file.tags.iter().collect();
+        tags.sort_by(|a, b| a.0.cmp(b.0));
+        for (tag, _) in &tags {
+            let canonical = clean_tag(tag);
+            vocabulary.canonical.insert(canonical.clone(), canonical);
+        }
+        
+        for (tag, entry) in tags {
+            let canonical = clean_tag(tag);
+            let entry = entry.clone().unwrap_or_default();
+            for synonym in &entry.synonyms {
+                let synonym = clean_tag(synonym);
+                if let Some(other) = vocabulary.canonical.get(&synonym)
+                    && *other != canonical
+                {
+                    return Err(anyhow!("'{}' is listed under both '{}' and '{}'", synonym, other, canonical));
+                }
+                vocabulary.canonical.insert(synonym, canonical.clone());
+            }
+            if let Some(parent) = entry.parent {
+                vocabulary.parents.insert(canonical, clean_tag(&parent));
+            }
+        }
+        
+        // Parents are tags in their own right
+        for parent in vocabulary.parents.values() {
+            vocabulary.canonical.entry(parent.clone()).or_insert_with(|| parent.clone());
+        }
+        for (spelling, canonical) in &vocabulary.canonical {
+            vocabulary.squashed.insert(spelling.replace(' ', ""), canonical.clone());
+        }
+        
+        Ok(vocabulary)
+    }
+    
+    /// Map each tag to its canonical form, add parent tags, and drop banned
+    /// tags and duplicates, keeping the model's order
+    pub fn normalize(&self, tags: &[String]) -> NormalizedTags {
+        let mut result = NormalizedTags::default();
+        let mut seen = HashSet::new();
+        
+        for tag in tags {
+            let cleaned = clean_tag(tag);
+            if cleaned.is_empty() || self.banned.contains(&cleaned) {
+                continue;
+            }
+            
+            let canonical = match self.lookup(&cleaned) {
+                Some(canonical) => canonical,
+                None => {
+                    result.unknown.push(cleaned.clone());
+                    if self.unknown == UnknownTags::Drop {
+                        continue;
+                    }
+                    cleaned
+                }
+            };
+            
+            // Walk up the hierarchy; the seen check also guards against cycles
+            let mut current = Some(canonical);
+            while let Some(tag) = current {
+                if self.banned.contains(&tag) || !seen.insert(tag.clone()) {
+                    break;
+                }
+                current = self.parents.get(&tag).cloned();
+                result.tags.push(tag);
+            }
+        }
+        
+        result
+    }
+    
+    /// Find the canonical tag for a cleaned-up spelling, also trying its singular
+    /// and ignoring spaces
+    fn lookup(&self, tag: &str) -> Option<String> {
+        if let Some(canonical) = self.canonical.get(tag) {
+            return Some(canonical.clone());
+        }
+        let squashed = tag.replace(' ', "");
+        std::iter::once(squashed.clone())
+            .chain(singular_forms(&squashed))
+            .find_map(|form| self.squashed.get(&form).cloned())
+    }
+}
+
+/// Lowercase, turn `-` and `_` into spaces and collapse whitespace
+fn clean_tag(tag: &str) -> String {
+    tag.to_lowercase()
+        .replace(['-', '_'], " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Possible singulars of the last word of a tag ("sunsets" -> "sunset", "berries" -> "berry")
+fn singular_forms(tag: &str) -> Vec<String> {
+    let mut forms = Vec::new();
+    if let Some(stem) = tag.strip_suffix("ies") {
+        forms.push(format!("{}y", stem));
+    }
+    if let Some(stem) = tag.strip_suffix("es") {
+        forms.push(stem.to_string());
+    }
+    if let Some(stem) = tag.strip_suffix('s')
+        && !stem.ends_with('s')
+    {
+        forms.push(stem.to_string());
+    }
+    forms
+}
+
+/// Unknown tags waiting for someone to add them to the vocabulary or ban them,
+/// with the photos using them, kept in `.zim-photo/tag_review.db` inside the photo directory
+pub struct TagReview {
+    conn: Connection,
+    root: PathBuf,
+}
+
+impl TagReview {
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let db_path: PathBuf = state_dir(&root)?.join("tag_review.db");
+        let conn = Connection::open(&db_path)
+            .with_context(|| format!("Failed to open tag review queue: {}", db_path.display()))?;
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS unknown_tag_photos (
+                tag TEXT NOT NULL,
+                photo TEXT NOT NULL,
+                last_seen TEXT NOT NULL,
+                PRIMARY KEY (tag, photo)
+            );
+        ")?;
+        Ok(TagReview { conn, root })
+    }
+    
+    /// Record the unknown tags a photo uses now, replacing what was recorded for it before
+    pub fn record(&self, photo: &Path, tags: &[String]) -> Result<()> {
+        let photo = relative_key(&self.root, photo);
+        let now = Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+        self.conn.execute("DELETE FROM unknown_tag_photos WHERE photo = ?1", params![photo])?;
+        for tag in tags {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO unknown_tag_photos (tag, photo, last_seen) VALUES (?1, ?2, ?3)",
+                params![tag, photo, now],
+            )?;
+        }
+        Ok(())
+    }
+    
+    /// Forget tags that have since been added to the vocabulary or banned
+    pub fn prune(&self, vocabulary: &Vocabulary) -> Result<usize> {
+        let tags: Vec<String> = self.conn
+            .prepare("SELECT DISTINCT tag FROM unknown_tag_photos")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        
+        let mut pruned = 0;
+        for tag in tags {
+            if vocabulary.banned.contains(&tag) || vocabulary.lookup(&tag).is_some() {
+                self.conn.execute("DELETE FROM unknown_tag_photos WHERE tag = ?1", params![tag])?;
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+    
+    /// Unknown tags with the number of photos using them and one of those photos,
+    /// most used first
+    pub fn list(&self) -> Result<Vec<(String, i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tag, COUNT(*) AS photos, MIN(photo) FROM unknown_tag_photos
+             GROUP BY tag ORDER BY photos DESC, tag"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+/// Print the unknown tags waiting for review in a photo directory
+pub fn print_review(root: &str, vocabulary: Option<&Vocabulary>) -> Result<()> {
+    let review = TagReview::open(root)?;
+    if let Some(vocabulary) = vocabulary {
+        review.prune(vocabulary)?;
+    }
+    
+    let tags = review.list()?;
+    if tags.is_empty() {
+        println!("✅ No unknown tags waiting for review");
+        return Ok(());
+    }
+    
+    println!("🏷️  {} unknown tags waiting for review:\n", tags.len());
+    for (tag, count, example) in tags {
+        println!("  {:>5}  {}  (e.g. {})", count, tag, example);
+    }
+    println!("\nAdd them to the vocabulary as tags or synonyms, or list them under `banned:`.");
+    Ok(())
+}
+
+/// Re-apply the vocabulary to the AI tags of every sidecar under a photo directory
+pub fn normalize_sidecars(photo_dir: &str, vocabulary: &Vocabulary, dry_run: bool) -> Result<()> {
+    use crate::photo_walker::PhotoWalker;
+    use crate::manifest::Manifest;
+    use crate::sidecar_writer::update_frontmatter;
+    use serde_yaml::Value;
+    
+    println!("🏷️  Normalizing AI tags in: {}\n", photo_dir);
+    
+    let review = match vocabulary.unknown {
+        UnknownTags::Review => Some(TagReview::open(photo_dir)?),
+        _ => None,
+    };
+    
+    let photos = PhotoWalker::new(photo_dir, false).find_photos()?;
+    let manifest = Manifest::open(photo_dir)?;
+    let mut changed = 0;
+    let mut unchanged = 0;
+    let mut errors = Vec::new();
+    
+    for photo in photos.iter().filter(|p| p.has_sidecar) {
+        let mut unknown = Vec::new();
+        let mut differs = false;
+        let result = update_frontmatter(&photo.sidecar_path, &manifest, |frontmatter| {
+            let Some(Value::Sequence(tags)) = frontmatter.get("ai_tags") else {
+                return false;
+            };
+            let tags: Vec<String> = tags.iter()
+                .filter_map(|t| t.as_str().map(str::to_string))
+                .collect();
+            
+            let normalized = vocabulary.normalize(&tags);
+            unknown = normalized.unknown;
+            if normalized.tags == tags {
+                return false;
+            }
+            
+            println!("  {}: {} → {}", photo.filename, tags.join(", "), normalized.tags.join(", "));
+            differs = true;
+            if dry_run {
+                return false;
+            }
+            let value = normalized.tags.into_iter().map(Value::from).collect();
+            frontmatter.insert("ai_tags".into(), Value::Sequence(value));
+            true
+        });
+        
+        // A dry run leaves the review queue alone too
+        if let Some(review) = &review
+            && !dry_run
+            && result.is_ok()
+        {
+            review.record(&photo.path, &unknown)?;
+        }
+        match result {
+            Ok(_) if differs => changed += 1,
+            Ok(_) => unchanged += 1,
+            Err(e) => errors.push(format!("{}: {}", photo.filename, e)),
+        }
+    }
+    
+    if let Some(review) = &review
+        && !dry_run
+    {
+        review.prune(vocabulary)?;
+    }
+    
+    println!("\n📊 Tag Normalization Summary:");
+    if dry_run {
+        println!("  🔍 Sidecars that would change: {} (dry run, nothing was written)", changed);
+    } else {
+        println!("  ✅ Sidecars updated: {}", changed);
+    }
+    println!("  ⏭️  Already normalized: {}", unchanged);
+    if !errors.is_empty() {
+        println!("  ❌ Errors: {}", errors.len());
+        for error in &errors {
+            println!("     {}", error);
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn vocabulary(yaml: &str) -> Result<Vocabulary> {
+        Vocabulary::from_file(serde_yaml::from_str(yaml).unwrap())
+    }
+    
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+    
+    #[test]
+    fn singular_forms_of_the_last_word() {
+        assert_eq!(singular_forms("sunsets"), vec!["sunset"]);
+        assert_eq!(singular_forms("berries"), vec!["berry", "berri", "berrie"]);
+        assert_eq!(singular_forms("beaches"), vec!["beach", "beache"]);
+        assert!(singular_forms("glass").is_empty());
+        assert!(singular_forms("tree").is_empty());
+    }
+    
+    #[test]
+    fn normalizes_synonyms_plurals_and_spacing() {
+        let vocabulary = vocabulary("
+tags:
+  sunset:
+    synonyms: [dusk]
+  dog:
+    parent: animal
+  berry:
+banned: [photo]
+").unwrap();
+        let normalized = vocabulary.normalize(&tags(&["Sun-Set", "dusk", "Dogs", "berries", "photo", "car"]));
+        assert_eq!(normalized.tags, vec!["sunset", "dog", "animal", "berry", "car"]);
+        assert_eq!(normalized.unknown, vec!["car"]);
+    }
+    
+    #[test]
+    fn drops_unknown_tags_when_asked() {
+        let vocabulary = vocabulary("unknown: drop\ntags:\n  beach:\n").unwrap();
+        let normalized = vocabulary.normalize(&tags(&["car", "beach"]));
+        assert_eq!(normalized.tags, vec!["beach"]);
+        assert_eq!(normalized.unknown, vec!["car"]);
+    }
+    
+    #[test]
+    fn parent_cycles_end() {
+        let vocabulary = vocabulary("tags:\n  a:\n    parent: b\n  b:\n    parent: a\n").unwrap();
+        assert_eq!(vocabulary.normalize(&tags(&["a"])).tags, vec!["a", "b"]);
+    }
+    
+    #[test]
+    fn review_counts_photos_not_sightings() {
+        let root = std::env::temp_dir().join(format!("zim-photo-review-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let review = TagReview::open(&root).unwrap();
+        
+        review.record(&root.join("a.jpg"), &tags(&["kayak", "dock"])).unwrap();
+        review.record(&root.join("a.jpg"), &tags(&["kayak"])).unwrap();
+        review.record(&root.join("b.jpg"), &tags(&["kayak"])).unwrap();
+        assert_eq!(review.list().unwrap(), vec![("kayak".to_string(), 2, "a.jpg".to_string())]);
+        
+        let vocabulary = vocabulary("tags:\n  kayak:\n").unwrap();
+        assert_eq!(review.prune(&vocabulary).unwrap(), 1);
+        assert!(review.list().unwrap().is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[test]
+    fn clashing_synonyms_are_rejected_in_any_order() {
+        assert!(vocabulary("tags:\n  sea:\n    synonyms: [ocean]\n  ocean:\n").is_err());
+        assert!(vocabulary("tags:\n  ocean:\n  sea:\n    synonyms: [ocean]\n").is_err());
+        assert!(vocabulary("tags:\n  sea:\n    synonyms: [water]\n  lake:\n    synonyms: [water]\n").is_err());
+    }
+}
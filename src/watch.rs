@@ -0,0 +1,254 @@
+use anyhow::{Result, Context};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+use crate::ai_queue::{AiQueue, install_interrupt_handler, interrupted};
+use crate::groups::group_of;
+use crate::ignore_rules::IgnoreRules;
+use crate::manifest::Manifest;
+use crate::photo_walker::{PhotoFile, PhotoWalker};
+use crate::sidecar_writer::{AiOptions, SidecarWriter, UpdateContext, default_jobs, run_queued_ai, update_frontmatter, update_photos};
+
+/// How often pending files are looked at while no events arrive
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A photo that was created or written to, waiting for the writes to stop
+struct PendingFile {
+    last_event: Instant,
+    /// Size and modification time when last looked at
+    stamp: Option<(u64, SystemTime)>,
+}
+
+/// Watch a folder and write sidecars for photos as they arrive.
+///
+/// New and modified photos are processed once no events arrived for `settle` and
+/// their size stopped changing, in batches through the same pipeline as `update`.
+/// Photos renamed or moved within the folder take their sidecar with them. When
+/// grouping, a new file brings the rest of its group along, so a JPEG or an edit
+/// arriving after its RAW file is added to the RAW file's sidecar.
+pub fn watch_directory(
+    photo_dir: &str,
+    catalog_path: &str,
+    settle: Duration,
+    show_progress: bool,
+    jobs: Option<usize>,
+    group: bool,
+    ai: Option<&AiOptions>,
+) -> Result<()> {
+    let root = fs::canonicalize(photo_dir)
+        .with_context(|| format!("Failed to open {}", photo_dir))?;
+    
+    // A drop folder usually has no catalog to go with it
+    let catalog_path = Path::new(catalog_path).exists().then_some(catalog_path);
+    if catalog_path.is_some() {
+        println!("✅ Using Lightroom catalog");
+    } else {
+        println!("ℹ️  Lightroom catalog not found, using EXIF and AI data only");
+    }
+    let queue = if let Some(ai) = ai {
+        println!("🤖 AI vision analysis enabled with {}", ai.model_name());
+        Some(AiQueue::open(&root)?)
+    } else {
+        None
+    };
+    install_interrupt_handler()?;
+    
+    let manifest = Manifest::open(&root)?;
+    let context = UpdateContext {
+        catalog_path,
+        manifest: &manifest,
+        queue: queue.as_ref(),
+        skip_existing: true,
+        show_progress,
+        jobs: default_jobs(jobs),
+        ai,
+        group,
+    };
+    
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .context("Failed to start watching for file changes")?;
+    watcher.watch(&root, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", root.display()))?;
+    
+    // Photos that arrived while nobody was watching
+    println!("🔎 Catching up on photos added since the last run");
+    let walker = PhotoWalker::new(&root, false);
+    let mut writer = SidecarWriter::new();
+    let (walk_counts, _) = update_photos(&context, &mut writer, |found| walker.for_each_photo(found))?;
+    walk_counts?;
+    run_queued_ai(&context, &mut writer)?;
+    print_batch_summary(&writer);
+    
+    println!("\n👀 Watching {} for new photos (Ctrl-C to stop)", root.display());
+    let mut rules = IgnoreRules::for_root(&root)?;
+    let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+    while !interrupted() {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => handle_event(event, &mut pending, &manifest),
+            Ok(Err(e)) => println!("  ⚠️  Watch error: {}", e),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        
+        let ready = settled_files(&mut pending, settle);
+        // By folder, then by name, the way the pipeline wants them when grouping
+        let mut paths = BTreeSet::new();
+        for path in ready {
+            match rules.file_excluded_by(&path) {
+                Ok(None) => {}
+                Ok(Some(_)) => continue,
+                Err(e) => {
+                    println!("  ⚠️  Failed to check the ignore rules for {}: {:#}", path.display(), e);
+                    continue;
+                }
+            }
+            let path_display = path.display().to_string();
+            let Some(photo) = PhotoFile::new(path) else {
+                continue;
+            };
+            let members = if group {
+                let photos = match group_of(photo, &root) {
+                    Ok(photos) => photos,
+                    Err(e) => {
+                        println!("  ⚠️  Failed to group {}: {:#}", path_display, e);
+                        continue;
+                    }
+                };
+                std::iter::once(photos.master).chain(photos.companions).collect()
+            } else {
+                vec![photo]
+            };
+            for photo in members {
+                paths.insert((photo.path.parent().map(Path::to_path_buf), photo.path));
+            }
+        }
+        let photos: Vec<PhotoFile> = paths.into_iter()
+            .filter_map(|(_, path)| PhotoFile::new(path))
+            .collect();
+        if photos.is_empty() {
+            continue;
+        }
+        
+        // A failed batch is reported and the files are picked up again on their next change
+        let mut writer = SidecarWriter::new();
+        let updated = update_photos(&context, &mut writer, |found| {
+            for photo in photos {
+                if !found(photo) {
+                    break;
+                }
+            }
+        });
+        if let Err(e) = updated {
+            println!("  ❌ Failed to update the new photos: {:#}", e);
+            continue;
+        }
+        if let Err(e) = run_queued_ai(&context, &mut writer) {
+            println!("  ❌ Failed to run the queued AI analyses: {:#}", e);
+        }
+        print_batch_summary(&writer);
+    }
+    
+    println!("\n👋 Stopped watching");
+    Ok(())
+}
+
+/// Note new and written files, and move sidecars along with renamed photos
+fn handle_event(event: Event, pending: &mut HashMap<PathBuf, PendingFile>, manifest: &Manifest) {
+    match event.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let [from, to] = event.paths.as_slice() else {
+                return;
+            };
+            if let Some(file) = pending.remove(from) {
+                pending.insert(to.clone(), file);
+            }
+            if let Err(e) = follow_rename(from, to, manifest) {
+                println!("  ⚠️  Failed to move the sidecar of {}: {}", from.display(), e);
+            }
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            for path in &event.paths {
+                pending.remove(path);
+            }
+        }
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            for path in event.paths {
+                if path.is_file() && PhotoFile::new(path.clone()).is_some() {
+                    pending.insert(path, PendingFile { last_event: Instant::now(), stamp: None });
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Move a renamed photo's sidecar next to it under its new name. A renamed folder
+/// takes its sidecars along by itself; only the manifest needs to follow.
+fn follow_rename(from: &Path, to: &Path, manifest: &Manifest) -> Result<()> {
+    if to.is_dir() {
+        return manifest.moved(from, to);
+    }
+    let (Some(old), Some(new)) = (PhotoFile::new(from.to_path_buf()), PhotoFile::new(to.to_path_buf())) else {
+        return Ok(());
+    };
+    if !old.sidecar_path.exists() || new.sidecar_path.exists() {
+        return Ok(());
+    }
+    
+    fs::rename(&old.sidecar_path, &new.sidecar_path)?;
+    manifest.moved(from, to)?;
+    if old.filename != new.filename {
+        update_frontmatter(&new.sidecar_path, manifest, |frontmatter| {
+            frontmatter.insert("filename".into(), new.filename.clone().into());
+            true
+        })?;
+    }
+    println!("  📦 {} → {} (sidecar moved along)", old.filename, new.path.display());
+    Ok(())
+}
+
+/// Files that had no events for `settle` and stopped growing since last looked at
+fn settled_files(pending: &mut HashMap<PathBuf, PendingFile>, settle: Duration) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+    pending.retain(|path, file| {
+        if file.last_event.elapsed() < settle {
+            return true;
+        }
+        let Ok(metadata) = fs::metadata(path) else {
+            return false;
+        };
+        let stamp = (metadata.len(), metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+        if file.stamp == Some(stamp) {
+            ready.push(path.clone());
+            return false;
+        }
+        // Still being copied (or not looked at yet): check again after another wait
+        file.stamp = Some(stamp);
+        file.last_event = Instant::now();
+        true
+    });
+    ready
+}
+
+/// A short summary of a batch, instead of the full report of `update`; nothing
+/// when every photo turned out to be unchanged
+fn print_batch_summary(writer: &SidecarWriter) {
+    if writer.files_written + writer.ai_failed + writer.hand_edited + writer.errors.len() == 0 {
+        return;
+    }
+    println!("\n📥 {} sidecars written, {} unchanged", writer.files_written, writer.files_skipped);
+    if writer.ai_analyzed + writer.ai_failed > 0 {
+        println!("  🤖 AI analyzed: {}, failed: {}", writer.ai_analyzed, writer.ai_failed);
+    }
+    if writer.hand_edited > 0 {
+        println!("  ✋ Sidecars edited by hand, left alone: {}", writer.hand_edited);
+    }
+    for error in &writer.errors {
+        println!("  ❌ {}", error);
+    }
+}